@@ -0,0 +1,207 @@
+// Axis-aligned bounding boxes, for culling and acceleration structures.
+
+use crate::mat4::Mat4;
+use crate::ray::Ray;
+use crate::vec3::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub minimum: Point3,
+    pub maximum: Point3,
+}
+
+// The far slab distance is pushed out by this much so that rounding can't
+// make a box miss a ray that hits what is inside it (pbrt's 1 + 2 gamma(3)).
+const ROUNDING: f64 = 4.0 * f64::EPSILON;
+
+impl Aabb {
+    // The box with `a` and `b` at opposite corners, in any order.
+    pub fn of(a: Point3, b: Point3) -> Aabb {
+        Aabb {
+            minimum: Point3::of(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+            maximum: Point3::of(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
+        }
+    }
+
+    // Contains nothing, and grows to exactly what is added to it.
+    pub fn empty() -> Aabb {
+        Aabb {
+            minimum: Point3::of(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            maximum: Point3::of(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points<'a, I: IntoIterator<Item = &'a Point3>>(points: I) -> Aabb {
+        points
+            .into_iter()
+            .fold(Aabb::empty(), |b, p| surrounding_box(&b, &Aabb::of(*p, *p)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|a| self.minimum[a] > self.maximum[a])
+    }
+
+    // Grown by `margin` on every side.
+    pub fn padded(&self, margin: f64) -> Aabb {
+        let m = Vec3::of(margin, margin, margin);
+        Aabb {
+            minimum: self.minimum - m,
+            maximum: self.maximum + m,
+        }
+    }
+
+    // The overlap of the two boxes, or `None` if they don't meet.
+    pub fn intersection(&self, other: &Aabb) -> Option<Aabb> {
+        let b = Aabb {
+            minimum: Point3::of(
+                self.minimum.x().max(other.minimum.x()),
+                self.minimum.y().max(other.minimum.y()),
+                self.minimum.z().max(other.minimum.z()),
+            ),
+            maximum: Point3::of(
+                self.maximum.x().min(other.maximum.x()),
+                self.maximum.y().min(other.maximum.y()),
+                self.maximum.z().min(other.maximum.z()),
+            ),
+        };
+        if b.is_empty() {
+            None
+        } else {
+            Some(b)
+        }
+    }
+
+    // The box around this one's eight corners after an affine transform.
+    pub fn transformed(&self, m: &Mat4) -> Aabb {
+        let mut out = Aabb::empty();
+        for i in 0..8 {
+            let pick = |a: usize| {
+                if i & (1 << a) == 0 {
+                    self.minimum[a]
+                } else {
+                    self.maximum[a]
+                }
+            };
+            let p = m.transform_point(&Point3::of(pick(0), pick(1), pick(2)));
+            out = surrounding_box(&out, &Aabb::of(p, p));
+        }
+        out
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        (self.minimum + self.maximum) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.maximum - self.minimum;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    // Slab test: whether the ray passes through the box somewhere in
+    // [t_min, t_max].
+    pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        let inv_d = Vec3::of(
+            1.0 / r.direction.x(),
+            1.0 / r.direction.y(),
+            1.0 / r.direction.z(),
+        );
+        self.hit_with_inverse(&r.origin, &inv_d, t_min, t_max)
+    }
+
+    // `hit` with the reciprocal of the ray direction worked out once by the
+    // caller, for testing one ray against many boxes.
+    pub fn hit_with_inverse(&self, origin: &Point3, inv_d: &Vec3, t_min: f64, t_max: f64) -> bool {
+        self.clip(origin, inv_d, t_min, t_max).is_some()
+    }
+
+    // The part of [t_min, t_max] where the ray is inside the box, if any. A
+    // zero direction component gives infinite slab distances, or NaN for an
+    // origin on that face, which `max` and `min` pass over.
+    pub fn clip(
+        &self,
+        origin: &Point3,
+        inv_d: &Vec3,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(f64, f64)> {
+        let (mut t_min, mut t_max) = (t_min, t_max);
+        for a in 0..3 {
+            let mut t0 = (self.minimum[a] - origin[a]) * inv_d[a];
+            let mut t1 = (self.maximum[a] - origin[a]) * inv_d[a];
+            if inv_d[a] < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1 + t1.abs() * ROUNDING);
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+pub fn surrounding_box(box0: &Aabb, box1: &Aabb) -> Aabb {
+    Aabb {
+        minimum: Point3::of(
+            box0.minimum.x().min(box1.minimum.x()),
+            box0.minimum.y().min(box1.minimum.y()),
+            box0.minimum.z().min(box1.minimum.z()),
+        ),
+        maximum: Point3::of(
+            box0.maximum.x().max(box1.maximum.x()),
+            box0.maximum.y().max(box1.maximum.y()),
+            box0.maximum.z().max(box1.maximum.z()),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::scenes;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn hits_lie_inside_bounding_boxes() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for entry in scenes::small_scenes() {
+            let scene = entry.build(42);
+            for object in &scene.world {
+                let mut b = Aabb::empty();
+                if !object.bounding_box(0.0, 1.0, &mut b) {
+                    continue;
+                }
+                let extent = b.maximum - b.minimum;
+                let slack = 1e-9 * (1.0 + b.minimum.length().max(b.maximum.length()));
+                let outer = b.padded(slack);
+                // From well outside the box towards points inside it.
+                for _ in 0..16 {
+                    let target = b.minimum
+                        + Vec3::of(
+                            extent.x() * rng.gen_range(0.0, 1.0),
+                            extent.y() * rng.gen_range(0.0, 1.0),
+                            extent.z() * rng.gen_range(0.0, 1.0),
+                        );
+                    let dir = Vec3::of(
+                        rng.gen_range(-1.0, 1.0),
+                        rng.gen_range(-1.0, 1.0),
+                        rng.gen_range(-1.0, 1.0),
+                    );
+                    let origin = target - dir * (2.0 * extent.length() + 1.0);
+                    let r = Ray::of(origin, target - origin);
+                    let mut rec = HitRecord::default();
+                    if !object.hit(&r, 0.001, f64::INFINITY, &mut rec) {
+                        continue;
+                    }
+                    assert!(b.hit(&r, 0.001, f64::INFINITY), "{}", entry.name);
+                    let inside = (0..3)
+                        .all(|a| rec.p[a] >= outer.minimum[a] && rec.p[a] <= outer.maximum[a]);
+                    assert!(inside, "{}: {:?} outside {:?}", entry.name, rec.p, b);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,291 @@
+// Acceleration structures share one interface, so they can be swapped for
+// each other and compared. Each finds the same hit as testing every object
+// in order, as `Vec` does, including which object wins when two are hit at
+// exactly the same `t`: the later one.
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::hittable::{HitRecord, Hittable};
+use crate::mesh::TriangleMesh;
+use crate::object::Object;
+use crate::ray::Ray;
+use crate::scene_graph::Instance;
+use crate::transform::Transformed;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub trait Accelerator<T: Hittable>: Hittable {
+    fn build(objects: Vec<T>) -> Self
+    where
+        Self: Sized;
+
+    fn objects(&self) -> &[T];
+
+    // `hit`, adding the number of objects tested against the ray to `tests`.
+    fn hit_counting(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        tests: &mut u64,
+    ) -> bool;
+}
+
+// The linear scan the others are measured against.
+impl<T: Hittable> Accelerator<T> for Vec<T> {
+    fn build(objects: Vec<T>) -> Vec<T> {
+        objects
+    }
+
+    fn objects(&self) -> &[T] {
+        self
+    }
+
+    fn hit_counting(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        tests: &mut u64,
+    ) -> bool {
+        *tests += self.len() as u64;
+        self.hit(r, t_min, t_max, rec)
+    }
+}
+
+// An accelerator over the world's objects, with groups and the faces of
+// meshes at the top level split out so the structure can sort them too.
+//
+// Transformed objects and instances stay whole, making this the top level
+// of two: geometry under them that is worth it gets a BVH of its own, in
+// its own space, which rays enter through the transform. Each shared
+// geometry gets one, however many instances place it, so memory grows with
+// the unique geometry rather than the instances.
+pub fn from_world<A: Accelerator<Object>>(world: &[Object]) -> A {
+    let mut objects = Vec::with_capacity(world.len());
+    flatten(world, &mut objects, &mut HashMap::new());
+    A::build(objects)
+}
+
+// Bottom level hierarchies already built, by the geometry they replace.
+type Shared = HashMap<*const Object, Arc<Object>>;
+
+fn flatten(world: &[Object], out: &mut Vec<Object>, shared: &mut Shared) {
+    for object in world {
+        match object {
+            Object::List(list) => flatten(list, out, shared),
+            Object::Mesh(mesh) => out.extend(
+                TriangleMesh::triangles(mesh)
+                    .into_iter()
+                    .map(Object::MeshTriangle),
+            ),
+            _ => out.push(placed(object, shared)),
+        }
+    }
+}
+
+// `object` with any geometry it places swapped for a hierarchy over it.
+fn placed(object: &Object, shared: &mut Shared) -> Object {
+    match object {
+        Object::Transformed(x) => Transformed::with_inverse(
+            bottom_level(&x.object, shared),
+            *x.object_to_world(),
+            *x.world_to_object(),
+        )
+        .into(),
+        Object::Instance(x) => {
+            let instance = Instance {
+                geometry: bottom_level(&x.object.geometry, shared),
+                material: x.object.material,
+            };
+            Transformed::with_inverse(instance, *x.object_to_world(), *x.world_to_object()).into()
+        }
+        _ => object.clone(),
+    }
+}
+
+fn bottom_level(geometry: &Arc<Object>, shared: &mut Shared) -> Arc<Object> {
+    let key = Arc::as_ptr(geometry);
+    if let Some(built) = shared.get(&key) {
+        return Arc::clone(built);
+    }
+    let built = match &**geometry {
+        Object::List(_) | Object::Mesh(_) => {
+            let mut objects = Vec::new();
+            flatten(std::slice::from_ref(&**geometry), &mut objects, shared);
+            Arc::new(Bvh::new(objects).into())
+        }
+        Object::Transformed(_) | Object::Instance(_) => Arc::new(placed(geometry, shared)),
+        _ => Arc::clone(geometry),
+    };
+    shared.insert(key, Arc::clone(&built));
+    built
+}
+
+// Every object's bounding box, or `None` for an unbounded one. Boxes that
+// aren't finite, as from a NaN radius, count as unbounded too, so that they
+// can't spoil the boxes of their neighbours.
+pub(crate) fn object_boxes<T: Hittable + Sync>(objects: &[T]) -> Vec<Option<Aabb>> {
+    objects
+        .par_iter()
+        .map(|object| {
+            let mut bounds = Aabb::empty();
+            if !object.bounding_box(0.0, 1.0, &mut bounds) {
+                return None;
+            }
+            let finite =
+                (0..3).all(|a| bounds.minimum[a].is_finite() && bounds.maximum[a].is_finite());
+            if finite {
+                Some(bounds)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// Boxes are grown by this much of their distance from the origin before
+// being sorted into cells or nodes, so that rounding can't leave a hit
+// outside every one that lists its object.
+const SLACK: f64 = 1e-9;
+
+pub(crate) fn loosened(b: &Aabb) -> Aabb {
+    let reach = (0..3)
+        .map(|a| b.minimum[a].abs().max(b.maximum[a].abs()))
+        .fold(1.0, f64::max);
+    b.padded(SLACK * reach)
+}
+
+// Tries object `i` against the nearest hit so far, as its `t` and index,
+// keeping the later object on a tie (`None` sorts before any index).
+pub(crate) fn try_object<T: Hittable>(
+    objects: &[T],
+    i: u32,
+    r: &Ray,
+    t_min: f64,
+    closest: &mut (f64, Option<u32>),
+    rec: &mut HitRecord,
+    tests: &mut u64,
+) {
+    *tests += 1;
+    let mut temp_rec = HitRecord::default();
+    if objects[i as usize].hit(r, t_min, closest.0, &mut temp_rec)
+        && (temp_rec.t < closest.0 || closest.1 < Some(i))
+    {
+        *closest = (temp_rec.t, Some(i));
+        *rec = temp_rec;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Grid;
+    use crate::kdtree::KdTree;
+    use crate::scenes;
+    use crate::sphere::Sphere;
+    use crate::vec3::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn matches_the_linear_scan<A: Accelerator<Object>>() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for entry in scenes::small_scenes() {
+            let scene = entry.build(42);
+            let accel: A = from_world(&scene.world);
+            let view = scene.view;
+            let spread = (view.lookat - view.lookfrom).length();
+            let (mut scanned, mut tested) = (0, 0);
+            for _ in 0..300 {
+                let jitter = Vec3::of(
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                );
+                let aim = Vec3::of(
+                    rng.gen_range(-0.5, 0.5),
+                    rng.gen_range(-0.5, 0.5),
+                    rng.gen_range(-0.5, 0.5),
+                );
+                let origin = view.lookfrom + jitter * (0.2 * spread);
+                let r = Ray::of(origin, view.lookat + aim * spread - origin);
+                let (mut expected, mut found) = (HitRecord::default(), HitRecord::default());
+                let hit = accel.objects().to_vec().hit_counting(
+                    &r,
+                    0.001,
+                    f64::INFINITY,
+                    &mut expected,
+                    &mut scanned,
+                );
+                assert_eq!(
+                    accel.hit_counting(&r, 0.001, f64::INFINITY, &mut found, &mut tested),
+                    hit,
+                    "{}",
+                    entry.name
+                );
+                if hit {
+                    assert_eq!(
+                        (found.t, found.p, found.normal, found.mat_ptr),
+                        (expected.t, expected.p, expected.normal, expected.mat_ptr),
+                        "{}",
+                        entry.name
+                    );
+                    assert_eq!(
+                        (found.u, found.v),
+                        (expected.u, expected.v),
+                        "{}",
+                        entry.name
+                    );
+                }
+            }
+            assert!(tested <= scanned, "{}", entry.name);
+        }
+    }
+
+    // A NaN radius makes a NaN box, which must not stop the rest of the
+    // world from being built or hit.
+    fn survives_nan_bounds<A: Accelerator<Object>>() {
+        let world: Vec<Object> = vec![
+            Sphere::of(Point3::of(0.0, 0.0, -1.0), f64::NAN, 0).into(),
+            Sphere::of(Point3::of(2.0, 0.0, -1.0), 0.5, 0).into(),
+            Sphere::of(Point3::of(-2.0, 0.0, -1.0), 0.5, 0).into(),
+        ];
+        let accel: A = from_world(&world);
+        let across = [-2.0, 0.0, 2.0, 5.0]
+            .iter()
+            .map(|x| Ray::of(Point3::of(*x, 0.0, 1.0), Vec3::of(0.0, 0.0, -1.0)));
+        // As scattered from a hit on the NaN sphere.
+        let nan = Vec3::of(f64::NAN, f64::NAN, f64::NAN);
+        for r in across.chain(std::iter::once(Ray::of(nan, nan))) {
+            let (mut expected, mut found) = (HitRecord::default(), HitRecord::default());
+            let hit = world.hit(&r, 0.001, f64::INFINITY, &mut expected);
+            assert_eq!(accel.hit(&r, 0.001, f64::INFINITY, &mut found), hit);
+            assert_eq!(found.t.to_bits(), expected.t.to_bits());
+        }
+    }
+
+    #[test]
+    fn non_finite_boxes_are_tested_unbounded() {
+        survives_nan_bounds::<Bvh<Object>>();
+        survives_nan_bounds::<Grid<Object>>();
+        survives_nan_bounds::<KdTree<Object>>();
+    }
+
+    #[test]
+    fn bvh_matches_the_linear_scan() {
+        matches_the_linear_scan::<Bvh<Object>>();
+    }
+
+    #[test]
+    fn grid_matches_the_linear_scan() {
+        matches_the_linear_scan::<Grid<Object>>();
+    }
+
+    #[test]
+    fn kd_tree_matches_the_linear_scan() {
+        matches_the_linear_scan::<KdTree<Object>>();
+    }
+}
@@ -0,0 +1,499 @@
+// Bounding volume hierarchy over the world. Built top down with the surface
+// area heuristic, evaluated over a few bins of object centroids on each
+// axis, and flattened depth first into one array: a node's first child
+// directly follows it, and only the second child's index is stored.
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::accel::{self, object_boxes, try_object, Accelerator};
+use crate::hittable::{HitRecord, Hittable};
+use crate::object::Object;
+use crate::ray::Ray;
+use crate::vec3::*;
+use rayon::prelude::*;
+
+// Centroid bins per axis when looking for a split.
+const BINS: usize = 16;
+// Nodes this small become leaves unless splitting looks cheaper.
+const MAX_LEAF: usize = 4;
+// Cost of a box test relative to an object test.
+const TRAVERSAL_COST: f64 = 0.125;
+// Deeper nodes are leaves, so traversal can use a fixed stack.
+const MAX_DEPTH: usize = 60;
+// Nodes with fewer objects than this are built on one thread.
+const PARALLEL_MIN: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Node {
+    bounds: Aabb,
+    // For a leaf, where its objects start in `order`; otherwise the index of
+    // the second child.
+    offset: u32,
+    // Objects in a leaf; zero for an interior node.
+    count: u32,
+    // Axis the children were split on, to visit the nearer one first.
+    axis: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bvh<T: Hittable> {
+    objects: Vec<T>,
+    nodes: Vec<Node>,
+    // Object indices, grouped by leaf.
+    order: Vec<u32>,
+    // Objects without a bounding box, tested against every ray.
+    unbounded: Vec<u32>,
+}
+
+// An object's box and centroid while building.
+#[derive(Clone, Copy)]
+struct Item {
+    bounds: Aabb,
+    centroid: Point3,
+    index: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Bin {
+    bounds: Aabb,
+    count: usize,
+}
+
+impl<T: Hittable + Sync> Bvh<T> {
+    // Builds the hierarchy, sharing the work out over rayon's threads: big
+    // nodes bin their objects in parallel chunks, and build their two
+    // subtrees at the same time.
+    pub fn new(objects: Vec<T>) -> Bvh<T> {
+        let boxes = object_boxes(&objects);
+        let mut items = Vec::with_capacity(objects.len());
+        let mut unbounded = Vec::new();
+        for (i, bounds) in boxes.into_iter().enumerate() {
+            match bounds {
+                Some(bounds) => items.push(Item {
+                    bounds,
+                    centroid: bounds.centroid(),
+                    index: i as u32,
+                }),
+                None => unbounded.push(i as u32),
+            }
+        }
+        let mut nodes = Vec::new();
+        if !items.is_empty() {
+            build(&mut items, 0, 0, &mut nodes);
+        }
+        Bvh {
+            objects,
+            nodes,
+            // Leaves own consecutive runs of the partitioned items.
+            order: items.iter().map(|item| item.index).collect(),
+            unbounded,
+        }
+    }
+
+    // Brings the boxes up to date after objects have moved, keeping the
+    // tree as it is. Much cheaper than a rebuild, but the tree only stays
+    // good while objects move coherently, as in an animated mesh. Objects
+    // must stay bounded or unbounded as they were built.
+    pub fn refit(&mut self) {
+        let boxes: Vec<Aabb> = self
+            .objects
+            .par_iter()
+            .map(|object| {
+                let mut bounds = Aabb::empty();
+                object.bounding_box(0.0, 1.0, &mut bounds);
+                bounds
+            })
+            .collect();
+        // Children come after their parent, so going backwards finishes
+        // them first.
+        for i in (0..self.nodes.len()).rev() {
+            let node = self.nodes[i];
+            self.nodes[i].bounds = if node.count > 0 {
+                let start = node.offset as usize;
+                self.order[start..start + node.count as usize]
+                    .iter()
+                    .fold(Aabb::empty(), |b, &k| {
+                        surrounding_box(&b, &boxes[k as usize])
+                    })
+            } else {
+                surrounding_box(
+                    &self.nodes[i + 1].bounds,
+                    &self.nodes[node.offset as usize].bounds,
+                )
+            };
+        }
+    }
+}
+
+impl<T: Hittable> Bvh<T> {
+    pub fn objects(&self) -> &[T] {
+        &self.objects
+    }
+
+    // For moving objects in place; `refit` afterwards.
+    pub fn objects_mut(&mut self) -> &mut [T] {
+        &mut self.objects
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+impl Bvh<Object> {
+    // See `accel::from_world`.
+    pub fn from_world(world: &[Object]) -> Bvh<Object> {
+        accel::from_world(world)
+    }
+}
+
+// Appends the nodes for `items`, which start at `start` in the whole item
+// list, to `nodes`.
+fn build(items: &mut [Item], start: usize, depth: usize, nodes: &mut Vec<Node>) {
+    let parallel = items.len() >= PARALLEL_MIN;
+    let (bounds, centroids) = if parallel {
+        items.par_chunks(PARALLEL_MIN).map(item_bounds).reduce(
+            || (Aabb::empty(), Aabb::empty()),
+            |a, b| (surrounding_box(&a.0, &b.0), surrounding_box(&a.1, &b.1)),
+        )
+    } else {
+        item_bounds(items)
+    };
+    let index = nodes.len();
+    nodes.push(Node {
+        bounds,
+        offset: start as u32,
+        count: items.len() as u32,
+        axis: 0,
+    });
+
+    let split = if items.len() > 1 && depth < MAX_DEPTH {
+        best_split(items, &bounds, &centroids)
+    } else {
+        None
+    };
+    let (axis, bin) = match split {
+        Some((axis, bin, cost)) if items.len() > MAX_LEAF || cost < items.len() as f64 => {
+            (axis, bin)
+        }
+        _ => return,
+    };
+
+    let mut mid = 0;
+    for k in 0..items.len() {
+        if bin_of(&items[k].centroid, &centroids, axis) <= bin {
+            items.swap(k, mid);
+            mid += 1;
+        }
+    }
+    nodes[index].axis = axis as u8;
+    nodes[index].count = 0;
+
+    let (left, right) = items.split_at_mut(mid);
+    let second = if parallel {
+        // The second subtree goes into its own list, which is moved in
+        // behind the first with its links shifted to match.
+        let (_, mut tail) = rayon::join(
+            || build(left, start, depth + 1, nodes),
+            || {
+                let mut tail = Vec::new();
+                build(right, start + mid, depth + 1, &mut tail);
+                tail
+            },
+        );
+        let base = nodes.len();
+        for node in &mut tail {
+            if node.count == 0 {
+                node.offset += base as u32;
+            }
+        }
+        nodes.append(&mut tail);
+        base
+    } else {
+        build(left, start, depth + 1, nodes);
+        let base = nodes.len();
+        build(right, start + mid, depth + 1, nodes);
+        base
+    };
+    nodes[index].offset = second as u32;
+}
+
+// The box around the items and the box around their centroids.
+fn item_bounds(items: &[Item]) -> (Aabb, Aabb) {
+    items
+        .iter()
+        .fold((Aabb::empty(), Aabb::empty()), |(b, c), item| {
+            (
+                surrounding_box(&b, &item.bounds),
+                surrounding_box(&c, &Aabb::of(item.centroid, item.centroid)),
+            )
+        })
+}
+
+fn bin_of(centroid: &Point3, centroids: &Aabb, axis: usize) -> usize {
+    let (lo, hi) = (centroids.minimum[axis], centroids.maximum[axis]);
+    let b = ((centroid[axis] - lo) / (hi - lo) * BINS as f64) as usize;
+    b.min(BINS - 1)
+}
+
+fn empty_bins() -> [[Bin; BINS]; 3] {
+    let bin = Bin {
+        bounds: Aabb::empty(),
+        count: 0,
+    };
+    [[bin; BINS]; 3]
+}
+
+// The items binned by centroid along each axis. Axes where the centroids
+// don't spread out are left empty.
+fn fill_bins(items: &[Item], centroids: &Aabb) -> [[Bin; BINS]; 3] {
+    let mut bins = empty_bins();
+    for (axis, bins) in bins.iter_mut().enumerate() {
+        if centroids.maximum[axis] <= centroids.minimum[axis] {
+            continue;
+        }
+        for item in items {
+            let bin = &mut bins[bin_of(&item.centroid, centroids, axis)];
+            bin.bounds = surrounding_box(&bin.bounds, &item.bounds);
+            bin.count += 1;
+        }
+    }
+    bins
+}
+
+fn merge_bins(mut a: [[Bin; BINS]; 3], b: [[Bin; BINS]; 3]) -> [[Bin; BINS]; 3] {
+    for (a, b) in a.iter_mut().flatten().zip(b.iter().flatten()) {
+        a.bounds = surrounding_box(&a.bounds, &b.bounds);
+        a.count += b.count;
+    }
+    a
+}
+
+// The cheapest split by the surface area heuristic, as the axis, the last
+// bin on the low side and the cost relative to testing every object. None
+// if the centroids all coincide.
+fn best_split(items: &[Item], bounds: &Aabb, centroids: &Aabb) -> Option<(usize, usize, f64)> {
+    let all_bins = if items.len() >= PARALLEL_MIN {
+        items
+            .par_chunks(PARALLEL_MIN)
+            .map(|chunk| fill_bins(chunk, centroids))
+            .reduce(empty_bins, merge_bins)
+    } else {
+        fill_bins(items, centroids)
+    };
+    let area = bounds.surface_area();
+    let mut best: Option<(usize, usize, f64)> = None;
+    for (axis, bins) in all_bins.iter().enumerate() {
+        // Sweep from the right to find the cost of everything above each
+        // boundary, then from the left to finish each sum.
+        let mut right_cost = [0.0; BINS];
+        let (mut b, mut n) = (Aabb::empty(), 0);
+        for k in (1..BINS).rev() {
+            b = surrounding_box(&b, &bins[k].bounds);
+            n += bins[k].count;
+            right_cost[k - 1] = if n > 0 {
+                n as f64 * b.surface_area()
+            } else {
+                0.0
+            };
+        }
+        let (mut b, mut n) = (Aabb::empty(), 0);
+        for k in 0..BINS - 1 {
+            b = surrounding_box(&b, &bins[k].bounds);
+            n += bins[k].count;
+            if n == 0 || n == items.len() {
+                continue;
+            }
+            let cost = if area > 0.0 {
+                TRAVERSAL_COST + (n as f64 * b.surface_area() + right_cost[k]) / area
+            } else {
+                TRAVERSAL_COST + items.len() as f64
+            };
+            if cost < best.map_or(f64::INFINITY, |(_, _, c)| c) {
+                best = Some((axis, k, cost));
+            }
+        }
+    }
+    best
+}
+
+impl<T: Hittable + Sync> Accelerator<T> for Bvh<T> {
+    fn build(objects: Vec<T>) -> Bvh<T> {
+        Bvh::new(objects)
+    }
+
+    fn objects(&self) -> &[T] {
+        &self.objects
+    }
+
+    fn hit_counting(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        tests: &mut u64,
+    ) -> bool {
+        let objects = &self.objects;
+        let mut closest = (t_max, None);
+        for &i in &self.unbounded {
+            try_object(objects, i, r, t_min, &mut closest, rec, tests);
+        }
+        if self.nodes.is_empty() {
+            return closest.1.is_some();
+        }
+
+        let inv_d = Vec3::of(
+            1.0 / r.direction.x(),
+            1.0 / r.direction.y(),
+            1.0 / r.direction.z(),
+        );
+        let mut stack = [0u32; MAX_DEPTH + 2];
+        let mut top = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            // Boxes beyond the nearest hit so far are skipped.
+            if node
+                .bounds
+                .hit_with_inverse(&r.origin, &inv_d, t_min, closest.0)
+            {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for &i in &self.order[start..start + node.count as usize] {
+                        try_object(objects, i, r, t_min, &mut closest, rec, tests);
+                    }
+                } else {
+                    // Go into the child on the side the ray comes from and
+                    // come back for the other.
+                    let (first, second) = if inv_d[node.axis as usize] < 0.0 {
+                        (node.offset as usize, current + 1)
+                    } else {
+                        (current + 1, node.offset as usize)
+                    };
+                    stack[top] = second as u32;
+                    top += 1;
+                    current = first;
+                    continue;
+                }
+            }
+            if top == 0 {
+                break;
+            }
+            top -= 1;
+            current = stack[top] as usize;
+        }
+        closest.1.is_some()
+    }
+}
+
+impl<T: Hittable + Sync> Hittable for Bvh<T> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.hit_counting(r, t_min, t_max, rec, &mut 0)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        if !self.unbounded.is_empty() || self.nodes.is_empty() {
+            return false;
+        }
+        *output_box = self.nodes[0].bounds;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::TriangleMesh;
+    use crate::scenes;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::Arc;
+
+    #[test]
+    fn refits_a_deformed_mesh() {
+        // Big enough to build in parallel.
+        let mesh = Arc::new(TriangleMesh::icosphere(4, 0));
+        assert!(mesh.len() >= PARALLEL_MIN);
+        let mut bvh = Bvh::new(
+            TriangleMesh::triangles(&mesh)
+                .into_iter()
+                .map(Object::MeshTriangle)
+                .collect(),
+        );
+        let mut moved = (*mesh).clone();
+        for p in &mut moved.positions {
+            *p = Point3::of(
+                p.x() * (1.0 + 0.5 * (3.0 * p.y()).sin()),
+                p.y() * 2.0,
+                p.z() + 1.0,
+            );
+        }
+        let moved = Arc::new(moved);
+        for object in bvh.objects_mut() {
+            if let Object::MeshTriangle(t) = object {
+                t.mesh = Arc::clone(&moved);
+            }
+        }
+        bvh.refit();
+
+        let linear = bvh.objects().to_vec();
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..2000 {
+            let origin = Point3::of(
+                rng.gen_range(-4.0, 4.0),
+                rng.gen_range(-4.0, 4.0),
+                rng.gen_range(-4.0, 4.0),
+            );
+            let target = Point3::of(
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-2.0, 2.0),
+                rng.gen_range(0.0, 2.0),
+            );
+            let r = Ray::of(origin, target - origin);
+            let (mut expected, mut found) = (HitRecord::default(), HitRecord::default());
+            let hit = linear.hit(&r, 0.001, f64::INFINITY, &mut expected);
+            assert_eq!(bvh.hit(&r, 0.001, f64::INFINITY, &mut found), hit);
+            if hit {
+                assert_eq!((found.t, found.p), (expected.t, expected.p));
+            }
+        }
+    }
+
+    #[test]
+    fn instances_share_bottom_levels() {
+        let scene = scenes::find("forest").unwrap().build(42);
+        let bvh = Bvh::from_world(&scene.world);
+        let mut kinds: Vec<*const Object> = Vec::new();
+        for object in bvh.objects() {
+            if let Object::Instance(x) = object {
+                assert!(matches!(*x.object.geometry, Object::Bvh(_)));
+                let key = Arc::as_ptr(&x.object.geometry);
+                if !kinds.contains(&key) {
+                    kinds.push(key);
+                }
+            }
+        }
+        assert_eq!(kinds.len(), 3);
+
+        let view = scene.view;
+        // The linear scan goes through every triangle of every tree.
+        let mut rng = StdRng::seed_from_u64(9);
+        for _ in 0..4 {
+            let aim = Vec3::of(
+                rng.gen_range(-8.0, 8.0),
+                rng.gen_range(-1.0, 3.0),
+                rng.gen_range(-8.0, 8.0),
+            );
+            let r = Ray::of(view.lookfrom, view.lookat + aim - view.lookfrom);
+            let (mut expected, mut found) = (HitRecord::default(), HitRecord::default());
+            let hit = scene.world.hit(&r, 0.001, f64::INFINITY, &mut expected);
+            assert_eq!(bvh.hit(&r, 0.001, f64::INFINITY, &mut found), hit);
+            if hit {
+                assert_eq!(
+                    (found.t, found.p, found.normal, found.mat_ptr),
+                    (expected.t, expected.p, expected.normal, expected.mat_ptr)
+                );
+            }
+        }
+    }
+}
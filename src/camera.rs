@@ -0,0 +1,67 @@
+use crate::ray::Ray;
+use crate::vec3::*;
+use std::f64::consts::PI;
+
+pub fn degrees_to_radians(degrees: f64) -> f64 {
+    degrees * PI / 180.0
+}
+
+pub struct Camera {
+    pub origin: Point3,
+    pub lower_left_corner: Point3,
+    pub horizontal: Vec3,
+    pub vertical: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+    pub lens_radius: f64,
+}
+
+impl Camera {
+    pub fn new(
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        vfov: f64,
+        aspect_ratio: f64,
+        aperture: f64,
+        focus_dist: f64,
+    ) -> Camera {
+        let theta = degrees_to_radians(vfov);
+        let h = (theta / 2.0).tan();
+        let viewport_height = 2.0 * h;
+        let viewport_width = aspect_ratio * viewport_height;
+
+        let w = unit_vector(&(lookfrom - lookat));
+        let u = unit_vector(&cross(&vup, &w));
+        let v = cross(&w, &u);
+
+        let origin = lookfrom;
+        let horizontal = u * viewport_width * focus_dist;
+        let vertical = v * viewport_height * focus_dist;
+        let lower_left_corner = origin - (horizontal / 2.0) - (vertical / 2.0) - (w * focus_dist);
+
+        let lens_radius = aperture / 2.0;
+        Camera {
+            u,
+            v,
+            w,
+            lens_radius,
+            origin: lookfrom,
+            horizontal,
+            vertical,
+            lower_left_corner,
+        }
+    }
+}
+
+pub fn get_ray(cam: &Camera, s: f64, t: f64) -> Ray {
+    let rd = Vec3::random_in_unit_disk() * cam.lens_radius;
+    let offset = (cam.u * rd.x()) + (cam.v * rd.y());
+    Ray {
+        origin: cam.origin + offset,
+        direction: cam.lower_left_corner + (cam.horizontal * s) + (cam.vertical * t)
+            - cam.origin
+            - offset,
+    }
+}
@@ -0,0 +1,311 @@
+// Constructive solid geometry. `hit` only finds the nearest surface, so
+// solids also report every span of a ray that lies inside them, and a CSG
+// node combines its operands' spans with the boolean operation. The nearest
+// boundary of the result is the hit.
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::vec3::*;
+use std::sync::Arc;
+
+// A stretch of a ray inside a solid, between two crossings of its surface.
+// Unlike a hit, the records here carry the outward normal whichever side the
+// ray comes from. Either end can be infinite for unbounded solids, in which
+// case that record is otherwise empty.
+#[derive(Clone, Copy)]
+pub struct Span {
+    pub enter: HitRecord,
+    pub exit: HitRecord,
+}
+
+pub trait Solid: Hittable {
+    // Appends the spans along the whole line through `r`, in order and not
+    // overlapping. Trimming to a ray's range is up to the caller.
+    fn spans(&self, r: &Ray, out: &mut Vec<Span>);
+}
+
+impl<T: Solid + ?Sized> Solid for Arc<T> {
+    fn spans(&self, r: &Ray, out: &mut Vec<Span>) {
+        (**self).spans(r, out)
+    }
+}
+
+// Upper bound on the surface crossings `march_spans` will follow.
+const MAX_CROSSINGS: usize = 64;
+
+// End of a span that runs off to infinity.
+pub fn at_infinity(t: f64) -> HitRecord {
+    HitRecord {
+        t,
+        ..HitRecord::default()
+    }
+}
+
+// Spans of any closed object, found by stepping from one hit to the next
+// along the whole line and reading entries and exits off `front_face`.
+pub fn march_spans<H: Hittable + ?Sized>(object: &H, r: &Ray, out: &mut Vec<Span>) {
+    let mut t_min = f64::NEG_INFINITY;
+    let mut enter: Option<HitRecord> = None;
+    let mut rec = HitRecord::default();
+    for _ in 0..MAX_CROSSINGS {
+        if !object.hit(r, t_min, f64::INFINITY, &mut rec) {
+            break;
+        }
+        let mut crossing = rec;
+        if !crossing.front_face {
+            crossing.normal = -crossing.normal;
+        }
+        match (rec.front_face, enter) {
+            (true, None) => enter = Some(crossing),
+            (false, Some(start)) => {
+                out.push(Span {
+                    enter: start,
+                    exit: crossing,
+                });
+                enter = None;
+            }
+            // An exit with no entry means the entry was lost to rounding or
+            // the line starts inside; an entry while inside is the same
+            // surface seen twice. Keep what is consistent.
+            (false, None) => out.push(Span {
+                enter: at_infinity(f64::NEG_INFINITY),
+                exit: crossing,
+            }),
+            (true, Some(_)) => {}
+        }
+        // Step past this crossing without skipping the next surface.
+        t_min = rec.t + 1e-9 * rec.t.abs().max(1.0);
+    }
+    if let Some(start) = enter {
+        out.push(Span {
+            enter: start,
+            exit: at_infinity(f64::INFINITY),
+        });
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOp {
+    fn inside(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOp::Union => in_a || in_b,
+            CsgOp::Intersection => in_a && in_b,
+            CsgOp::Difference => in_a && !in_b,
+        }
+    }
+}
+
+// Boolean combination of two solids. Each point of the result's surface
+// takes its material and UVs from the operand it came from; surfaces that
+// `b` cuts out of `a` in a difference face into `b`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Csg<S: Solid> {
+    pub op: CsgOp,
+    pub a: S,
+    pub b: S,
+}
+
+impl<S: Solid> Csg<S> {
+    pub fn new(op: CsgOp, a: S, b: S) -> Csg<S> {
+        Csg { op, a, b }
+    }
+}
+
+// Sweeps the crossings of both operands in order, tracking which ones the
+// line is inside, and keeps those where the combined state changes.
+pub fn combine(op: CsgOp, a: &[Span], b: &[Span], out: &mut Vec<Span>) {
+    // (crossing, from b, entering)
+    let mut events: Vec<(HitRecord, bool, bool)> = Vec::with_capacity(2 * (a.len() + b.len()));
+    for (spans, from_b) in [(a, false), (b, true)] {
+        for span in spans {
+            events.push((span.enter, from_b, true));
+            events.push((span.exit, from_b, false));
+        }
+    }
+    events.sort_by(|x, y| x.0.t.total_cmp(&y.0.t));
+
+    let (mut in_a, mut in_b, mut inside) = (false, false, false);
+    let mut start = at_infinity(f64::NEG_INFINITY);
+    for (mut crossing, from_b, entering) in events {
+        if from_b {
+            in_b = entering;
+        } else {
+            in_a = entering;
+        }
+        let now = op.inside(in_a, in_b);
+        if now == inside {
+            continue;
+        }
+        if from_b && op == CsgOp::Difference {
+            crossing.normal = -crossing.normal;
+        }
+        if now {
+            start = crossing;
+        } else {
+            out.push(Span {
+                enter: start,
+                exit: crossing,
+            });
+        }
+        inside = now;
+    }
+}
+
+impl<S: Solid> Solid for Csg<S> {
+    fn spans(&self, r: &Ray, out: &mut Vec<Span>) {
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        self.a.spans(r, &mut a);
+        // Nothing of a difference or intersection lies outside `a`.
+        if a.is_empty() && self.op != CsgOp::Union {
+            return;
+        }
+        self.b.spans(r, &mut b);
+        combine(self.op, &a, &b, out);
+    }
+}
+
+impl<S: Solid> Hittable for Csg<S> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut spans = Vec::new();
+        self.spans(r, &mut spans);
+        let nearest = spans
+            .iter()
+            .flat_map(|s| [s.enter, s.exit])
+            .find(|c| c.t.is_finite() && c.t >= t_min && c.t <= t_max);
+        match nearest {
+            Some(crossing) => {
+                let outward_normal = crossing.normal;
+                let front_face = dot(&r.direction, &outward_normal) < 0.0;
+                *rec = HitRecord {
+                    normal: if front_face {
+                        outward_normal
+                    } else {
+                        -outward_normal
+                    },
+                    front_face,
+                    ..crossing
+                };
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        let (mut a, mut b) = (Aabb::empty(), Aabb::empty());
+        let a_bounded = self.a.bounding_box(time0, time1, &mut a);
+        let b_bounded = self.b.bounding_box(time0, time1, &mut b);
+        let bounds = match (self.op, a_bounded, b_bounded) {
+            (CsgOp::Union, true, true) => surrounding_box(&a, &b),
+            // Boxes that don't overlap leave nothing to hit; any box will do.
+            (CsgOp::Intersection, true, true) => a.intersection(&b).unwrap_or(a),
+            (CsgOp::Intersection, true, false) | (CsgOp::Difference, true, _) => a,
+            (CsgOp::Intersection, false, true) => b,
+            _ => return false,
+        };
+        *output_box = bounds;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Object;
+    use crate::sphere::Sphere;
+
+    fn crossing(t: f64, z: f64) -> HitRecord {
+        HitRecord {
+            t,
+            normal: Vec3::of(0.0, 0.0, z),
+            ..HitRecord::default()
+        }
+    }
+
+    // Along a ray down -z, entering through +z faces and leaving through -z.
+    fn span(enter: f64, exit: f64) -> Span {
+        Span {
+            enter: crossing(enter, 1.0),
+            exit: crossing(exit, -1.0),
+        }
+    }
+
+    fn ends(spans: &[Span]) -> Vec<(f64, f64, f64, f64)> {
+        spans
+            .iter()
+            .map(|s| (s.enter.t, s.enter.normal.z(), s.exit.t, s.exit.normal.z()))
+            .collect()
+    }
+
+    fn sphere(z: f64) -> Arc<Object> {
+        Arc::new(Sphere::of(Point3::of(0.0, 0.0, z), 1.0, 0).into())
+    }
+
+    fn down(z: f64) -> Ray {
+        Ray::of(Point3::of(0.0, 0.0, z), Vec3::of(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn spans_cover_the_whole_line_with_outward_normals() {
+        let mut spans = Vec::new();
+        march_spans(&*sphere(0.0), &down(5.0), &mut spans);
+        assert_eq!(ends(&spans), vec![(4.0, 1.0, 6.0, -1.0)]);
+
+        // Starting inside still finds the entry behind the origin.
+        let mut spans = Vec::new();
+        march_spans(&*sphere(0.0), &down(0.5), &mut spans);
+        assert_eq!(ends(&spans), vec![(-0.5, 1.0, 1.5, -1.0)]);
+    }
+
+    #[test]
+    fn combines_overlapping_spans() {
+        let (a, b) = ([span(1.0, 4.0)], [span(3.0, 6.0)]);
+        let combined = |op| {
+            let mut out = Vec::new();
+            combine(op, &a, &b, &mut out);
+            ends(&out)
+        };
+        assert_eq!(combined(CsgOp::Union), vec![(1.0, 1.0, 6.0, -1.0)]);
+        assert_eq!(combined(CsgOp::Intersection), vec![(3.0, 1.0, 4.0, -1.0)]);
+        // Where `b` cuts `a` the face points back into `b`.
+        assert_eq!(combined(CsgOp::Difference), vec![(1.0, 1.0, 3.0, -1.0)]);
+
+        let mut out = Vec::new();
+        combine(CsgOp::Difference, &b, &a, &mut out);
+        assert_eq!(ends(&out), vec![(4.0, 1.0, 6.0, -1.0)]);
+    }
+
+    #[test]
+    fn hits_face_the_ray() {
+        // Two unit spheres, one centred a unit above the other along z.
+        let (a, b) = (sphere(0.0), sphere(1.0));
+        let hit = |op, z| {
+            let mut rec = HitRecord::default();
+            let csg = Csg::new(op, Arc::clone(&a), Arc::clone(&b));
+            assert!(csg.hit(&down(z), 0.001, f64::INFINITY, &mut rec));
+            (rec.t, rec.normal.z(), rec.front_face)
+        };
+        assert_eq!(hit(CsgOp::Union, 5.0), (3.0, 1.0, true));
+        assert_eq!(hit(CsgOp::Intersection, 5.0), (4.0, 1.0, true));
+        // The cut face is `b`'s exit turned around to face out of `a - b`.
+        assert_eq!(hit(CsgOp::Difference, 5.0), (5.0, 1.0, true));
+        // From inside the union, the way out faces back at the ray.
+        assert_eq!(hit(CsgOp::Union, 0.5), (1.5, 1.0, false));
+        // From inside `b` but not the difference, the cut face is an entry.
+        assert_eq!(hit(CsgOp::Difference, 1.5), (1.5, 1.0, true));
+    }
+
+    #[test]
+    fn nan_crossings_do_not_panic() {
+        let (a, b) = ([span(f64::NAN, 4.0)], [span(3.0, 6.0)]);
+        let mut out = Vec::new();
+        combine(CsgOp::Union, &a, &b, &mut out);
+    }
+}
@@ -0,0 +1,465 @@
+// Cubic Bézier curves for hair, fur and grass, intersected by recursive
+// subdivision as in pbrt: the control points are moved into a space where
+// the ray runs down +z from the origin, and the curve is split in half until
+// the pieces are close to straight, discarding any whose bounds miss the ray.
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::mesh::TriangleMesh;
+use crate::ray::Ray;
+use crate::vec3::*;
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CurveKind {
+    // A ribbon that always turns to face the ray, with a flat normal.
+    Flat,
+    // A tube of circular cross-section.
+    Round,
+}
+
+// Bézier segment with control points `points`, whose width runs linearly
+// from `width0` at the first point to `width1` at the last. On a hit, u is
+// the curve parameter, v runs from 0 to 1 across the width, and the tangent
+// points along the curve.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Curve {
+    pub points: [Point3; 4],
+    pub width0: f64,
+    pub width1: f64,
+    pub kind: CurveKind,
+    pub mat_ptr: usize,
+}
+
+fn bezier(cp: &[Point3; 4], u: f64) -> Point3 {
+    let a = lerp(u, &cp[0], &cp[1]);
+    let b = lerp(u, &cp[1], &cp[2]);
+    let c = lerp(u, &cp[2], &cp[3]);
+    lerp(u, &lerp(u, &a, &b), &lerp(u, &b, &c))
+}
+
+fn lerp(u: f64, a: &Point3, b: &Point3) -> Point3 {
+    *a * (1.0 - u) + *b * u
+}
+
+// The two halves of the curve either side of u = 1/2 (de Casteljau).
+fn split(cp: &[Point3; 4]) -> [[Point3; 4]; 2] {
+    let a = lerp(0.5, &cp[0], &cp[1]);
+    let b = lerp(0.5, &cp[1], &cp[2]);
+    let c = lerp(0.5, &cp[2], &cp[3]);
+    let ab = lerp(0.5, &a, &b);
+    let bc = lerp(0.5, &b, &c);
+    let mid = lerp(0.5, &ab, &bc);
+    [[cp[0], a, ab, mid], [mid, bc, c, cp[3]]]
+}
+
+impl Curve {
+    pub fn flat(points: [Point3; 4], width0: f64, width1: f64, mat_ptr: usize) -> Curve {
+        Curve {
+            points,
+            width0,
+            width1,
+            kind: CurveKind::Flat,
+            mat_ptr,
+        }
+    }
+
+    pub fn round(points: [Point3; 4], width0: f64, width1: f64, mat_ptr: usize) -> Curve {
+        Curve {
+            points,
+            width0,
+            width1,
+            kind: CurveKind::Round,
+            mat_ptr,
+        }
+    }
+
+    pub fn point(&self, u: f64) -> Point3 {
+        bezier(&self.points, u)
+    }
+
+    pub fn width(&self, u: f64) -> f64 {
+        self.width0 * (1.0 - u) + self.width1 * u
+    }
+
+    // Unit direction of the curve at u. Where a control point doubles up
+    // and the derivative vanishes, the chord stands in for it.
+    pub fn tangent(&self, u: f64) -> Vec3 {
+        let cp = &self.points;
+        let d = (cp[1] - cp[0]) * ((1.0 - u) * (1.0 - u))
+            + (cp[2] - cp[1]) * (2.0 * u * (1.0 - u))
+            + (cp[3] - cp[2]) * (u * u);
+        if d.length_squared() > 1e-24 {
+            unit_vector(&d)
+        } else {
+            unit_vector(&(cp[3] - cp[0]))
+        }
+    }
+
+    // Subdivisions needed for the pieces to lie within a twentieth of the
+    // width of straight lines, from the curve's second differences.
+    fn depth(cp: &[Point3; 4], max_width: f64) -> u32 {
+        let mut l0: f64 = 0.0;
+        for i in 0..2 {
+            let d = cp[i] - cp[i + 1] * 2.0 + cp[i + 2];
+            l0 = l0.max(d.x().abs()).max(d.y().abs()).max(d.z().abs());
+        }
+        let eps = 0.05 * max_width;
+        let r0 = (std::f64::consts::SQRT_2 * 6.0 * l0 / (8.0 * eps)).log2() / 2.0;
+        r0.round().clamp(0.0, 10.0) as u32
+    }
+
+    // Searches the piece `cp` spanning [u0, u1] for the nearest hit between
+    // `z_min` and `z_max`, given in ray space. A hit lowers `z_max` and
+    // records its u, and whether it is on the far side of a tube.
+    fn recurse(
+        &self,
+        cp: &[Point3; 4],
+        (u0, u1): (f64, f64),
+        depth: u32,
+        z_min: f64,
+        z_max: &mut f64,
+        found: &mut Option<(f64, bool)>,
+    ) {
+        let half = 0.5 * self.width(u0).max(self.width(u1));
+        let (mut lo, mut hi) = (cp[0], cp[0]);
+        for p in &cp[1..] {
+            lo = Point3::of(lo.x().min(p.x()), lo.y().min(p.y()), lo.z().min(p.z()));
+            hi = Point3::of(hi.x().max(p.x()), hi.y().max(p.y()), hi.z().max(p.z()));
+        }
+        if lo.x() - half > 0.0
+            || hi.x() + half < 0.0
+            || lo.y() - half > 0.0
+            || hi.y() + half < 0.0
+            || lo.z() - half > *z_max
+            || hi.z() + half < z_min
+        {
+            return;
+        }
+
+        if depth > 0 {
+            let u_mid = 0.5 * (u0 + u1);
+            let [a, b] = split(cp);
+            self.recurse(&a, (u0, u_mid), depth - 1, z_min, z_max, found);
+            self.recurse(&b, (u_mid, u1), depth - 1, z_min, z_max, found);
+            return;
+        }
+
+        // Close enough to a line. Skip rays that pass beyond either end, where
+        // the neighbouring piece or nothing should be hit.
+        let edge = (cp[1].y() - cp[0].y()) * -cp[0].y() + cp[0].x() * (cp[0].x() - cp[1].x());
+        if edge < 0.0 {
+            return;
+        }
+        let edge = (cp[2].y() - cp[3].y()) * -cp[3].y() + cp[3].x() * (cp[3].x() - cp[2].x());
+        if edge < 0.0 {
+            return;
+        }
+        let (sx, sy) = (cp[3].x() - cp[0].x(), cp[3].y() - cp[0].y());
+        let denom = sx * sx + sy * sy;
+        if denom == 0.0 {
+            return;
+        }
+        // Where the ray passes closest along the piece.
+        let w = ((-cp[0].x() * sx - cp[0].y() * sy) / denom).clamp(0.0, 1.0);
+        let u = u0 + w * (u1 - u0);
+        let pc = bezier(cp, w);
+        let radius = 0.5 * self.width(u);
+        let dist2 = pc.x() * pc.x() + pc.y() * pc.y();
+        if dist2 > radius * radius {
+            return;
+        }
+        let (z, far) = match self.kind {
+            CurveKind::Flat => (pc.z(), false),
+            // Come forward from the centre line to the near side of the tube,
+            // or go on to the far side from a ray that starts inside it.
+            CurveKind::Round => {
+                let half = (radius * radius - dist2).sqrt();
+                if pc.z() - half >= z_min {
+                    (pc.z() - half, false)
+                } else {
+                    (pc.z() + half, true)
+                }
+            }
+        };
+        if z < z_min || z > *z_max {
+            return;
+        }
+        *z_max = z;
+        *found = Some((u, far));
+    }
+
+    // An open-ended tube around the curve with `segments` + 1 rings of `sides`
+    // vertices, for export. Ribbons have no fixed orientation and come out
+    // as tubes too.
+    pub fn tube_mesh(&self, segments: usize, sides: usize) -> TriangleMesh {
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        // Carry a normal along the curve, turning it only as much as the
+        // tangent turns, so the rings don't twist.
+        let t0 = self.tangent(0.0);
+        let helper = if t0.x().abs() < 0.9 {
+            Vec3::of(1.0, 0.0, 0.0)
+        } else {
+            Vec3::of(0.0, 1.0, 0.0)
+        };
+        let mut n = unit_vector(&cross(&t0, &helper));
+        for i in 0..=segments {
+            let u = i as f64 / segments as f64;
+            let t = self.tangent(u);
+            n = unit_vector(&(n - t * dot(&n, &t)));
+            let b = cross(&t, &n);
+            let (c, radius) = (self.point(u), 0.5 * self.width(u));
+            for k in 0..=sides {
+                let v = k as f64 / sides as f64;
+                let (sin, cos) = (2.0 * PI * v).sin_cos();
+                let dir = n * cos + b * sin;
+                positions.push(c + dir * radius);
+                normals.push(dir);
+                uvs.push((u, v));
+            }
+        }
+        let index = |i: usize, k: usize| (i * (sides + 1) + k) as u32;
+        let mut indices = Vec::new();
+        for i in 0..segments {
+            for k in 0..sides {
+                indices.push([index(i, k), index(i, k + 1), index(i + 1, k + 1)]);
+                indices.push([index(i, k), index(i + 1, k + 1), index(i + 1, k)]);
+            }
+        }
+        TriangleMesh::new(positions, indices, self.mat_ptr)
+            .with_normals(normals)
+            .with_uvs(uvs)
+    }
+}
+
+impl Hittable for Curve {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // The curve lies inside the hull of its control points; most rays
+        // miss a sphere around that and need nothing more.
+        let center = (self.points[0] + self.points[1] + self.points[2] + self.points[3]) * 0.25;
+        let reach = self
+            .points
+            .iter()
+            .map(|p| (*p - center).length())
+            .fold(0.0, f64::max)
+            + 0.5 * self.width0.max(self.width1);
+        let oc = center - r.origin;
+        let along = dot(&oc, &r.direction) / r.direction.length_squared();
+        if (oc - r.direction * along).length_squared() > reach * reach {
+            return false;
+        }
+
+        // Ray space: origin at the ray's, +z down the ray, and x and y
+        // across it.
+        let speed = r.direction.length();
+        let z_axis = r.direction / speed;
+        let chord = self.points[3] - self.points[0];
+        let mut x_axis = cross(&z_axis, &chord);
+        if x_axis.length_squared() < 1e-24 {
+            let helper = if z_axis.x().abs() < 0.9 {
+                Vec3::of(1.0, 0.0, 0.0)
+            } else {
+                Vec3::of(0.0, 1.0, 0.0)
+            };
+            x_axis = cross(&z_axis, &helper);
+        }
+        let x_axis = unit_vector(&x_axis);
+        let y_axis = cross(&z_axis, &x_axis);
+        let cp = self.points.map(|p| {
+            let d = p - r.origin;
+            Point3::of(dot(&d, &x_axis), dot(&d, &y_axis), dot(&d, &z_axis))
+        });
+
+        let depth = Curve::depth(&cp, self.width0.max(self.width1));
+        let mut z_max = t_max * speed;
+        let mut found = None;
+        self.recurse(
+            &cp,
+            (0.0, 1.0),
+            depth,
+            t_min * speed,
+            &mut z_max,
+            &mut found,
+        );
+        let (u, far) = match found {
+            Some(found) => found,
+            None => return false,
+        };
+
+        let t = z_max / speed;
+        let p = r.at(t);
+        let tangent = self.tangent(u);
+        // Facing the ray square to the tangent, and across the width.
+        let mut facing = -(z_axis - tangent * dot(&z_axis, &tangent));
+        if facing.length_squared() < 1e-24 {
+            facing = -z_axis;
+        }
+        let facing = unit_vector(&facing);
+        let across = cross(&tangent, &facing);
+        let radius = 0.5 * self.width(u);
+        let s = if radius > 0.0 {
+            (dot(&(p - self.point(u)), &across) / radius).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        // A ribbon turns to face every ray, so only a tube has a back.
+        let outward = match self.kind {
+            CurveKind::Flat => facing,
+            CurveKind::Round => {
+                let out = (1.0 - s * s).sqrt();
+                facing * if far { -out } else { out } + across * s
+            }
+        };
+        let front_face = !far;
+
+        *rec = HitRecord {
+            p,
+            normal: if front_face { outward } else { -outward },
+            mat_ptr: self.mat_ptr,
+            t,
+            u,
+            v: 0.5 * (s + 1.0),
+            tangent,
+            front_face,
+        };
+        true
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        // The curve stays within the hull of its control points.
+        *output_box = Aabb::from_points(&self.points).padded(0.5 * self.width0.max(self.width1));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(curve: &Curve, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        let r = Ray::of(origin, direction);
+        curve.hit(&r, 0.001, f64::INFINITY, &mut rec).then_some(rec)
+    }
+
+    // Along x from 0 to 3, with u = x / 3, and 0.5 wide.
+    fn straight(round: bool) -> Curve {
+        let points = [0.0, 1.0, 2.0, 3.0].map(|x| Point3::of(x, 0.0, 0.0));
+        if round {
+            Curve::round(points, 0.5, 0.5, 0)
+        } else {
+            Curve::flat(points, 0.5, 0.5, 0)
+        }
+    }
+
+    #[test]
+    fn straight_tubes_are_cylinders() {
+        let tube = straight(true);
+        let down = Vec3::of(0.0, 0.0, -1.0);
+        for &(x, y) in &[(1.3, 0.1), (0.4, -0.2), (2.9, 0.0)] {
+            let rec = hit(&tube, Point3::of(x, y, 5.0), down).unwrap();
+            let z = (0.0625 - y * y).sqrt();
+            assert!((rec.t - (5.0 - z)).abs() < 1e-9, "{} {}", rec.t, z);
+            assert!((rec.u - x / 3.0).abs() < 1e-9);
+            assert!((rec.normal - Vec3::of(0.0, y, z) / 0.25).length() < 1e-9);
+            assert!(rec.front_face);
+        }
+        assert!(hit(&tube, Point3::of(1.3, 0.26, 5.0), down).is_none());
+        assert!(hit(&tube, Point3::of(3.1, 0.0, 5.0), down).is_none());
+        assert!(hit(&tube, Point3::of(-0.1, 0.0, 5.0), down).is_none());
+    }
+
+    #[test]
+    fn straight_ribbons_face_the_ray() {
+        let ribbon = straight(false);
+        let rec = hit(&ribbon, Point3::of(1.5, 0.2, 5.0), Vec3::of(0.0, 0.0, -1.0)).unwrap();
+        assert!((rec.t - 5.0).abs() < 1e-9);
+        assert_eq!(rec.normal, Vec3::of(0.0, 0.0, 1.0));
+        assert!(rec.front_face);
+        // Seen from the side it turns to face that way instead.
+        let rec = hit(&ribbon, Point3::of(1.5, -5.0, 0.2), Vec3::of(0.0, 1.0, 0.0)).unwrap();
+        assert!((rec.t - 5.0).abs() < 1e-9);
+        assert_eq!(rec.normal, Vec3::of(0.0, -1.0, 0.0));
+        assert!(hit(&ribbon, Point3::of(1.5, 0.3, 5.0), Vec3::of(0.0, 0.0, -1.0)).is_none());
+    }
+
+    #[test]
+    fn v_runs_across_the_width() {
+        let down = Vec3::of(0.0, 0.0, -1.0);
+        for curve in &[straight(false), straight(true)] {
+            let v = |y| hit(curve, Point3::of(1.5, y, 5.0), down).unwrap().v;
+            assert!((v(0.0) - 0.5).abs() < 1e-9);
+            assert!((v(0.125) + v(-0.125) - 1.0).abs() < 1e-9);
+            assert!((v(0.125) - 0.25).abs() < 1e-9 && v(0.2) < v(0.1));
+        }
+    }
+
+    #[test]
+    fn tubes_are_seen_from_inside() {
+        let tube = straight(true);
+        let rec = hit(&tube, Point3::of(1.5, 0.0, 0.15), Vec3::of(0.0, 1.0, 0.0)).unwrap();
+        assert!((rec.t - 0.2).abs() < 1e-9);
+        assert!(!rec.front_face);
+        assert!((rec.normal - Vec3::of(0.0, -0.8, -0.6)).length() < 1e-9);
+    }
+
+    #[test]
+    fn subdivision_finds_a_bent_curve() {
+        let points = [
+            Point3::of(0.0, 0.0, 0.0),
+            Point3::of(1.0, 2.0, 0.0),
+            Point3::of(2.0, -2.0, 0.5),
+            Point3::of(3.0, 0.0, 0.0),
+        ];
+        let curve = Curve::round(points, 0.1, 0.05, 0);
+        for i in 1..20 {
+            let u = i as f64 / 20.0;
+            let target = curve.point(u);
+            let origin = target + Vec3::of(0.3, -0.2, 4.0);
+            let rec = hit(&curve, origin, target - origin).unwrap();
+            assert!((rec.u - u).abs() < 0.02, "{} for {}", rec.u, u);
+            let off = (rec.p - curve.point(rec.u)).length();
+            assert!(
+                off <= 0.5 * curve.width(rec.u) + 1e-3,
+                "{} off at {}",
+                off,
+                u
+            );
+            assert!((rec.tangent - curve.tangent(rec.u)).length() < 1e-12);
+            assert!(dot(&rec.normal, &(target - origin)) < 0.0);
+        }
+        let beside = curve.point(0.5) + Vec3::of(0.0, 0.0, 0.2);
+        assert!(hit(
+            &curve,
+            beside + Vec3::of(0.0, 4.0, 0.0),
+            Vec3::of(0.0, -1.0, 0.0)
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn tangents_follow_the_curve() {
+        let tube = straight(true);
+        for &u in &[0.0, 0.3, 1.0] {
+            assert!((tube.tangent(u) - Vec3::of(1.0, 0.0, 0.0)).length() < 1e-12);
+        }
+        // With a doubled end point the chord stands in at that end.
+        let doubled = Curve::round(
+            [
+                Point3::of(0.0, 0.0, 0.0),
+                Point3::of(0.0, 0.0, 0.0),
+                Point3::of(1.0, 1.0, 0.0),
+                Point3::of(2.0, 0.0, 0.0),
+            ],
+            0.1,
+            0.1,
+            0,
+        );
+        assert!((doubled.tangent(0.0) - Vec3::of(1.0, 0.0, 0.0)).length() < 1e-12);
+        assert!((doubled.tangent(1.0) - unit_vector(&Vec3::of(1.0, -1.0, 0.0))).length() < 1e-12);
+        let midway = doubled.tangent(0.5);
+        assert!((midway.length() - 1.0).abs() < 1e-12 && midway.x() > 0.0);
+    }
+}
@@ -0,0 +1,350 @@
+// Displacement mapping, baked into the geometry when the scene is built. A
+// mesh is first split until no edge is longer than a target length, so that
+// the detail has vertices to land on, then each vertex is pushed along its
+// normal by a scalar texture and the normals are recomputed.
+
+use crate::heightfield::read_pgm;
+use crate::mesh::TriangleMesh;
+use crate::noise::fbm;
+use crate::vec3::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// A scalar looked up by position and texture coordinates. Any
+// `Fn(&Point3, (f64, f64)) -> f64` is one.
+pub trait ScalarTexture {
+    fn value(&self, p: &Point3, uv: (f64, f64)) -> f64;
+}
+
+impl<F: Fn(&Point3, (f64, f64)) -> f64> ScalarTexture for F {
+    fn value(&self, p: &Point3, uv: (f64, f64)) -> f64 {
+        self(p, uv)
+    }
+}
+
+// Fractal noise over space, so it needs no UVs and has no seams.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoiseTexture {
+    // Features per unit length of the first octave.
+    pub frequency: f64,
+    pub octaves: u32,
+    pub seed: u64,
+}
+
+impl ScalarTexture for NoiseTexture {
+    fn value(&self, p: &Point3, _uv: (f64, f64)) -> f64 {
+        fbm(&(*p * self.frequency), self.octaves, self.seed)
+    }
+}
+
+// Greyscale image in [0, 1], looked up bilinearly by UV and repeating
+// outside [0, 1]. v runs up the image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageTexture {
+    pub width: usize,
+    pub height: usize,
+    pub samples: Vec<f64>,
+}
+
+impl ImageTexture {
+    pub fn load_pgm(path: &Path) -> io::Result<ImageTexture> {
+        let (width, height, samples) = read_pgm(&fs::read(path)?)?;
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "empty texture image",
+            ));
+        }
+        Ok(ImageTexture {
+            width,
+            height,
+            samples,
+        })
+    }
+
+    fn texel(&self, x: i64, y: i64) -> f64 {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.rem_euclid(self.height as i64) as usize;
+        self.samples[y * self.width + x]
+    }
+}
+
+impl ScalarTexture for ImageTexture {
+    fn value(&self, _p: &Point3, (u, v): (f64, f64)) -> f64 {
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = self.texel(x0, y0) * (1.0 - fx) + self.texel(x0 + 1, y0) * fx;
+        let bottom = self.texel(x0, y0 + 1) * (1.0 - fx) + self.texel(x0 + 1, y0 + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+// Upper bound on splitting passes; each one at least halves the longest
+// edge.
+const MAX_PASSES: usize = 24;
+// Refining stops short of the target edge length rather than make more
+// triangles than this.
+pub const MAX_FACES: usize = 1 << 22;
+
+// Splits triangles until no edge is longer than `max_edge`, or until another
+// pass would make more than `MAX_FACES`. Only long edges are split, and a
+// triangle with one, two or three of them becomes two, three or four;
+// neighbours share the new midpoints, so no cracks open. Normals and UVs are
+// interpolated, and smooth normals are made first for a mesh without any.
+pub fn refine(mesh: &TriangleMesh, max_edge: f64) -> TriangleMesh {
+    refine_within(mesh, max_edge, MAX_FACES)
+}
+
+fn refine_within(mesh: &TriangleMesh, max_edge: f64, max_faces: usize) -> TriangleMesh {
+    assert!(max_edge > 0.0, "target edge length must be positive");
+    let mut mesh = if mesh.normals.is_empty() {
+        mesh.clone().with_smooth_normals()
+    } else {
+        mesh.clone()
+    };
+    let has_uvs = !mesh.uvs.is_empty();
+    let max2 = max_edge * max_edge;
+    for _ in 0..MAX_PASSES {
+        let long = |a: u32, b: u32| {
+            (mesh.positions[a as usize] - mesh.positions[b as usize]).length_squared() > max2
+        };
+        // Each long edge adds a triangle to each face it borders.
+        let faces: usize = mesh
+            .indices
+            .iter()
+            .map(|&[a, b, c]| {
+                1 + [long(a, b), long(b, c), long(c, a)]
+                    .iter()
+                    .filter(|&&l| l)
+                    .count()
+            })
+            .sum();
+        if faces == mesh.indices.len() || faces > max_faces {
+            break;
+        }
+
+        let mut positions = mesh.positions.clone();
+        let mut normals = mesh.normals.clone();
+        let mut uvs = mesh.uvs.clone();
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut indices = Vec::new();
+        let mut face_materials = Vec::new();
+        for (face, &[a, b, c]) in mesh.indices.iter().enumerate() {
+            let mut split = |x: u32, y: u32| -> Option<u32> {
+                if !long(x, y) {
+                    return None;
+                }
+                let key = (x.min(y), x.max(y));
+                Some(*midpoints.entry(key).or_insert_with(|| {
+                    let (i, j) = (x as usize, y as usize);
+                    positions.push((mesh.positions[i] + mesh.positions[j]) * 0.5);
+                    let n = mesh.normals[i] + mesh.normals[j];
+                    normals.push(if n.length_squared() > 0.0 {
+                        unit_vector(&n)
+                    } else {
+                        mesh.normals[i]
+                    });
+                    if has_uvs {
+                        let (ui, uj) = (mesh.uvs[i], mesh.uvs[j]);
+                        uvs.push((0.5 * (ui.0 + uj.0), 0.5 * (ui.1 + uj.1)));
+                    }
+                    (positions.len() - 1) as u32
+                }))
+            };
+            let corners = [a, b, c];
+            let mids = [split(a, b), split(b, c), split(c, a)];
+            // Turn the triangle so its first edge is split, and where two
+            // are, so is its second.
+            let turn = match mids {
+                [None, None, None] => None,
+                [Some(_), None, None] | [Some(_), Some(_), None] | [Some(_), Some(_), Some(_)] => {
+                    Some(0)
+                }
+                [None, Some(_), None] | [None, Some(_), Some(_)] => Some(1),
+                [None, None, Some(_)] | [Some(_), None, Some(_)] => Some(2),
+            };
+            match turn {
+                None => indices.push([a, b, c]),
+                Some(k) => {
+                    let v = |i: usize| corners[(k + i) % 3];
+                    let m = |i: usize| mids[(k + i) % 3];
+                    match (m(0), m(1), m(2)) {
+                        (Some(ab), None, None) => {
+                            indices.push([v(0), ab, v(2)]);
+                            indices.push([ab, v(1), v(2)]);
+                        }
+                        (Some(ab), Some(bc), None) => {
+                            indices.push([ab, v(1), bc]);
+                            indices.push([v(0), ab, bc]);
+                            indices.push([v(0), bc, v(2)]);
+                        }
+                        (Some(ab), Some(bc), Some(ca)) => {
+                            indices.push([v(0), ab, ca]);
+                            indices.push([v(1), bc, ab]);
+                            indices.push([v(2), ca, bc]);
+                            indices.push([ab, bc, ca]);
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            if let Some(&id) = mesh.face_materials.get(face) {
+                face_materials.resize(indices.len(), id);
+            }
+        }
+
+        let materials = mesh.materials.clone();
+        let mut next = TriangleMesh::new(positions, indices, materials[0]).with_normals(normals);
+        if has_uvs {
+            next = next.with_uvs(uvs);
+        }
+        if !face_materials.is_empty() {
+            next = next.with_face_materials(face_materials, materials);
+        }
+        mesh = next;
+    }
+    mesh
+}
+
+// Moves every vertex along its normal by `scale` times the texture there,
+// then recomputes smooth normals from the new shape. Needs vertex normals;
+// `refine` provides them. Vertices that share a position but not a normal,
+// as along a hard edge, move apart and open a crack.
+pub fn displace<T: ScalarTexture>(mesh: &TriangleMesh, texture: &T, scale: f64) -> TriangleMesh {
+    assert!(
+        !mesh.normals.is_empty(),
+        "displacement needs vertex normals"
+    );
+    let mut out = mesh.clone();
+    for (i, p) in out.positions.iter_mut().enumerate() {
+        let uv = mesh.uvs.get(i).cloned().unwrap_or((0.0, 0.0));
+        *p = *p + mesh.normals[i] * (scale * texture.value(p, uv));
+    }
+    out.with_smooth_normals()
+}
+
+// `refine` to `max_edge`, then `displace`.
+pub fn displaced<T: ScalarTexture>(
+    mesh: &TriangleMesh,
+    max_edge: f64,
+    texture: &T,
+    scale: f64,
+) -> TriangleMesh {
+    displace(&refine(mesh, max_edge), texture, scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn longest_edge(mesh: &TriangleMesh) -> f64 {
+        let edge =
+            |a: u32, b: u32| (mesh.positions[a as usize] - mesh.positions[b as usize]).length();
+        mesh.indices
+            .iter()
+            .map(|&[a, b, c]| edge(a, b).max(edge(b, c)).max(edge(c, a)))
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn refining_leaves_no_long_edges_or_cracks() {
+        let sphere = TriangleMesh::icosphere(1, 0);
+        let before = longest_edge(&sphere);
+        for &max_edge in &[before, 0.3, 0.1, 0.04] {
+            let fine = refine(&sphere, max_edge);
+            assert!(longest_edge(&fine) <= max_edge, "{}", max_edge);
+            // A closed surface with shared vertices has V - E + F = 2, and
+            // each edge borders two faces; a crack would add vertices.
+            assert_eq!(fine.positions.len(), fine.indices.len() / 2 + 2);
+            assert_eq!(fine.normals.len(), fine.positions.len());
+        }
+        assert_eq!(refine(&sphere, before).indices, sphere.indices);
+    }
+
+    #[test]
+    fn refining_carries_uvs_and_materials() {
+        let p = |x, y| Point3::of(x, y, 0.0);
+        let square = TriangleMesh::new(
+            vec![p(0.0, 0.0), p(1.0, 0.0), p(1.0, 1.0), p(0.0, 1.0)],
+            vec![[0, 1, 2], [0, 2, 3]],
+            0,
+        )
+        .with_uvs(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)])
+        .with_face_materials(vec![0, 1], vec![5, 6]);
+        let fine = refine(&square, 0.2);
+        assert!(longest_edge(&fine) <= 0.2);
+        for (q, &(u, v)) in fine.positions.iter().zip(&fine.uvs) {
+            assert!((q.x() - u).abs() < 1e-12 && (q.y() - v).abs() < 1e-12);
+        }
+        // Faces keep the material of the one they were cut from.
+        for (face, &[a, b, c]) in fine.indices.iter().enumerate() {
+            let centre = (fine.positions[a as usize]
+                + fine.positions[b as usize]
+                + fine.positions[c as usize])
+                / 3.0;
+            let expected = if centre.x() > centre.y() { 5 } else { 6 };
+            assert_eq!(fine.mat_ptr(face), expected);
+        }
+    }
+
+    #[test]
+    fn refining_stops_at_the_face_budget() {
+        let sphere = TriangleMesh::icosphere(0, 0);
+        let fine = refine_within(&sphere, 1e-9, 5000);
+        assert!(fine.indices.len() <= 5000);
+        // One more pass would have gone over.
+        assert!(fine.indices.len() * 4 > 5000);
+    }
+
+    #[test]
+    fn displacing_moves_vertices_along_their_normals() {
+        let sphere = refine(&TriangleMesh::icosphere(1, 0), 0.2);
+        let lifted = displace(&sphere, &|_: &Point3, _: (f64, f64)| 1.0, 0.5);
+        assert_eq!(lifted.indices, sphere.indices);
+        for (i, p) in lifted.positions.iter().enumerate() {
+            let expected = sphere.positions[i] + sphere.normals[i] * 0.5;
+            assert!((*p - expected).length() < 1e-12);
+            assert!(dot(&unit_vector(p), &lifted.normals[i]) > 0.95);
+        }
+        // Zero scale leaves the shape alone.
+        let same = displace(
+            &sphere,
+            &NoiseTexture {
+                frequency: 3.0,
+                octaves: 2,
+                seed: 1,
+            },
+            0.0,
+        );
+        assert_eq!(same.positions, sphere.positions);
+    }
+
+    #[test]
+    fn images_are_sampled_bilinearly_and_repeat() {
+        // 0 1
+        // 2 3, with v = 1 along the top row.
+        let image = ImageTexture {
+            width: 2,
+            height: 2,
+            samples: vec![0.0, 1.0, 2.0, 3.0],
+        };
+        let at = |u, v| image.value(&Point3::new(), (u, v));
+        let near = |a: f64, b: f64| (a - b).abs() < 1e-12;
+        // Texel centres.
+        assert!(near(at(0.25, 0.75), 0.0));
+        assert!(near(at(0.75, 0.25), 3.0));
+        // Halfway between texels, and between all four.
+        assert!(near(at(0.5, 0.75), 0.5));
+        assert!(near(at(0.5, 0.5), 1.5));
+        // Across the edges the image wraps around.
+        assert!(near(at(0.0, 0.75), 0.5));
+        assert!(near(at(0.25, 1.0), 1.0));
+        assert!(near(at(1.25, 0.75), 0.0));
+        assert!(near(at(-0.25, -1.75), 3.0));
+    }
+}
@@ -0,0 +1,442 @@
+use crate::material::*;
+use crate::scenes::{cover_view, Scene};
+use crate::vec3::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug)]
+pub enum RadiusDistribution {
+    Fixed(f64),
+    Uniform(f64, f64),
+}
+
+impl RadiusDistribution {
+    fn min(&self) -> f64 {
+        match *self {
+            RadiusDistribution::Fixed(r) => r,
+            RadiusDistribution::Uniform(min, _) => min,
+        }
+    }
+
+    fn max(&self) -> f64 {
+        match *self {
+            RadiusDistribution::Fixed(r) => r,
+            RadiusDistribution::Uniform(_, max) => max,
+        }
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> f64 {
+        match *self {
+            RadiusDistribution::Fixed(r) => r,
+            RadiusDistribution::Uniform(min, max) => rng.gen_range(min, max),
+        }
+    }
+}
+
+// Relative weights; they do not need to sum to one.
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialWeights {
+    pub lambertian: f64,
+    pub metal: f64,
+    pub dielectric: f64,
+}
+
+#[derive(Clone, Debug)]
+pub enum Palette {
+    // Product of two uniformly random colours, as in the book.
+    Random,
+    // Uniform pick from a fixed list.
+    Choose(Vec<Color>),
+}
+
+impl Palette {
+    pub fn named(name: &str) -> Option<Palette> {
+        let hex = |rgb: &[u32]| -> Palette {
+            Palette::Choose(
+                rgb.iter()
+                    .map(|c| {
+                        Color::of(
+                            ((c >> 16) & 0xff) as f64 / 255.0,
+                            ((c >> 8) & 0xff) as f64 / 255.0,
+                            (c & 0xff) as f64 / 255.0,
+                        )
+                    })
+                    .collect(),
+            )
+        };
+        match name {
+            "random" => Some(Palette::Random),
+            "pastel" => Some(hex(&[0xffb3ba, 0xffdfba, 0xffffba, 0xbaffc9, 0xbae1ff])),
+            "earth" => Some(hex(&[0x5b3a29, 0x8b5a2b, 0xa0522d, 0x6b8e23, 0x556b2f])),
+            "mono" => Some(hex(&[0x202020, 0x606060, 0xa0a0a0, 0xe0e0e0])),
+            _ => None,
+        }
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> Color {
+        match self {
+            Palette::Random => random_color(rng, 0.0, 1.0) * random_color(rng, 0.0, 1.0),
+            Palette::Choose(colors) => colors[rng.gen_range(0, colors.len())],
+        }
+    }
+}
+
+// Everything `random_scene` used to hardcode. The default places the same
+// spheres with the same materials for a given seed; only the ground has
+// changed, from a huge sphere to a `Plane`.
+#[derive(Clone, Debug)]
+pub struct RandomSceneConfig {
+    pub seed: u64,
+    // Grid cells along x and z, half-open like `-11..11`.
+    pub x_range: (i32, i32),
+    pub z_range: (i32, i32),
+    pub spacing: f64,
+    // Fraction of a cell a centre may be offset by.
+    pub jitter: f64,
+    pub radius: RadiusDistribution,
+    pub weights: MaterialWeights,
+    pub palette: Palette,
+    pub metal_albedo: (f64, f64),
+    pub fuzz: (f64, f64),
+    pub ir: f64,
+    // Small spheres may not come closer than the given distance to these points.
+    pub exclusions: Vec<(Point3, f64)>,
+    pub reject_overlaps: bool,
+    pub ground: bool,
+    pub feature_spheres: bool,
+}
+
+impl Default for RandomSceneConfig {
+    fn default() -> RandomSceneConfig {
+        RandomSceneConfig {
+            seed: 0,
+            x_range: (-11, 11),
+            z_range: (-11, 11),
+            spacing: 1.0,
+            jitter: 0.9,
+            radius: RadiusDistribution::Fixed(0.2),
+            weights: MaterialWeights {
+                lambertian: 0.8,
+                metal: 0.15,
+                dielectric: 0.05,
+            },
+            palette: Palette::Random,
+            metal_albedo: (0.5, 1.0),
+            fuzz: (0.0, 0.5),
+            ir: 1.5,
+            exclusions: vec![(Point3::of(4.0, 0.2, 0.0), 0.9)],
+            reject_overlaps: false,
+            ground: true,
+            feature_spheres: true,
+        }
+    }
+}
+
+impl RandomSceneConfig {
+    // Square grid of `-extent..extent` cells in both directions.
+    pub fn with_extent(mut self, extent: i32) -> RandomSceneConfig {
+        self.x_range = (-extent, extent);
+        self.z_range = (-extent, extent);
+        self
+    }
+
+    // Applies comma separated `key=value` overrides on top of `self`, e.g.
+    // `extent=50,radius=0.1..0.3,weights=1/1/0,palette=pastel,reject_overlaps=true`.
+    pub fn parse(mut self, spec: &str) -> Result<RandomSceneConfig, String> {
+        for item in spec.split(',').filter(|s| !s.is_empty()) {
+            let mut kv = item.splitn(2, '=');
+            let key = kv.next().unwrap_or("").trim();
+            let value = kv
+                .next()
+                .ok_or_else(|| format!("missing value for '{}'", key))?
+                .trim();
+            match key {
+                "seed" => self.seed = number(key, value)?,
+                "extent" => self = self.with_extent(number(key, value)?),
+                "spacing" => self.spacing = number(key, value)?,
+                "jitter" => self.jitter = number(key, value)?,
+                "radius" => {
+                    self.radius = match range(key, value)? {
+                        (r, None) => RadiusDistribution::Fixed(r),
+                        (min, Some(max)) => RadiusDistribution::Uniform(min, max),
+                    }
+                }
+                "weights" => {
+                    let w = value
+                        .split('/')
+                        .map(|x| number(key, x))
+                        .collect::<Result<Vec<f64>, String>>()?;
+                    if w.len() != 3 {
+                        return Err(format!("'{}' needs lambertian/metal/dielectric", key));
+                    }
+                    self.weights = MaterialWeights {
+                        lambertian: w[0],
+                        metal: w[1],
+                        dielectric: w[2],
+                    };
+                }
+                "palette" => {
+                    self.palette = Palette::named(value)
+                        .ok_or_else(|| format!("unknown palette '{}'", value))?
+                }
+                "fuzz" => self.fuzz = full_range(key, value)?,
+                "metal" => self.metal_albedo = full_range(key, value)?,
+                "ir" => self.ir = number(key, value)?,
+                "reject_overlaps" => self.reject_overlaps = flag(key, value)?,
+                "ground" => self.ground = flag(key, value)?,
+                "features" => self.feature_spheres = flag(key, value)?,
+                _ => return Err(format!("unknown generator option '{}'", key)),
+            }
+        }
+        self.check()?;
+        Ok(self)
+    }
+
+    // Rejects settings `generate` can't work with, such as ranges running
+    // backwards.
+    fn check(&self) -> Result<(), String> {
+        let (min, max) = (self.radius.min(), self.radius.max());
+        if !(min > 0.0 && max.is_finite()) {
+            return Err(format!(
+                "radii must be positive and finite, not {}..{}",
+                min, max
+            ));
+        }
+        if let RadiusDistribution::Uniform(min, max) = self.radius {
+            if min.partial_cmp(&max) != Some(Ordering::Less) {
+                return Err(format!("radius range {}..{} is empty", min, max));
+            }
+        }
+        if !(self.spacing > 0.0 && self.spacing.is_finite()) {
+            return Err(format!("spacing must be positive, not {}", self.spacing));
+        }
+        let ordered = [
+            ("fuzz", self.fuzz),
+            ("metal", self.metal_albedo),
+            ("extent", (self.x_range.0 as f64, self.x_range.1 as f64)),
+        ];
+        for (key, (min, max)) in ordered.iter() {
+            if matches!(min.partial_cmp(max), None | Some(Ordering::Greater)) {
+                return Err(format!("'{}' runs backwards, from {} to {}", key, min, max));
+            }
+        }
+        let w = self.weights;
+        let weights = [w.lambertian, w.metal, w.dielectric];
+        if weights.iter().any(|x| x.is_nan() || *x < 0.0) || weights.iter().sum::<f64>() <= 0.0 {
+            return Err(String::from(
+                "weights must be non-negative, and not all zero",
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn number<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("bad value '{}' for '{}'", value, key))
+}
+
+fn flag(key: &str, value: &str) -> Result<bool, String> {
+    number(key, value)
+}
+
+// Either `x` or `min..max`.
+fn range(key: &str, value: &str) -> Result<(f64, Option<f64>), String> {
+    match value.find("..") {
+        Some(i) => Ok((
+            number(key, &value[..i])?,
+            Some(number(key, &value[i + 2..])?),
+        )),
+        None => Ok((number(key, value)?, None)),
+    }
+}
+
+fn full_range(key: &str, value: &str) -> Result<(f64, f64), String> {
+    match range(key, value)? {
+        (x, None) => Ok((x, x)),
+        (min, Some(max)) => Ok((min, max)),
+    }
+}
+
+fn random_color(rng: &mut StdRng, min: f64, max: f64) -> Color {
+    Color::of(
+        rng.gen_range(min, max),
+        rng.gen_range(min, max),
+        rng.gen_range(min, max),
+    )
+}
+
+// `gen_range` panics on an empty range, which a degenerate config can ask for.
+fn gen_between(rng: &mut StdRng, (min, max): (f64, f64)) -> f64 {
+    if min < max {
+        rng.gen_range(min, max)
+    } else {
+        min
+    }
+}
+
+fn random_material(config: &RandomSceneConfig, rng: &mut StdRng, choose_mat: f64) -> Mat {
+    let w = config.weights;
+    let total = w.lambertian + w.metal + w.dielectric;
+    if choose_mat < w.lambertian / total {
+        Mat::L(Lambertian {
+            albedo: config.palette.sample(rng),
+        })
+    } else if choose_mat < (w.lambertian + w.metal) / total {
+        let (min, max) = config.metal_albedo;
+        let albedo = if min < max {
+            random_color(rng, min, max)
+        } else {
+            Color::of(min, min, min)
+        };
+        let fuzz = gen_between(rng, config.fuzz);
+        Mat::M(Metal::new(albedo, fuzz))
+    } else {
+        Mat::D(Dielectric { ir: config.ir })
+    }
+}
+
+pub fn generate(config: &RandomSceneConfig) -> Scene {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut scene = Scene::new(cover_view());
+
+    if config.ground {
+        scene.add_ground(Mat::L(Lambertian {
+            albedo: Color::of(0.5, 0.5, 0.5),
+        }));
+    }
+
+    // Placed spheres bucketed by squares as wide as the largest two radii,
+    // so only the eight squares around a sphere's own can hold one it hits.
+    let mut placed: HashMap<(i64, i64), Vec<(Point3, f64)>> = HashMap::new();
+    let width = 2.0 * config.radius.max();
+    let square = |p: Point3| {
+        (
+            (p.x() / width).floor() as i64,
+            (p.z() / width).floor() as i64,
+        )
+    };
+
+    for a in config.x_range.0..config.x_range.1 {
+        for b in config.z_range.0..config.z_range.1 {
+            let choose_mat: f64 = rng.gen();
+            let x = (a as f64 + config.jitter * rng.gen::<f64>()) * config.spacing;
+            let z = (b as f64 + config.jitter * rng.gen::<f64>()) * config.spacing;
+            let radius = config.radius.sample(&mut rng);
+            let center = Point3::of(x, radius, z);
+
+            let excluded = config
+                .exclusions
+                .iter()
+                .any(|(p, d)| (center - p).length() <= *d);
+            if excluded {
+                continue;
+            }
+            let m = random_material(config, &mut rng, choose_mat);
+
+            if config.reject_overlaps {
+                let (i0, j0) = square(center);
+                let overlaps = (i0 - 1..=i0 + 1).any(|i| {
+                    (j0 - 1..=j0 + 1).any(|j| {
+                        placed.get(&(i, j)).is_some_and(|v| {
+                            v.iter().any(|(c, r)| (center - c).length() < radius + r)
+                        })
+                    })
+                });
+                if overlaps {
+                    continue;
+                }
+                placed
+                    .entry(square(center))
+                    .or_default()
+                    .push((center, radius));
+            }
+            scene.add_sphere(center, radius, m);
+        }
+    }
+
+    if config.feature_spheres {
+        scene.add_sphere(
+            Point3::of(0.0, 1.0, 0.0),
+            1.0,
+            Mat::D(Dielectric { ir: 1.5 }),
+        );
+        scene.add_sphere(
+            Point3::of(-4.0, 1.0, 0.0),
+            1.0,
+            Mat::L(Lambertian {
+                albedo: Color::of(0.4, 0.2, 0.1),
+            }),
+        );
+        scene.add_sphere(
+            Point3::of(4.0, 1.0, 0.0),
+            1.0,
+            Mat::M(Metal::new(Color::of(0.7, 0.6, 0.5), 0.0)),
+        );
+    }
+
+    scene
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Object;
+
+    #[test]
+    fn rejects_what_generate_cannot_use() {
+        let parse = |spec| RandomSceneConfig::default().parse(spec);
+        assert!(parse("radius=0.1..0.3,spacing=2,fuzz=0..0.2,extent=3").is_ok());
+        for spec in &[
+            "radius=0.3..0.1",
+            "radius=0.2..0.2",
+            "radius=0",
+            "radius=-0.2",
+            "radius=inf",
+            "radius=nan",
+            "radius=0..0.3",
+            "radius=0.1..inf",
+            "spacing=0",
+            "spacing=-1",
+            "spacing=nan",
+            "fuzz=0.5..0.1",
+            "metal=1..0.5",
+            "extent=-2",
+            "weights=0/0/0",
+            "weights=1/-1/0",
+        ] {
+            assert!(parse(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn rejected_overlaps_leave_no_spheres_touching() {
+        let spheres = |spec| {
+            let config = RandomSceneConfig::default().parse(spec).unwrap();
+            let scene = generate(&RandomSceneConfig {
+                feature_spheres: false,
+                ..config
+            });
+            let spheres = scene.world.iter().filter_map(|o| match o {
+                Object::Sphere(s) => Some((s.center, s.radius)),
+                _ => None,
+            });
+            spheres.collect::<Vec<_>>()
+        };
+        let crowded = "spacing=0.3,radius=0.05..0.6,reject_overlaps=true,ground=false";
+        let placed = spheres(crowded);
+        assert!(placed.len() > 10);
+        for (k, (c, r)) in placed.iter().enumerate() {
+            for (d, s) in &placed[k + 1..] {
+                assert!((*c - *d).length() >= r + s);
+            }
+        }
+        // Radii far bigger than the grid don't overflow the buckets.
+        // Only one of them fits.
+        let huge = spheres("radius=1e10,reject_overlaps=true,ground=false");
+        assert_eq!(huge.len(), 1);
+    }
+}
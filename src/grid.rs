@@ -0,0 +1,369 @@
+// Hierarchical grid over the world. Each level splits its box into cells of
+// equal size, about `DENSITY` of them per object, and lists the objects
+// whose boxes overlap each cell; crowded cells get a finer grid of their
+// own. Rays step from cell to cell in order with a 3D DDA and stop once the
+// nearest hit so far lies within the cells already visited.
+//
+// An object is listed in every cell it overlaps, so a ray can meet it more
+// than once; a few of the objects tested last are remembered to skip most
+// repeats.
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::accel::{loosened, object_boxes, try_object, Accelerator};
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Cells per object in each level.
+const DENSITY: f64 = 3.0;
+// Cells along any one axis of a level.
+const MAX_RESOLUTION: usize = 128;
+// Cells holding more objects than this get a grid of their own...
+const MAX_CELL: usize = 8;
+// ...down to this many levels below the top.
+const MAX_LEVEL: usize = 2;
+// Recently tested objects remembered by a ray.
+const MAILBOX: usize = 8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grid<T: Hittable> {
+    objects: Vec<T>,
+    root: Option<Level>,
+    // Objects without a bounding box, tested against every ray.
+    unbounded: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Level {
+    bounds: Aabb,
+    resolution: [usize; 3],
+    cell_size: Vec3,
+    // The objects overlapping cell `c` are `items[starts[c]..starts[c + 1]]`.
+    starts: Vec<u32>,
+    items: Vec<u32>,
+    // Finer grids for crowded cells, which then list nothing themselves.
+    children: Vec<Option<Box<Level>>>,
+}
+
+// What a ray carries through the levels.
+struct Walk<'a, T> {
+    objects: &'a [T],
+    r: &'a Ray,
+    inv_d: Vec3,
+    t_min: f64,
+    closest: (f64, Option<u32>),
+    mailbox: [u32; MAILBOX],
+    next_slot: usize,
+    tests: u64,
+}
+
+impl<T: Hittable + Sync> Grid<T> {
+    pub fn new(objects: Vec<T>) -> Grid<T> {
+        let mut items = Vec::with_capacity(objects.len());
+        let mut unbounded = Vec::new();
+        for (i, bounds) in object_boxes(&objects).into_iter().enumerate() {
+            match bounds {
+                Some(bounds) => items.push((i as u32, loosened(&bounds))),
+                None => unbounded.push(i as u32),
+            }
+        }
+        let root = if items.is_empty() {
+            None
+        } else {
+            let bounds = items
+                .iter()
+                .fold(Aabb::empty(), |b, (_, item)| surrounding_box(&b, item));
+            Some(Level::new(&items, bounds, 0))
+        };
+        Grid {
+            objects,
+            root,
+            unbounded,
+        }
+    }
+}
+
+impl<T: Hittable> Grid<T> {
+    pub fn objects(&self) -> &[T] {
+        &self.objects
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.root.as_ref().map_or(0, Level::cell_count)
+    }
+}
+
+impl Level {
+    fn new(items: &[(u32, Aabb)], bounds: Aabb, level: usize) -> Level {
+        let extent = bounds.maximum - bounds.minimum;
+        // Cells per unit length that give `DENSITY` per object over the
+        // volume. An axis thinner than one such cell, as for a flat scene,
+        // gets a single cell and is left out of the volume, so the others
+        // take its share.
+        let mut flat = [false; 3];
+        let mut per_unit = 0.0;
+        loop {
+            let free: Vec<usize> = (0..3).filter(|&a| !flat[a]).collect();
+            if free.is_empty() {
+                break;
+            }
+            let volume: f64 = free.iter().map(|&a| extent[a]).product();
+            per_unit = (DENSITY * items.len() as f64 / volume).powf(1.0 / free.len() as f64);
+            let thin: Vec<usize> = free
+                .into_iter()
+                .filter(|&a| extent[a] == 0.0 || extent[a] * per_unit < 1.0)
+                .collect();
+            if thin.is_empty() {
+                break;
+            }
+            for a in thin {
+                flat[a] = true;
+            }
+        }
+        let mut resolution = [1; 3];
+        let mut cell_size = [0.0; 3];
+        for a in 0..3 {
+            if !flat[a] {
+                resolution[a] = ((extent[a] * per_unit).round() as usize).clamp(1, MAX_RESOLUTION);
+            }
+            cell_size[a] = extent[a] / resolution[a] as f64;
+        }
+        let mut grid = Level {
+            bounds,
+            resolution,
+            cell_size: Vec3::of(cell_size[0], cell_size[1], cell_size[2]),
+            starts: Vec::new(),
+            items: Vec::new(),
+            children: Vec::new(),
+        };
+
+        // Count, then fill, each cell's list.
+        let cells = resolution.iter().product::<usize>();
+        let mut starts = vec![0u32; cells + 1];
+        for (_, b) in items {
+            grid.for_cells(b, |c| starts[c + 1] += 1);
+        }
+        for c in 0..cells {
+            starts[c + 1] += starts[c];
+        }
+        // Positions in `items` for now, to find the boxes again below.
+        let mut filled = starts.clone();
+        let mut list = vec![0; starts[cells] as usize];
+        for (k, (_, b)) in items.iter().enumerate() {
+            grid.for_cells(b, |c| {
+                list[filled[c] as usize] = k as u32;
+                filled[c] += 1;
+            });
+        }
+
+        let mut children: Vec<Option<Box<Level>>> = vec![None; cells];
+        if level < MAX_LEVEL {
+            for (c, child) in children.iter_mut().enumerate() {
+                let (start, end) = (starts[c] as usize, starts[c + 1] as usize);
+                if end - start <= MAX_CELL {
+                    continue;
+                }
+                let cell = grid.cell_bounds(c);
+                // Objects covering the whole cell would be in every finer
+                // cell too, so only the rest count towards splitting it.
+                let clipped: Vec<(u32, Aabb)> = list[start..end]
+                    .iter()
+                    .map(|&k| {
+                        let (i, b) = items[k as usize];
+                        (i, b.intersection(&cell).unwrap_or(cell))
+                    })
+                    .collect();
+                let partial = clipped.iter().filter(|(_, b)| *b != cell).count();
+                if partial > MAX_CELL {
+                    let bounds = loosened(&cell);
+                    *child = Some(Box::new(Level::new(&clipped, bounds, level + 1)));
+                }
+            }
+        }
+        grid.starts = starts;
+        grid.items = list.iter().map(|&k| items[k as usize].0).collect();
+        grid.children = children;
+        grid
+    }
+
+    fn cell_count(&self) -> usize {
+        self.children
+            .iter()
+            .map(|child| child.as_ref().map_or(1, |c| c.cell_count()))
+            .sum()
+    }
+
+    // The cell along axis `a` holding coordinate `x`, clamped to the grid.
+    fn cell_of(&self, a: usize, x: f64) -> usize {
+        if self.cell_size[a] <= 0.0 {
+            return 0;
+        }
+        let c = ((x - self.bounds.minimum[a]) / self.cell_size[a]).floor();
+        (c.max(0.0) as usize).min(self.resolution[a] - 1)
+    }
+
+    fn index(&self, cell: [usize; 3]) -> usize {
+        (cell[2] * self.resolution[1] + cell[1]) * self.resolution[0] + cell[0]
+    }
+
+    fn cell_bounds(&self, c: usize) -> Aabb {
+        let [nx, ny, _] = self.resolution;
+        let cell = [c % nx, (c / nx) % ny, c / (nx * ny)];
+        let corner = |k: [usize; 3]| {
+            Point3::of(
+                self.bounds.minimum.x() + k[0] as f64 * self.cell_size.x(),
+                self.bounds.minimum.y() + k[1] as f64 * self.cell_size.y(),
+                self.bounds.minimum.z() + k[2] as f64 * self.cell_size.z(),
+            )
+        };
+        Aabb::of(
+            corner(cell),
+            corner([cell[0] + 1, cell[1] + 1, cell[2] + 1]),
+        )
+    }
+
+    fn for_cells<F: FnMut(usize)>(&self, b: &Aabb, mut f: F) {
+        let lo = [0, 1, 2].map(|a| self.cell_of(a, b.minimum[a]));
+        let hi = [0, 1, 2].map(|a| self.cell_of(a, b.maximum[a]));
+        for z in lo[2]..=hi[2] {
+            for y in lo[1]..=hi[1] {
+                for x in lo[0]..=hi[0] {
+                    f(self.index([x, y, z]));
+                }
+            }
+        }
+    }
+
+    // Steps the ray through the cells it crosses between `t0` and `t1`.
+    fn walk<T: Hittable>(&self, w: &mut Walk<T>, t0: f64, t1: f64, rec: &mut HitRecord) {
+        let (t_enter, t_exit) = match self.bounds.clip(&w.r.origin, &w.inv_d, t0, t1) {
+            Some(range) => range,
+            None => return,
+        };
+        let start = w.r.at(t_enter);
+        let mut cell = [0, 1, 2].map(|a| self.cell_of(a, start[a]));
+        // Where the ray next crosses into another cell along each axis, and
+        // how far apart those crossings are.
+        let mut next = [f64::INFINITY; 3];
+        let mut delta = [0.0; 3];
+        let mut step = [0isize; 3];
+        for a in 0..3 {
+            let d = w.r.direction[a];
+            if self.resolution[a] == 1 || d == 0.0 {
+                continue;
+            }
+            let k = if d > 0.0 { cell[a] + 1 } else { cell[a] };
+            let edge = self.bounds.minimum[a] + k as f64 * self.cell_size[a];
+            next[a] = (edge - w.r.origin[a]) * w.inv_d[a];
+            delta[a] = self.cell_size[a] * w.inv_d[a].abs();
+            step[a] = if d > 0.0 { 1 } else { -1 };
+        }
+
+        let mut cell_enter = t_enter;
+        loop {
+            let a = if next[0] < next[1] && next[0] < next[2] {
+                0
+            } else if next[1] < next[2] {
+                1
+            } else {
+                2
+            };
+            let cell_exit = next[a].min(t_exit);
+            let c = self.index(cell);
+            match &self.children[c] {
+                Some(child) => child.walk(w, cell_enter, cell_exit, rec),
+                None => {
+                    for k in self.starts[c]..self.starts[c + 1] {
+                        let i = self.items[k as usize];
+                        if w.mailbox.contains(&i) {
+                            continue;
+                        }
+                        w.mailbox[w.next_slot] = i;
+                        w.next_slot = (w.next_slot + 1) % MAILBOX;
+                        try_object(
+                            w.objects,
+                            i,
+                            w.r,
+                            w.t_min,
+                            &mut w.closest,
+                            rec,
+                            &mut w.tests,
+                        );
+                    }
+                }
+            }
+            // Anything in later cells is further away, unless it ties.
+            if w.closest.0 < cell_exit || cell_exit >= t_exit {
+                return;
+            }
+            let moved = cell[a] as isize + step[a];
+            // The ray never crosses into another cell, which only a NaN
+            // `t_exit` keeps from being caught above.
+            if step[a] == 0 || moved < 0 || moved >= self.resolution[a] as isize {
+                return;
+            }
+            cell[a] = moved as usize;
+            cell_enter = next[a];
+            next[a] += delta[a];
+        }
+    }
+}
+
+impl<T: Hittable + Sync> Accelerator<T> for Grid<T> {
+    fn build(objects: Vec<T>) -> Grid<T> {
+        Grid::new(objects)
+    }
+
+    fn objects(&self) -> &[T] {
+        &self.objects
+    }
+
+    fn hit_counting(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        tests: &mut u64,
+    ) -> bool {
+        let mut w = Walk {
+            objects: &self.objects,
+            r,
+            inv_d: Vec3::of(
+                1.0 / r.direction.x(),
+                1.0 / r.direction.y(),
+                1.0 / r.direction.z(),
+            ),
+            t_min,
+            closest: (t_max, None),
+            mailbox: [u32::MAX; MAILBOX],
+            next_slot: 0,
+            tests: 0,
+        };
+        for &i in &self.unbounded {
+            try_object(w.objects, i, r, t_min, &mut w.closest, rec, &mut w.tests);
+        }
+        if let Some(root) = &self.root {
+            let t_max = w.closest.0;
+            root.walk(&mut w, t_min, t_max, rec);
+        }
+        *tests += w.tests;
+        w.closest.1.is_some()
+    }
+}
+
+impl<T: Hittable + Sync> Hittable for Grid<T> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.hit_counting(r, t_min, t_max, rec, &mut 0)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        match &self.root {
+            Some(root) if self.unbounded.is_empty() => {
+                *output_box = root.bounds;
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,471 @@
+// Terrain from a regular grid of heights. Each cell between four samples is
+// split into two triangles, but rather than handing those to the world one
+// by one the ray walks the grid cell by cell (a 2D DDA, as in Amanatides and
+// Woo's voxel traversal) and only tests cells whose height range it passes
+// through.
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::mesh::TriangleMesh;
+use crate::ray::Ray;
+use crate::triangle::{fill_triangle_hit, intersect_triangle};
+use crate::vec3::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// `heights` holds `nx` samples along x for each of `nz` rows along z. The
+// grid covers `size.x()` by `size.z()` from `corner`, and a sample h sits at
+// `corner.y() + h * size.y()`, so heights in [0, 1] span `size.y()`. The
+// surface faces +y, and (u, v) run from 0 to 1 along x and z.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heightfield {
+    pub nx: usize,
+    pub nz: usize,
+    pub heights: Vec<f64>,
+    pub corner: Point3,
+    pub size: Vec3,
+    pub mat_ptr: usize,
+    normals: Vec<Vec3>,
+    // Lowest and highest world y of each cell, row by row.
+    cell_range: Vec<(f64, f64)>,
+    y_range: (f64, f64),
+}
+
+impl Heightfield {
+    pub fn new(
+        nx: usize,
+        nz: usize,
+        heights: Vec<f64>,
+        corner: Point3,
+        size: Vec3,
+        mat_ptr: usize,
+    ) -> Heightfield {
+        assert!(
+            nx >= 2 && nz >= 2,
+            "heightfield needs at least 2 x 2 samples"
+        );
+        assert_eq!(heights.len(), nx * nz, "one height per grid sample");
+        let mut field = Heightfield {
+            nx,
+            nz,
+            heights,
+            corner,
+            size,
+            mat_ptr,
+            normals: Vec::new(),
+            cell_range: Vec::new(),
+            y_range: (0.0, 0.0),
+        };
+        field.normals = (0..nz)
+            .flat_map(|j| (0..nx).map(move |i| (i, j)))
+            .map(|(i, j)| field.vertex_normal(i, j))
+            .collect();
+        field.cell_range = (0..nz - 1)
+            .flat_map(|j| (0..nx - 1).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let ys = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(a, b)| field.y(i + a, j + b));
+                let lo = ys.iter().cloned().fold(f64::INFINITY, f64::min);
+                let hi = ys.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                (lo, hi)
+            })
+            .collect();
+        field.y_range = field
+            .cell_range
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &(a, b)| {
+                (lo.min(a), hi.max(b))
+            });
+        field
+    }
+
+    // Samples `height(u, v)` at `nx` by `nz` points over [0, 1] x [0, 1].
+    pub fn from_fn<F: Fn(f64, f64) -> f64>(
+        nx: usize,
+        nz: usize,
+        height: F,
+        corner: Point3,
+        size: Vec3,
+        mat_ptr: usize,
+    ) -> Heightfield {
+        let heights = (0..nz)
+            .flat_map(|j| (0..nx).map(move |i| (i, j)))
+            .map(|(i, j)| height(i as f64 / (nx - 1) as f64, j as f64 / (nz - 1) as f64))
+            .collect();
+        Heightfield::new(nx, nz, heights, corner, size, mat_ptr)
+    }
+
+    // Heights from a greyscale PGM image, black at 0 and white at 1. Image
+    // columns run along x and rows along z.
+    pub fn load_pgm(
+        path: &Path,
+        corner: Point3,
+        size: Vec3,
+        mat_ptr: usize,
+    ) -> io::Result<Heightfield> {
+        let (width, height, samples) = read_pgm(&fs::read(path)?)?;
+        if width < 2 || height < 2 {
+            return Err(invalid("heightfield image smaller than 2 x 2".to_string()));
+        }
+        Ok(Heightfield::new(
+            width, height, samples, corner, size, mat_ptr,
+        ))
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        (
+            self.size.x() / (self.nx - 1) as f64,
+            self.size.z() / (self.nz - 1) as f64,
+        )
+    }
+
+    fn y(&self, i: usize, j: usize) -> f64 {
+        self.corner.y() + self.heights[j * self.nx + i] * self.size.y()
+    }
+
+    fn point(&self, i: usize, j: usize) -> Point3 {
+        let (cx, cz) = self.cell_size();
+        Point3::of(
+            self.corner.x() + i as f64 * cx,
+            self.y(i, j),
+            self.corner.z() + j as f64 * cz,
+        )
+    }
+
+    fn uv(&self, i: usize, j: usize) -> (f64, f64) {
+        (
+            i as f64 / (self.nx - 1) as f64,
+            j as f64 / (self.nz - 1) as f64,
+        )
+    }
+
+    // Slopes from central differences, one-sided at the edges.
+    fn vertex_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.nx - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.nz - 1));
+        let (cx, cz) = self.cell_size();
+        let dx = (self.y(i1, j) - self.y(i0, j)) / ((i1 - i0) as f64 * cx);
+        let dz = (self.y(i, j1) - self.y(i, j0)) / ((j1 - j0) as f64 * cz);
+        unit_vector(&Vec3::of(-dx, 1.0, -dz))
+    }
+
+    // Grid indices of the two triangles of cell (i, j), wound to face +y.
+    fn cell_triangles(i: usize, j: usize) -> [[(usize, usize); 3]; 2] {
+        [
+            [(i, j), (i, j + 1), (i + 1, j + 1)],
+            [(i, j), (i + 1, j + 1), (i + 1, j)],
+        ]
+    }
+
+    fn hit_cell(
+        &self,
+        i: usize,
+        j: usize,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+    ) -> bool {
+        let mut closest = t_max;
+        let mut hit_anything = false;
+        for corners in Heightfield::cell_triangles(i, j).iter() {
+            let p = corners.map(|(a, b)| self.point(a, b));
+            if let Some(hit) = intersect_triangle(r, &p[0], &p[1], &p[2], t_min, closest) {
+                let normals = corners.map(|(a, b)| &self.normals[b * self.nx + a]);
+                let uv = corners.map(|(a, b)| self.uv(a, b));
+                fill_triangle_hit(
+                    r,
+                    [&p[0], &p[1], &p[2]],
+                    Some(normals),
+                    uv,
+                    hit,
+                    self.mat_ptr,
+                    rec,
+                );
+                closest = rec.t;
+                hit_anything = true;
+            }
+        }
+        hit_anything
+    }
+
+    // The same surface as an indexed mesh, for export.
+    pub fn to_mesh(&self) -> TriangleMesh {
+        let positions = (0..self.nz)
+            .flat_map(|j| (0..self.nx).map(move |i| (i, j)))
+            .map(|(i, j)| self.point(i, j))
+            .collect();
+        let uvs = (0..self.nz)
+            .flat_map(|j| (0..self.nx).map(move |i| (i, j)))
+            .map(|(i, j)| self.uv(i, j))
+            .collect();
+        let indices = (0..self.nz - 1)
+            .flat_map(|j| (0..self.nx - 1).map(move |i| (i, j)))
+            .flat_map(|(i, j)| Heightfield::cell_triangles(i, j))
+            .map(|corners| corners.map(|(a, b)| (b * self.nx + a) as u32))
+            .collect();
+        TriangleMesh::new(positions, indices, self.mat_ptr)
+            .with_normals(self.normals.clone())
+            .with_uvs(uvs)
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // Clip the ray to the field's bounding box.
+        let lo = Point3::of(self.corner.x(), self.y_range.0, self.corner.z());
+        let hi = Point3::of(
+            self.corner.x() + self.size.x(),
+            self.y_range.1,
+            self.corner.z() + self.size.z(),
+        );
+        let (mut t0, mut t1) = (t_min, t_max);
+        for a in 0..3 {
+            let inv = 1.0 / r.direction[a];
+            let (mut ta, mut tb) = ((lo[a] - r.origin[a]) * inv, (hi[a] - r.origin[a]) * inv);
+            if inv < 0.0 {
+                std::mem::swap(&mut ta, &mut tb);
+            }
+            t0 = t0.max(ta);
+            t1 = t1.min(tb);
+            if t1 < t0 {
+                return false;
+            }
+        }
+        if !t0.is_finite() {
+            return false;
+        }
+
+        // Set up the walk from the cell the ray enters by.
+        let (cx, cz) = self.cell_size();
+        let start = r.at(t0);
+        let cell = |x: f64, origin: f64, c: f64, n: usize| {
+            ((x - origin) / c).floor().clamp(0.0, (n - 2) as f64) as usize
+        };
+        let mut i = cell(start.x(), self.corner.x(), cx, self.nx);
+        let mut j = cell(start.z(), self.corner.z(), cz, self.nz);
+        // Step direction, t of the next cell boundary and t across one cell.
+        let axis = |d: f64, o: f64, edge: f64, c: f64| {
+            if d > 0.0 {
+                (1, (edge + c - o) / d, c / d)
+            } else if d < 0.0 {
+                (-1, (edge - o) / d, -c / d)
+            } else {
+                (0, f64::INFINITY, f64::INFINITY)
+            }
+        };
+        let (step_i, mut next_x, delta_x) = axis(
+            r.direction.x(),
+            r.origin.x(),
+            self.corner.x() + i as f64 * cx,
+            cx,
+        );
+        let (step_j, mut next_z, delta_z) = axis(
+            r.direction.z(),
+            r.origin.z(),
+            self.corner.z() + j as f64 * cz,
+            cz,
+        );
+
+        let slack = 1e-9 * (self.y_range.1 - self.y_range.0).abs().max(1.0);
+        let mut t_enter = t0;
+        loop {
+            let t_exit = next_x.min(next_z).min(t1);
+            let (y_lo, y_hi) = self.cell_range[j * (self.nx - 1) + i];
+            let (ya, yb) = (
+                r.origin.y() + t_enter * r.direction.y(),
+                r.origin.y() + t_exit * r.direction.y(),
+            );
+            // Hits in this cell are nearer than any in the cells after it.
+            if ya.min(yb) <= y_hi + slack
+                && ya.max(yb) >= y_lo - slack
+                && self.hit_cell(i, j, r, t_min, t_max, rec)
+            {
+                return true;
+            }
+            if t_exit >= t1 {
+                return false;
+            }
+            if next_x < next_z {
+                let next = i as i64 + step_i;
+                if next < 0 || next > (self.nx - 2) as i64 {
+                    return false;
+                }
+                i = next as usize;
+                t_enter = next_x;
+                next_x += delta_x;
+            } else {
+                let next = j as i64 + step_j;
+                if next < 0 || next > (self.nz - 2) as i64 {
+                    return false;
+                }
+                j = next as usize;
+                t_enter = next_z;
+                next_z += delta_z;
+            }
+        }
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        *output_box = Aabb::of(
+            Point3::of(self.corner.x(), self.y_range.0, self.corner.z()),
+            Point3::of(
+                self.corner.x() + self.size.x(),
+                self.y_range.1,
+                self.corner.z() + self.size.z(),
+            ),
+        );
+        true
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Width, height and samples scaled to [0, 1] of a binary (P5) or text (P2)
+// PGM image, row by row from the top.
+pub fn read_pgm(data: &[u8]) -> io::Result<(usize, usize, Vec<f64>)> {
+    // Header fields are separated by whitespace, with `#` comments to the
+    // end of the line.
+    fn field(data: &[u8], pos: &mut usize) -> io::Result<String> {
+        loop {
+            while *pos < data.len() && data[*pos].is_ascii_whitespace() {
+                *pos += 1;
+            }
+            if *pos < data.len() && data[*pos] == b'#' {
+                while *pos < data.len() && data[*pos] != b'\n' {
+                    *pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+        let start = *pos;
+        while *pos < data.len() && !data[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if start == *pos {
+            return Err(invalid("unexpected end of PGM data".to_string()));
+        }
+        Ok(String::from_utf8_lossy(&data[start..*pos]).into_owned())
+    }
+    let mut pos = 0;
+    let number = |s: String| -> io::Result<usize> {
+        s.parse()
+            .map_err(|_| invalid(format!("bad number in PGM: {}", s)))
+    };
+
+    let magic = field(data, &mut pos)?;
+    if magic != "P2" && magic != "P5" {
+        return Err(invalid(format!("not a PGM image: {}", magic)));
+    }
+    let width = number(field(data, &mut pos)?)?;
+    let height = number(field(data, &mut pos)?)?;
+    let max = number(field(data, &mut pos)?)?;
+    if max == 0 || max > 65535 {
+        return Err(invalid(format!("bad PGM maximum value: {}", max)));
+    }
+    // The header sizes are only trusted once the data is known to hold that
+    // many samples: P2 samples take a byte or more each, P5 one or two.
+    let count = width
+        .checked_mul(height)
+        .ok_or_else(|| invalid(format!("PGM too large: {} x {}", width, height)))?;
+    let mut samples = Vec::new();
+    if magic == "P2" {
+        if count > data.len() - pos {
+            return Err(invalid("PGM data is truncated".to_string()));
+        }
+        for _ in 0..count {
+            samples.push(number(field(data, &mut pos)?)? as f64 / max as f64);
+        }
+    } else {
+        // A single whitespace byte separates the header from the samples.
+        let body = &data[(pos + 1).min(data.len())..];
+        let bytes = if max < 256 { 1 } else { 2 };
+        if body.len() / bytes < count {
+            return Err(invalid("PGM data is truncated".to_string()));
+        }
+        for k in 0..count {
+            let v = if bytes == 1 {
+                body[k] as usize
+            } else {
+                (body[2 * k] as usize) << 8 | body[2 * k + 1] as usize
+            };
+            samples.push(v as f64 / max as f64);
+        }
+    }
+    Ok((width, height, samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::fbm;
+
+    #[test]
+    fn grid_walk_matches_every_triangle() {
+        let field = Heightfield::from_fn(
+            17,
+            11,
+            |u, v| fbm(&Point3::of(4.0 * u, 0.0, 4.0 * v), 4, 7),
+            Point3::of(-2.0, 0.0, -1.0),
+            Vec3::of(4.0, 1.5, 3.0),
+            0,
+        );
+        let mesh = field.to_mesh();
+        let mut state = 1u64;
+        let mut random = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        for _ in 0..2000 {
+            let origin = Point3::of(
+                6.0 * random() - 3.0,
+                3.0 * random() - 1.0,
+                6.0 * random() - 3.0,
+            );
+            let target = Point3::of(
+                4.0 * random() - 2.0,
+                1.5 * random() - 0.5,
+                3.0 * random() - 1.0,
+            );
+            let r = Ray::of(origin, target - origin);
+            let mut expected = HitRecord::default();
+            let mut found = HitRecord::default();
+            let hit = mesh.hit(&r, 0.0, f64::INFINITY, &mut expected);
+            assert_eq!(field.hit(&r, 0.0, f64::INFINITY, &mut found), hit);
+            if hit {
+                assert!((found.t - expected.t).abs() < 1e-9);
+                assert!((found.u - expected.u).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_pgm() {
+        let (w, h, samples) = read_pgm(b"P2\n# comment\n3 2\n4\n0 1 2\n3 4 0\n").unwrap();
+        assert_eq!((w, h), (3, 2));
+        assert_eq!(samples, vec![0.0, 0.25, 0.5, 0.75, 1.0, 0.0]);
+        let (w, h, samples) = read_pgm(b"P5 2 1 255\n\x00\xff").unwrap();
+        assert_eq!((w, h, samples), (2, 1, vec![0.0, 1.0]));
+    }
+
+    #[test]
+    fn rejects_pgm_sizes_the_data_cannot_hold() {
+        let huge = format!("P5 {} {} 255\n\x00", usize::MAX, 2);
+        for data in [
+            huge.as_bytes(),
+            b"P5 100000 100000 255\n\x00\xff",
+            b"P5 2 1 65535\n\x00\xff",
+            b"P2 100000 100000 255\n0 1",
+            b"P2 3 1 255\n0 1",
+        ] {
+            assert!(
+                read_pgm(data).is_err(),
+                "{:?}",
+                String::from_utf8_lossy(data)
+            );
+        }
+    }
+}
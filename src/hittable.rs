@@ -0,0 +1,102 @@
+use crate::aabb::{surrounding_box, Aabb};
+use crate::ray::Ray;
+use crate::vec3::*;
+use std::sync::Arc;
+
+#[derive(Clone, Copy)]
+pub struct HitRecord {
+    pub p: Point3,
+    pub normal: Vec3,
+    pub mat_ptr: usize,
+    pub t: f64,
+    // Surface coordinates, each usually in [0, 1].
+    pub u: f64,
+    pub v: f64,
+    // Unit direction along the surface for anisotropic shading, such as
+    // along a hair. Zero where the primitive doesn't define one.
+    pub tangent: Vec3,
+    pub front_face: bool,
+}
+
+impl Default for HitRecord {
+    fn default() -> HitRecord {
+        HitRecord {
+            p: Point3::new(),
+            normal: Vec3::new(),
+            mat_ptr: 0, // Default texture
+            t: 0.0,
+            u: 0.0,
+            v: 0.0,
+            tangent: Vec3::new(),
+            front_face: false,
+        }
+    }
+}
+
+pub trait Hittable {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
+
+    // Sets `output_box` to a box holding everything the object could be hit
+    // at between times `time0` and `time1`. False, leaving the box alone, if
+    // the object is unbounded, like a plane. Nothing moves yet, so the times
+    // only matter to anything that passes them on.
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool;
+}
+
+// Lets one object be shared between several instances, e.g. `Transformed<Arc<_>>`.
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        (**self).hit(r, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        (**self).bounding_box(time0, time1, output_box)
+    }
+}
+
+impl<T: Hittable + ?Sized> Hittable for Box<T> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        (**self).hit(r, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        (**self).bounding_box(time0, time1, output_box)
+    }
+}
+
+impl<T: Hittable> Hittable for Vec<T> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
+
+        for x in self {
+            let temp_rec = &mut HitRecord::default();
+            let hit = x.hit(r, t_min, closest_so_far, temp_rec);
+            if hit {
+                hit_anything = true;
+                closest_so_far = temp_rec.t;
+                *rec = *temp_rec;
+            }
+        }
+
+        hit_anything
+    }
+
+    // The box around all the objects; false if the list is empty or any
+    // of them is unbounded.
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let mut temp_box = Aabb::empty();
+        let mut total = Aabb::empty();
+        for x in self {
+            if !x.bounding_box(time0, time1, &mut temp_box) {
+                return false;
+            }
+            total = surrounding_box(&total, &temp_box);
+        }
+        *output_box = total;
+        true
+    }
+}
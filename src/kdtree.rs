@@ -0,0 +1,365 @@
+// kd-tree over the world, after pbrt's. Each node splits space with one
+// axis-aligned plane, chosen where the surface area heuristic says it pays
+// most, and objects straddling the plane go down both sides. Rays visit the
+// near side of each plane first and stop once the nearest hit so far lies
+// within the space already visited.
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::accel::{loosened, object_boxes, try_object, Accelerator};
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Costs of testing one object and of stepping through one node.
+const INTERSECT_COST: f64 = 80.0;
+const TRAVERSAL_COST: f64 = 1.0;
+// Discount for a split that leaves one side empty.
+const EMPTY_BONUS: f64 = 0.5;
+// Nodes this small become leaves.
+const MAX_LEAF: usize = 1;
+// Splits that cost more than not splitting, allowed on any path.
+const MAX_BAD_REFINES: usize = 3;
+// Leaves below this depth, so traversal can use a fixed stack.
+const MAX_DEPTH: usize = 60;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KdTree<T: Hittable> {
+    objects: Vec<T>,
+    nodes: Vec<KdNode>,
+    // Object indices, grouped by leaf.
+    items: Vec<u32>,
+    bounds: Aabb,
+    // Objects without a bounding box, tested against every ray.
+    unbounded: Vec<u32>,
+}
+
+// Stored depth first, the child below the plane right after its parent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct KdNode {
+    split: f64,
+    // The split axis, or `LEAF`.
+    axis: u8,
+    // For a leaf, where its objects start in `items`; otherwise the index of
+    // the child above the plane.
+    offset: u32,
+    // Objects in a leaf.
+    count: u32,
+}
+
+const LEAF: u8 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum EdgeKind {
+    // Starts sort before ends at the same place.
+    Start,
+    End,
+}
+
+#[derive(Clone, Copy)]
+struct Edge {
+    t: f64,
+    kind: EdgeKind,
+    item: u32,
+}
+
+struct Builder<'a> {
+    boxes: &'a [Aabb],
+    nodes: Vec<KdNode>,
+    items: Vec<u32>,
+}
+
+impl<T: Hittable + Sync> KdTree<T> {
+    pub fn new(objects: Vec<T>) -> KdTree<T> {
+        let mut boxes = Vec::with_capacity(objects.len());
+        let mut ids = Vec::with_capacity(objects.len());
+        let mut unbounded = Vec::new();
+        for (i, bounds) in object_boxes(&objects).into_iter().enumerate() {
+            match bounds {
+                Some(bounds) => {
+                    boxes.push(loosened(&bounds));
+                    ids.push(i as u32);
+                }
+                None => unbounded.push(i as u32),
+            }
+        }
+        let bounds = boxes
+            .iter()
+            .fold(Aabb::empty(), |b, item| surrounding_box(&b, item));
+        let mut builder = Builder {
+            boxes: &boxes,
+            nodes: Vec::new(),
+            items: Vec::new(),
+        };
+        if !boxes.is_empty() {
+            let depth = (8.0 + 1.3 * (boxes.len() as f64).log2()).round() as usize;
+            let all = (0..boxes.len() as u32).collect();
+            builder.build(all, bounds, depth.min(MAX_DEPTH), 0);
+        }
+        // Leaves hold positions in `boxes`; swap them for object indices.
+        let items = builder.items.iter().map(|&k| ids[k as usize]).collect();
+        KdTree {
+            objects,
+            nodes: builder.nodes,
+            items,
+            bounds,
+            unbounded,
+        }
+    }
+}
+
+impl<T: Hittable> KdTree<T> {
+    pub fn objects(&self) -> &[T] {
+        &self.objects
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+impl Builder<'_> {
+    fn leaf(&mut self, items: &[u32]) {
+        self.nodes.push(KdNode {
+            split: 0.0,
+            axis: LEAF,
+            offset: self.items.len() as u32,
+            count: items.len() as u32,
+        });
+        self.items.extend_from_slice(items);
+    }
+
+    fn build(&mut self, items: Vec<u32>, bounds: Aabb, depth: usize, bad_refines: usize) {
+        let n = items.len();
+        if n <= MAX_LEAF || depth == 0 {
+            return self.leaf(&items);
+        }
+
+        // Try the longest axis first, and the others only if it has no
+        // split inside the node.
+        let extent = bounds.maximum - bounds.minimum;
+        let mut axes = [0, 1, 2];
+        axes.sort_by(|&a, &b| extent[b].total_cmp(&extent[a]));
+        let area = bounds.surface_area();
+        let leaf_cost = INTERSECT_COST * n as f64;
+        let mut best: Option<(f64, usize, Vec<Edge>, usize)> = None;
+        for &axis in &axes {
+            let mut edges = Vec::with_capacity(2 * n);
+            for &item in &items {
+                let b = &self.boxes[item as usize];
+                edges.push(Edge {
+                    t: b.minimum[axis],
+                    kind: EdgeKind::Start,
+                    item,
+                });
+                edges.push(Edge {
+                    t: b.maximum[axis],
+                    kind: EdgeKind::End,
+                    item,
+                });
+            }
+            edges.sort_by(|a, b| a.t.total_cmp(&b.t).then(a.kind.cmp(&b.kind)));
+
+            let (o0, o1) = ((axis + 1) % 3, (axis + 2) % 3);
+            let (mut below, mut above) = (0, n);
+            let mut found: Option<(f64, usize)> = None;
+            for (k, edge) in edges.iter().enumerate() {
+                if edge.kind == EdgeKind::End {
+                    above -= 1;
+                }
+                let t = edge.t;
+                if t > bounds.minimum[axis] && t < bounds.maximum[axis] {
+                    // Areas of the two halves, from the split across.
+                    let cap = extent[o0] * extent[o1];
+                    let around = extent[o0] + extent[o1];
+                    let area_below = 2.0 * (cap + (t - bounds.minimum[axis]) * around);
+                    let area_above = 2.0 * (cap + (bounds.maximum[axis] - t) * around);
+                    let bonus = if below == 0 || above == 0 {
+                        EMPTY_BONUS
+                    } else {
+                        0.0
+                    };
+                    let cost = TRAVERSAL_COST
+                        + INTERSECT_COST
+                            * (1.0 - bonus)
+                            * (area_below * below as f64 + area_above * above as f64)
+                            / area;
+                    if cost < found.map_or(f64::INFINITY, |(c, _)| c) {
+                        found = Some((cost, k));
+                    }
+                }
+                if edge.kind == EdgeKind::Start {
+                    below += 1;
+                }
+            }
+            if let Some((cost, k)) = found {
+                if cost < best.as_ref().map_or(f64::INFINITY, |b| b.0) {
+                    best = Some((cost, axis, edges, k));
+                }
+                break;
+            }
+        }
+
+        let (cost, axis, edges, k) = match best {
+            Some(best) => best,
+            None => return self.leaf(&items),
+        };
+        let bad_refines = if cost > leaf_cost {
+            bad_refines + 1
+        } else {
+            bad_refines
+        };
+        if (cost > 4.0 * leaf_cost && n < 16) || bad_refines >= MAX_BAD_REFINES {
+            return self.leaf(&items);
+        }
+
+        // Anything that starts before the split edge is below it, and
+        // anything that ends after it is above.
+        let below: Vec<u32> = edges[..k]
+            .iter()
+            .filter(|e| e.kind == EdgeKind::Start)
+            .map(|e| e.item)
+            .collect();
+        let above: Vec<u32> = edges[k + 1..]
+            .iter()
+            .filter(|e| e.kind == EdgeKind::End)
+            .map(|e| e.item)
+            .collect();
+        let split = edges[k].t;
+        let max = with_component(bounds.maximum, axis, split);
+        let min = with_component(bounds.minimum, axis, split);
+        let index = self.nodes.len();
+        self.nodes.push(KdNode {
+            split,
+            axis: axis as u8,
+            offset: 0,
+            count: 0,
+        });
+        self.build(
+            below,
+            Aabb {
+                minimum: bounds.minimum,
+                maximum: max,
+            },
+            depth - 1,
+            bad_refines,
+        );
+        self.nodes[index].offset = self.nodes.len() as u32;
+        self.build(
+            above,
+            Aabb {
+                minimum: min,
+                maximum: bounds.maximum,
+            },
+            depth - 1,
+            bad_refines,
+        );
+    }
+}
+
+fn with_component(v: Vec3, axis: usize, value: f64) -> Vec3 {
+    let mut e = [v.x(), v.y(), v.z()];
+    e[axis] = value;
+    Vec3::of(e[0], e[1], e[2])
+}
+
+impl<T: Hittable + Sync> Accelerator<T> for KdTree<T> {
+    fn build(objects: Vec<T>) -> KdTree<T> {
+        KdTree::new(objects)
+    }
+
+    fn objects(&self) -> &[T] {
+        &self.objects
+    }
+
+    fn hit_counting(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        tests: &mut u64,
+    ) -> bool {
+        let objects = &self.objects;
+        let mut closest = (t_max, None);
+        for &i in &self.unbounded {
+            try_object(objects, i, r, t_min, &mut closest, rec, tests);
+        }
+        if self.nodes.is_empty() {
+            return closest.1.is_some();
+        }
+        let inv_d = Vec3::of(
+            1.0 / r.direction.x(),
+            1.0 / r.direction.y(),
+            1.0 / r.direction.z(),
+        );
+        let (mut t0, mut t1) = match self.bounds.clip(&r.origin, &inv_d, t_min, closest.0) {
+            Some(range) => range,
+            None => return closest.1.is_some(),
+        };
+
+        // Nodes still to visit, with the stretch of the ray inside each.
+        let mut stack = [(0u32, 0.0, 0.0); MAX_DEPTH + 2];
+        let mut top = 0;
+        let mut current = 0;
+        loop {
+            // Everything from here on is further away, unless it ties.
+            if closest.0 < t0 {
+                break;
+            }
+            let node = &self.nodes[current];
+            if node.axis != LEAF {
+                let axis = node.axis as usize;
+                let o = r.origin[axis];
+                let mut t_plane = (node.split - o) * inv_d[axis];
+                // A ray lying in the plane never crosses it.
+                if t_plane.is_nan() {
+                    t_plane = f64::INFINITY;
+                }
+                let below_first = o < node.split || (o == node.split && r.direction[axis] <= 0.0);
+                let (first, second) = if below_first {
+                    (current + 1, node.offset as usize)
+                } else {
+                    (node.offset as usize, current + 1)
+                };
+                if t_plane > t1 || t_plane <= 0.0 {
+                    current = first;
+                } else if t_plane < t0 {
+                    current = second;
+                } else {
+                    stack[top] = (second as u32, t_plane, t1);
+                    top += 1;
+                    current = first;
+                    t1 = t_plane;
+                }
+                continue;
+            }
+            let start = node.offset as usize;
+            for &i in &self.items[start..start + node.count as usize] {
+                try_object(objects, i, r, t_min, &mut closest, rec, tests);
+            }
+            if top == 0 {
+                break;
+            }
+            top -= 1;
+            let (next, near, far) = stack[top];
+            current = next as usize;
+            t0 = near;
+            t1 = far;
+        }
+        closest.1.is_some()
+    }
+}
+
+impl<T: Hittable + Sync> Hittable for KdTree<T> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.hit_counting(r, t_min, t_max, rec, &mut 0)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        if !self.unbounded.is_empty() || self.nodes.is_empty() {
+            return false;
+        }
+        *output_box = self.bounds;
+        true
+    }
+}
@@ -0,0 +1,38 @@
+extern crate rand;
+extern crate rayon;
+
+pub mod aabb;
+pub mod accel;
+pub mod bvh;
+pub mod camera;
+pub mod csg;
+pub mod curve;
+pub mod displace;
+pub mod generator;
+pub mod grid;
+pub mod heightfield;
+pub mod hittable;
+pub mod kdtree;
+pub mod mat4;
+pub mod material;
+pub mod mesh;
+pub mod noise;
+pub mod obj;
+pub mod object;
+pub mod patch;
+pub mod plane;
+pub mod poly;
+pub mod quad;
+pub mod quadric;
+pub mod ray;
+pub mod scene_file;
+pub mod scene_graph;
+pub mod scenes;
+pub mod sdf;
+pub mod sphere;
+pub mod subdiv;
+pub mod torus;
+pub mod transform;
+pub mod triangle;
+pub mod vec3;
+pub mod voxel;
@@ -1,271 +1,24 @@
 extern crate rand;
 extern crate rayon;
-mod vec3;
+extern crate raytracing;
 use rayon::prelude::*;
-use std::f64::consts::PI;
-use std::f64::INFINITY;
-use vec3::*;
-//use vec3::{unit_vector, Color, Point3, Vec3};
-
-struct Ray {
-    origin: Point3,
-    direction: Vec3,
-}
-
-impl Ray {
-    pub fn new() -> Ray {
-        Ray {
-            origin: Vec3::new(),
-            direction: Vec3::new(),
-        }
-    }
-    pub fn of(origin: Point3, direction: Vec3) -> Ray {
-        Ray {
-            origin: origin,
-            direction: direction,
-        }
-    }
-    pub fn at(&self, t: f64) -> Point3 {
-        &self.origin + (&self.direction * t)
-    }
-}
-
-trait MatClone {
-    fn clone_box(&self) -> Box<dyn Material>;
-}
-
-impl<T> MatClone for T
-where
-    T: 'static + Material + Clone,
-{
-    fn clone_box(&self) -> Box<dyn Material> {
-        Box::new(self.clone())
-    }
-}
-
-// We can now implement Clone manually by forwarding to clone_box.
-impl Clone for Box<dyn Material> {
-    fn clone(&self) -> Box<dyn Material> {
-        self.clone_box()
-    }
-}
-
-trait Material: MatClone {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool;
-}
-
-#[derive(Clone, Copy)]
-struct Dielectric {
-    ir: f64,
-}
-
-impl Dielectric {
-    fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
-        let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
-        r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
-    }
-}
-
-impl Material for Dielectric {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        *attenuation = Color::of(1.0, 1.0, 1.0);
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
-        };
-
-        let unit_direction = unit_vector(&r_in.direction);
-        let cos_theta = dot(&-unit_direction, &rec.normal).min(1.0);
-        let sin_theta = (1.0 - (cos_theta * cos_theta)).sqrt();
-
-        let cannot_refract = (refraction_ratio * sin_theta) > 1.0;
-        let direction: Vec3;
-
-        if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > random_f64() {
-            direction = reflect(&unit_direction, &rec.normal);
-        } else {
-            direction = refract(&unit_direction, &rec.normal, refraction_ratio);
-        }
-
-        *scattered = Ray::of(rec.p, direction);
-        true
-    }
-}
-
-#[derive(Clone, Copy)]
-struct Lambertian {
-    albedo: Color,
-}
-
-impl Material for Lambertian {
-    fn scatter(
-        &self,
-        _r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        let mut scatter_direction = rec.normal + Vec3::rand_unit_vector();
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
-        }
-        *scattered = Ray::of(rec.p, scatter_direction);
-        *attenuation = self.albedo;
-        return true;
-    }
-}
-
-#[derive(Clone, Copy)]
-struct Metal {
-    albedo: Color,
-    fuzz: f64,
-}
-
-impl Metal {
-    fn new(a: Color, f: f64) -> Metal {
-        Metal {
-            albedo: a,
-            fuzz: if f < 1.0 { f } else { 1.0 },
-        }
-    }
-}
-
-impl Material for Metal {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        let reflected = reflect(&unit_vector(&r_in.direction), &rec.normal);
-        *scattered = Ray::of(rec.p, reflected + (Vec3::rand_in_unit_sphere() * self.fuzz));
-        *attenuation = self.albedo;
-        dot(&scattered.direction, &rec.normal) > 0.0
-    }
-}
-
-#[derive(Clone, Copy)]
-enum Mat {
-    M(Metal),
-    L(Lambertian),
-    D(Dielectric),
-}
-
-#[derive(Clone, Copy)]
-struct HitRecord {
-    p: Point3,
-    normal: Vec3,
-    mat_ptr: usize,
-    t: f64,
-    front_face: bool,
-}
-
-impl HitRecord {
-    fn default() -> HitRecord {
-        HitRecord {
-            p: Point3::new(),
-            normal: Vec3::new(),
-            mat_ptr: 0, // Default texture
-            t: 0.0,
-            front_face: false,
-        }
-    }
-}
-
-trait Hittable {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
-}
-
-#[derive(Clone)]
-struct Sphere {
-    center: Point3,
-    radius: f64,
-    mat_ptr: usize,
-}
-
-impl Sphere {
-    pub fn of(cen: Point3, radius: f64, mat_ptr: usize) -> Sphere {
-        Sphere {
-            center: cen,
-            radius: radius,
-            mat_ptr: mat_ptr,
-        }
-    }
-}
-
-impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let oc = &r.origin - &self.center;
-        let a = r.direction.length_squared();
-        let half_b = dot(&oc, &r.direction);
-        let c = oc.length_squared() - self.radius * self.radius;
-
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0.0 {
-            return false;
-        }
-        let sqrtd = discriminant.sqrt();
-
-        // Find the nearest root that lies in the acceptable range.
-        let mut root = (-half_b - sqrtd) / a;
-        if (root < t_min) || (t_max < root) {
-            root = (-half_b + sqrtd) / a;
-            if (root < t_min) || (t_max < root) {
-                return false;
-            }
-        }
-        let new_p = r.at(root);
-        let outward_normal = (&new_p - &self.center) / self.radius;
-        let fface = dot(&r.direction, &outward_normal) < 0.0;
-        let new_normal = if fface {
-            outward_normal
-        } else {
-            -&outward_normal
-        };
-
-        *rec = HitRecord {
-            t: root,
-            p: new_p,
-            mat_ptr: self.mat_ptr.clone(),
-            normal: new_normal,
-            front_face: fface,
-        };
-        true
-    }
-}
-
-impl<T: Hittable> Hittable for Vec<T> {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let mut hit_anything = false;
-        let mut closest_so_far = t_max;
-
-        for x in self {
-            let temp_rec = &mut HitRecord::default();
-            let hit = x.hit(&r, t_min, closest_so_far, temp_rec);
-            if hit {
-                hit_anything = true;
-                closest_so_far = temp_rec.t;
-                *rec = temp_rec.clone();
-            }
-        }
-
-        hit_anything
-    }
-}
+use raytracing::accel::{self, Accelerator};
+use raytracing::bvh::Bvh;
+use raytracing::camera::{get_ray, Camera};
+use raytracing::generator::{generate, RandomSceneConfig};
+use raytracing::grid::Grid;
+use raytracing::hittable::{HitRecord, Hittable};
+use raytracing::kdtree::KdTree;
+use raytracing::material::{scatter_mat, Mat};
+use raytracing::object::Object;
+use raytracing::ray::Ray;
+use raytracing::scenes::Scene;
+use raytracing::vec3::*;
+use raytracing::{obj, scene_file, scenes};
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant};
 
 pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
     if x < min {
@@ -290,234 +43,115 @@ pub fn write_color(pixel_color: Color, samples_per_pixel: u32) {
     );
 }
 
-fn hit_sphere(center: Point3, radius: f64, r: Ray) -> f64 {
-    let oc = &r.origin - &center;
-    let a = r.direction.length_squared();
-    let half_b = dot(&oc, &r.direction);
-    let c = oc.length_squared() - radius * radius;
-    let discriminant = half_b * half_b - a * c;
-    if discriminant < 0.0 {
-        -1.0
-    } else {
-        (-half_b - discriminant.sqrt()) / (a)
-    }
-}
-
-fn scatter_mat(
-    m: Mat,
-    r_in: &Ray,
-    rec: &HitRecord,
-    attenuation: &mut Color,
-    scattered: &mut Ray,
-) -> bool {
-    match m {
-        Mat::M(x) => x.scatter(r_in, rec, attenuation, scattered),
-        Mat::D(x) => x.scatter(r_in, rec, attenuation, scattered),
-        Mat::L(x) => x.scatter(r_in, rec, attenuation, scattered),
-    }
-}
-
-fn ray_color<T: Hittable>(r: Ray, world: &T, depth: u32, materials: &Vec<Mat>) -> Color {
+fn ray_color<T: Hittable>(r: Ray, world: &T, depth: u32, materials: &[Mat]) -> Color {
     let rec: &mut HitRecord = &mut HitRecord::default();
 
-    if depth <= 0 {
+    if depth == 0 {
         return Color::of(0.0, 0.0, 0.0);
     }
 
-    if world.hit(&r, 0.001, INFINITY, rec) {
+    if world.hit(&r, 0.001, f64::INFINITY, rec) {
         let mut scattered = Ray::new();
         let mut attenuation = Color::new();
-        let mat = rec.clone().mat_ptr;
-        if scatter_mat(materials[mat], &r, &rec, &mut attenuation, &mut scattered) {
+        let mat = rec.mat_ptr;
+        if scatter_mat(materials[mat], &r, rec, &mut attenuation, &mut scattered) {
             return attenuation * ray_color(scattered, world, depth - 1, materials);
         }
         return Color::of(0.0, 0.0, 0.0);
     }
     let unit_direction = unit_vector(&r.direction);
     let t = 0.5 * (unit_direction.y() + 1.0);
-    return (&Color::of(1.0, 1.0, 1.0) * (1.0 - t)) + (&Color::of(0.5, 0.7, 1.0) * t);
-}
-
-fn degrees_to_radians(degrees: f64) -> f64 {
-    degrees * PI / 180.0
-}
-
-struct Camera {
-    origin: Point3,
-    lower_left_corner: Point3,
-    horizontal: Vec3,
-    vertical: Vec3,
-    u: Vec3,
-    v: Vec3,
-    w: Vec3,
-    lens_radius: f64,
-}
-
-impl Camera {
-    pub fn new(
-        lookfrom: Point3,
-        lookat: Point3,
-        vup: Vec3,
-        vfov: f64,
-        aspect_ratio: f64,
-        aperture: f64,
-        focus_dist: f64,
-    ) -> Camera {
-        let theta = degrees_to_radians(vfov);
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h;
-        let viewport_width = aspect_ratio * viewport_height;
-
-        let w = unit_vector(&(lookfrom - lookat));
-        let u = unit_vector(&cross(&vup, &w));
-        let v = cross(&w, &u);
-
-        let origin = lookfrom;
-        let horizontal = u * viewport_width * focus_dist;
-        let vertical = v * viewport_height * focus_dist;
-        let lower_left_corner =
-            &origin - (&horizontal / 2.0) - (&vertical / 2.0) - (w * focus_dist);
-
-        let lens_radius = aperture / 2.0;
-        Camera {
-            u: u,
-            v: v,
-            w: w,
-            lens_radius: lens_radius,
-            origin: lookfrom,
-            horizontal: horizontal,
-            vertical: vertical,
-            lower_left_corner: lower_left_corner,
-        }
-    }
-}
-
-fn get_ray(cam: &Camera, s: f64, t: f64) -> Ray {
-    let rd = Vec3::random_in_unit_disk() * cam.lens_radius;
-    let offset = (cam.u * rd.x()) + (cam.v * rd.y());
-    Ray {
-        origin: &cam.origin + offset,
-        direction: &cam.lower_left_corner + (&cam.horizontal * s) + (&cam.vertical * t)
-            - &cam.origin
-            - offset,
+    (Color::of(1.0, 1.0, 1.0) * (1.0 - t)) + (Color::of(0.5, 0.7, 1.0) * t)
+}
+
+// One accelerator's showing on a set of rays: the nearest hit for each, as
+// its `t` and material, and what it cost.
+struct Trial {
+    build: Duration,
+    trace: Duration,
+    tests: u64,
+    hits: Vec<Option<(f64, usize)>>,
+}
+
+fn trial<A: Accelerator<Object> + Sync>(world: &[Object], rays: &[Ray]) -> Trial {
+    let start = Instant::now();
+    let accel: A = accel::from_world(world);
+    let build = start.elapsed();
+    let start = Instant::now();
+    let traced: Vec<_> = rays
+        .par_iter()
+        .map(|r| {
+            let mut rec = HitRecord::default();
+            let mut tests = 0;
+            let hit = accel.hit_counting(r, 0.001, f64::INFINITY, &mut rec, &mut tests);
+            (
+                if hit {
+                    Some((rec.t, rec.mat_ptr))
+                } else {
+                    None
+                },
+                tests,
+            )
+        })
+        .collect();
+    Trial {
+        build,
+        trace: start.elapsed(),
+        tests: traced.iter().map(|(_, tests)| tests).sum(),
+        hits: traced.into_iter().map(|(hit, _)| hit).collect(),
     }
 }
 
-fn random_scene() -> (Vec<Sphere>, Vec<Mat>) {
-    let mut materials: Vec<Mat> = vec![];
-    let mut world = Vec::new();
-
-    let ground_material = Mat::L(Lambertian {
-        albedo: Color::of(0.5, 0.5, 0.5),
-    });
-
-    let mut p = 0 as usize;
-    materials.push(ground_material);
-    world.push(Sphere {
-        center: Vec3::of(0.0, -1000.0, 0.0),
-        mat_ptr: p, // ground_material
-        radius: 1000.0,
-    });
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_f64();
-            let center = Point3::of(
-                a as f64 + 0.9 * random_f64(),
-                0.2,
-                b as f64 + 0.9 * random_f64(),
-            );
-
-            if (&center - Point3::of(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    let albedo = Color::rand() * Color::rand();
-                    materials.push(Mat::L(Lambertian { albedo: albedo }));
-                    p += 1;
-                    world.push(Sphere {
-                        center: center,
-                        radius: 0.2,
-                        mat_ptr: p,
-                    });
-                } else if choose_mat < 0.95 {
-                    let albedo = Color::rand_range(0.5, 1.0);
-                    let fuzz = random_float(0.0, 0.5);
-                    materials.push(Mat::M(Metal::new(albedo, fuzz)));
-                    p += 1;
-                    world.push(Sphere {
-                        center: center,
-                        radius: 0.2,
-                        mat_ptr: p,
-                    })
-                } else {
-                    materials.push(Mat::D(Dielectric { ir: 1.5 }));
-                    p += 1;
-                    world.push(Sphere {
-                        center: center,
-                        radius: 0.2,
-                        mat_ptr: p,
-                    })
-                }
-            }
-        }
+// Shoots a ray through the middle of each pixel with each accelerator and
+// prints how they compare, checking the others against the BVH. The linear
+// scan is left out, being far too slow on the scenes worth comparing.
+fn compare(world: &[Object], cam: &Camera, image_width: i32, image_height: i32) {
+    let rays: Vec<Ray> = (0..image_height)
+        .flat_map(|j| {
+            (0..image_width).map(move |i| {
+                let u = (i as f64 + 0.5) / (image_width - 1) as f64;
+                let v = (j as f64 + 0.5) / (image_height - 1) as f64;
+                get_ray(cam, u, v)
+            })
+        })
+        .collect();
+    let trials = [
+        ("bvh", trial::<Bvh<Object>>(world, &rays)),
+        ("grid", trial::<Grid<Object>>(world, &rays)),
+        ("kdtree", trial::<KdTree<Object>>(world, &rays)),
+    ];
+    println!(
+        "{:<8}{:>12}{:>12}{:>12}{:>10}",
+        "accel", "build ms", "trace ms", "tests/ray", "differ"
+    );
+    for (name, t) in &trials {
+        let differ = t
+            .hits
+            .iter()
+            .zip(&trials[0].1.hits)
+            .filter(|(a, b)| a != b)
+            .count();
+        println!(
+            "{:<8}{:>12.1}{:>12.1}{:>12.2}{:>10}",
+            name,
+            t.build.as_secs_f64() * 1000.0,
+            t.trace.as_secs_f64() * 1000.0,
+            t.tests as f64 / rays.len() as f64,
+            differ
+        );
     }
-
-    materials.push(Mat::D(Dielectric { ir: 1.5 }));
-    p += 1;
-    world.push(Sphere {
-        center: Point3::of(0.0, 1.0, 0.0),
-        radius: 1.0,
-        mat_ptr: p,
-    });
-
-    materials.push(Mat::L(Lambertian {
-        albedo: Color::of(0.4, 0.2, 0.1),
-    }));
-    p += 1;
-    world.push(Sphere {
-        center: Point3::of(-4.0, 1.0, 0.0),
-        radius: 1.0,
-        mat_ptr: p,
-    });
-
-    materials.push(Mat::M(Metal::new(Color::of(0.7, 0.6, 0.5), 0.0)));
-    p += 1;
-    world.push(Sphere {
-        center: Point3::of(4.0, 1.0, 0.0),
-        radius: 1.0,
-        mat_ptr: p,
-    });
-
-    (world, materials)
 }
 
-fn main() {
-    // Image
-    let aspect_ratio = 16.0 / 9.0;
-    let image_width = 800;
-    let image_height = (image_width as f64 / aspect_ratio) as i32;
-    let samples_per_pixel = 50;
+fn render<A: Accelerator<Object> + Sync>(
+    scene: &Scene,
+    cam: &Camera,
+    image_width: i32,
+    image_height: i32,
+    samples_per_pixel: u32,
+) {
+    let world: A = accel::from_world(&scene.world);
     let max_depth = 50;
 
-    // Camera
-    let lookfrom = Point3::of(13.0, 2.0, 3.0);
-    let lookat = Point3::of(0.0, 0.0, 0.0);
-    let vup = Vec3::of(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
-    let aperture = 0.1;
-    let fov = 20.0;
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        fov,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-    );
-
-    // World
-    let (world, materials) = random_scene();
-
     println!("P3\n{} {}\n255", image_width, image_height);
 
     for j1 in 0..image_height {
@@ -531,14 +165,14 @@ fn main() {
                     .map(|_| {
                         let u = (i as f64 + random_f64()) / (image_width - 1) as f64;
                         let v = (j as f64 + random_f64()) / (image_height - 1) as f64;
-                        let r = get_ray(&cam, u, v);
-                        ray_color(r, &world, max_depth, &materials)
+                        let r = get_ray(cam, u, v);
+                        ray_color(r, &world, max_depth, &scene.materials)
                     })
                     .sum();
                 (i, pixel_color)
             })
             .collect();
-        colors.sort_by(|(x, _), (y, _)| x.cmp(y));
+        colors.sort_by_key(|(x, _)| *x);
         //eprintln!("{:?}", colors);
         for (_, pixel_color) in colors {
             write_color(pixel_color, samples_per_pixel);
@@ -546,3 +180,118 @@ fn main() {
     }
     eprintln!("Done.\n");
 }
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: raytracing [--scene NAME | --generator SPEC | --load FILE] [--seed N] [--export FILE] [--width N] [--samples N] [--accel bvh|grid|kdtree|linear] [--compare] [--list]"
+    );
+    process::exit(2);
+}
+
+fn fail(msg: String) -> ! {
+    eprintln!("{}", msg);
+    process::exit(2);
+}
+
+fn parse_arg<T: std::str::FromStr>(arg: Option<String>) -> T {
+    match arg.map(|s| s.parse()) {
+        Some(Ok(n)) => n,
+        _ => usage(),
+    }
+}
+
+fn main() {
+    // Options
+    let mut scene_name = String::from("random");
+    let mut generator_spec: Option<String> = None;
+    let mut load_path: Option<PathBuf> = None;
+    let mut export_path: Option<PathBuf> = None;
+    let mut seed: u64 = rand::random();
+    let mut image_width: i32 = 800;
+    let mut samples_per_pixel: u32 = 50;
+    let mut accel_name = String::from("bvh");
+    let mut comparing = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scene" => scene_name = args.next().unwrap_or_else(|| usage()),
+            "--generator" => generator_spec = Some(args.next().unwrap_or_else(|| usage())),
+            "--load" => load_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--export" => export_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--seed" => seed = parse_arg(args.next()),
+            "--width" => image_width = parse_arg(args.next()),
+            "--samples" => samples_per_pixel = parse_arg(args.next()),
+            "--accel" => accel_name = args.next().unwrap_or_else(|| usage()),
+            "--compare" => comparing = true,
+            "--list" => {
+                for entry in scenes::CATALOGUE {
+                    println!("{:<16}{}", entry.name, entry.description);
+                }
+                return;
+            }
+            _ => usage(),
+        }
+    }
+    // World
+    let scene = if let Some(path) = load_path {
+        eprintln!("Scene {}", path.display());
+        let result = match path.extension().and_then(|e| e.to_str()) {
+            Some("obj") => obj::load_obj(&path),
+            _ => scene_file::load_scene(&path),
+        };
+        result.unwrap_or_else(|e| fail(format!("{}: {}", path.display(), e)))
+    } else if let Some(spec) = generator_spec {
+        let config = RandomSceneConfig {
+            seed,
+            ..RandomSceneConfig::default()
+        }
+        .parse(&spec)
+        .unwrap_or_else(|e| fail(e));
+        eprintln!("Generated scene (seed {})", config.seed);
+        generate(&config)
+    } else {
+        let entry = scenes::find(&scene_name)
+            .unwrap_or_else(|| fail(format!("unknown scene '{}', try --list", scene_name)));
+        eprintln!("Scene {} (seed {})", entry.name, seed);
+        entry.build(seed)
+    };
+
+    if let Some(path) = export_path {
+        let result = match path.extension().and_then(|e| e.to_str()) {
+            Some("obj") => obj::save_obj(&scene, &path),
+            _ => scene_file::save_scene(&scene, &path),
+        };
+        result.unwrap_or_else(|e| fail(format!("{}: {}", path.display(), e)));
+        eprintln!("Exported to {}", path.display());
+        return;
+    }
+    let view = scene.view;
+    let render = match accel_name.as_str() {
+        "bvh" => render::<Bvh<Object>>,
+        "grid" => render::<Grid<Object>>,
+        "kdtree" => render::<KdTree<Object>>,
+        "linear" => render::<Vec<Object>>,
+        _ => fail(format!("unknown accelerator '{}'", accel_name)),
+    };
+
+    // Image
+    let aspect_ratio = view.aspect_ratio;
+    let image_height = (image_width as f64 / aspect_ratio) as i32;
+
+    // Camera
+    let cam = Camera::new(
+        view.lookfrom,
+        view.lookat,
+        view.vup,
+        view.vfov,
+        aspect_ratio,
+        view.aperture,
+        view.focus_dist,
+    );
+
+    if comparing {
+        compare(&scene.world, &cam, image_width, image_height);
+        return;
+    }
+    render(&scene, &cam, image_width, image_height, samples_per_pixel);
+}
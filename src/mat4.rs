@@ -0,0 +1,138 @@
+use crate::camera::degrees_to_radians;
+use crate::vec3::*;
+use std::ops::Mul;
+
+// Row-major 4x4 matrix acting on column vectors, so `a * b` applies `b` first.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Mat4 {
+        Mat4::identity()
+    }
+}
+
+impl Mat4 {
+    pub fn identity() -> Mat4 {
+        Mat4::scaling(Vec3::of(1.0, 1.0, 1.0))
+    }
+
+    pub fn translation(offset: Vec3) -> Mat4 {
+        Mat4 {
+            m: [
+                [1.0, 0.0, 0.0, offset.x()],
+                [0.0, 1.0, 0.0, offset.y()],
+                [0.0, 0.0, 1.0, offset.z()],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn scaling(s: Vec3) -> Mat4 {
+        Mat4 {
+            m: [
+                [s.x(), 0.0, 0.0, 0.0],
+                [0.0, s.y(), 0.0, 0.0],
+                [0.0, 0.0, s.z(), 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    // Counter-clockwise rotation about `axis` when looking down it towards the origin.
+    pub fn rotation(axis: Vec3, degrees: f64) -> Mat4 {
+        let a = unit_vector(&axis);
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let theta = degrees_to_radians(degrees);
+        let (s, c) = theta.sin_cos();
+        let t = 1.0 - c;
+        Mat4 {
+            m: [
+                [t * x * x + c, t * x * y - s * z, t * x * z + s * y, 0.0],
+                [t * x * y + s * z, t * y * y + c, t * y * z - s * x, 0.0],
+                [t * x * z - s * y, t * y * z + s * x, t * z * z + c, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut out = Mat4 { m: [[0.0; 4]; 4] };
+        for (i, row) in self.m.iter().enumerate() {
+            for (j, x) in row.iter().enumerate() {
+                out.m[j][i] = *x;
+            }
+        }
+        out
+    }
+
+    // Gauss-Jordan elimination with partial pivoting. `None` if singular.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap_or(col);
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let d = a[col][col];
+            for j in 0..4 {
+                a[col][j] /= d;
+                inv[col][j] /= d;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let f = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= f * a[col][j];
+                        inv[row][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Mat4 { m: inv })
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3];
+        let y = m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3];
+        let z = m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3];
+        let w = m[3][0] * p.x() + m[3][1] * p.y() + m[3][2] * p.z() + m[3][3];
+        if w == 1.0 {
+            Point3::of(x, y, z)
+        } else {
+            Point3::of(x, y, z) / w
+        }
+    }
+
+    // Ignores the translation column.
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::of(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut out = Mat4 { m: [[0.0; 4]; 4] };
+        for i in 0..4 {
+            for j in 0..4 {
+                out.m[i][j] = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,153 @@
+use crate::hittable::HitRecord;
+use crate::ray::Ray;
+use crate::vec3::*;
+
+pub trait MatClone {
+    fn clone_box(&self) -> Box<dyn Material>;
+}
+
+impl<T> MatClone for T
+where
+    T: 'static + Material + Clone,
+{
+    fn clone_box(&self) -> Box<dyn Material> {
+        Box::new(self.clone())
+    }
+}
+
+// We can now implement Clone manually by forwarding to clone_box.
+impl Clone for Box<dyn Material> {
+    fn clone(&self) -> Box<dyn Material> {
+        self.clone_box()
+    }
+}
+
+pub trait Material: MatClone {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dielectric {
+    pub ir: f64,
+}
+
+impl Dielectric {
+    fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
+        let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
+        r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        *attenuation = Color::of(1.0, 1.0, 1.0);
+        let refraction_ratio = if rec.front_face {
+            1.0 / self.ir
+        } else {
+            self.ir
+        };
+
+        let unit_direction = unit_vector(&r_in.direction);
+        let cos_theta = dot(&-unit_direction, &rec.normal).min(1.0);
+        let sin_theta = (1.0 - (cos_theta * cos_theta)).sqrt();
+
+        let cannot_refract = (refraction_ratio * sin_theta) > 1.0;
+
+        let direction = if cannot_refract
+            || Dielectric::reflectance(cos_theta, refraction_ratio) > random_f64()
+        {
+            reflect(&unit_direction, &rec.normal)
+        } else {
+            refract(&unit_direction, &rec.normal, refraction_ratio)
+        };
+
+        *scattered = Ray::of(rec.p, direction);
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lambertian {
+    pub albedo: Color,
+}
+
+impl Material for Lambertian {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let mut scatter_direction = rec.normal + Vec3::rand_unit_vector();
+        if scatter_direction.near_zero() {
+            scatter_direction = rec.normal;
+        }
+        *scattered = Ray::of(rec.p, scatter_direction);
+        *attenuation = self.albedo;
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metal {
+    pub albedo: Color,
+    pub fuzz: f64,
+}
+
+impl Metal {
+    pub fn new(a: Color, f: f64) -> Metal {
+        Metal {
+            albedo: a,
+            fuzz: if f < 1.0 { f } else { 1.0 },
+        }
+    }
+}
+
+impl Material for Metal {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let reflected = reflect(&unit_vector(&r_in.direction), &rec.normal);
+        *scattered = Ray::of(rec.p, reflected + (Vec3::rand_in_unit_sphere() * self.fuzz));
+        *attenuation = self.albedo;
+        dot(&scattered.direction, &rec.normal) > 0.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mat {
+    M(Metal),
+    L(Lambertian),
+    D(Dielectric),
+}
+
+pub fn scatter_mat(
+    m: Mat,
+    r_in: &Ray,
+    rec: &HitRecord,
+    attenuation: &mut Color,
+    scattered: &mut Ray,
+) -> bool {
+    match m {
+        Mat::M(x) => x.scatter(r_in, rec, attenuation, scattered),
+        Mat::D(x) => x.scatter(r_in, rec, attenuation, scattered),
+        Mat::L(x) => x.scatter(r_in, rec, attenuation, scattered),
+    }
+}
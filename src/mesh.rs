@@ -0,0 +1,351 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::triangle::{fill_triangle_hit, intersect_triangle};
+use crate::vec3::*;
+use std::sync::Arc;
+
+// Indexed triangle mesh. Vertex attributes live in shared buffers and each
+// face is three `u32` indices into them. `normals` and `uvs` are either empty
+// or hold one entry per position. Faces pick a slot in the mesh's own
+// material table through `face_materials` (empty means slot 0 everywhere),
+// and the table maps slots onto the scene's `mat_ptr` indices.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub indices: Vec<[u32; 3]>,
+    pub face_materials: Vec<u32>,
+    pub materials: Vec<usize>,
+}
+
+// The constructors assert that their buffers agree, as they are meant for
+// meshes built in code where a mismatch is a bug. Readers of scene and OBJ
+// files check what they read first and report it as an error instead.
+impl TriangleMesh {
+    pub fn new(positions: Vec<Point3>, indices: Vec<[u32; 3]>, mat_ptr: usize) -> TriangleMesh {
+        assert!(
+            indices
+                .iter()
+                .flatten()
+                .all(|&i| (i as usize) < positions.len()),
+            "mesh index out of range"
+        );
+        TriangleMesh {
+            positions,
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices,
+            face_materials: Vec::new(),
+            materials: vec![mat_ptr],
+        }
+    }
+
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> TriangleMesh {
+        assert_eq!(normals.len(), self.positions.len(), "one normal per vertex");
+        self.normals = normals;
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<(f64, f64)>) -> TriangleMesh {
+        assert_eq!(uvs.len(), self.positions.len(), "one uv per vertex");
+        self.uvs = uvs;
+        self
+    }
+
+    // `ids` holds a slot in `table` for every face.
+    pub fn with_face_materials(mut self, ids: Vec<u32>, table: Vec<usize>) -> TriangleMesh {
+        assert_eq!(ids.len(), self.indices.len(), "one material id per face");
+        assert!(
+            ids.iter().all(|&i| (i as usize) < table.len()),
+            "material id out of range"
+        );
+        self.face_materials = ids;
+        self.materials = table;
+        self
+    }
+
+    // Area weighted vertex normals for smooth shading.
+    pub fn with_smooth_normals(self) -> TriangleMesh {
+        let mut normals = vec![Vec3::new(); self.positions.len()];
+        for face in &self.indices {
+            let [a, b, c] = self.vertices(face);
+            // The cross product's length is twice the face area.
+            let n = cross(&(b - a), &(c - a));
+            for &i in face {
+                normals[i as usize] = normals[i as usize] + n;
+            }
+        }
+        let normals = normals
+            .iter()
+            .map(|n| {
+                if n.length_squared() > 0.0 {
+                    unit_vector(n)
+                } else {
+                    *n
+                }
+            })
+            .collect();
+        self.with_normals(normals)
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn vertices(&self, face: &[u32; 3]) -> [Point3; 3] {
+        [
+            self.positions[face[0] as usize],
+            self.positions[face[1] as usize],
+            self.positions[face[2] as usize],
+        ]
+    }
+
+    pub fn mat_ptr(&self, face: usize) -> usize {
+        match self.face_materials.get(face) {
+            Some(&slot) => self.materials[slot as usize],
+            None => self.materials[0],
+        }
+    }
+
+    pub fn hit_face(
+        &self,
+        face: usize,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+    ) -> bool {
+        let [i0, i1, i2] = self.indices[face];
+        let (i0, i1, i2) = (i0 as usize, i1 as usize, i2 as usize);
+        let p = [
+            &self.positions[i0],
+            &self.positions[i1],
+            &self.positions[i2],
+        ];
+        let hit = match intersect_triangle(r, p[0], p[1], p[2], t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let normals = if self.normals.is_empty() {
+            None
+        } else {
+            Some([&self.normals[i0], &self.normals[i1], &self.normals[i2]])
+        };
+        let uv = if self.uvs.is_empty() {
+            [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]
+        } else {
+            [self.uvs[i0], self.uvs[i1], self.uvs[i2]]
+        };
+        fill_triangle_hit(r, p, normals, uv, hit, self.mat_ptr(face), rec);
+        true
+    }
+
+    // One lightweight reference per face, for handing the faces out as
+    // separate primitives.
+    pub fn triangles(mesh: &Arc<TriangleMesh>) -> Vec<MeshTriangle> {
+        (0..mesh.len() as u32)
+            .map(|face| MeshTriangle {
+                mesh: Arc::clone(mesh),
+                face,
+            })
+            .collect()
+    }
+
+    // Icosahedron with each face split `subdivisions` times and pushed out
+    // onto the unit sphere.
+    pub fn icosphere(subdivisions: u32, mat_ptr: usize) -> TriangleMesh {
+        let t = (1.0 + 5f64.sqrt()) / 2.0;
+        let mut positions: Vec<Point3> = [
+            (-1.0, t, 0.0),
+            (1.0, t, 0.0),
+            (-1.0, -t, 0.0),
+            (1.0, -t, 0.0),
+            (0.0, -1.0, t),
+            (0.0, 1.0, t),
+            (0.0, -1.0, -t),
+            (0.0, 1.0, -t),
+            (t, 0.0, -1.0),
+            (t, 0.0, 1.0),
+            (-t, 0.0, -1.0),
+            (-t, 0.0, 1.0),
+        ]
+        .iter()
+        .map(|&(x, y, z)| unit_vector(&Point3::of(x, y, z)))
+        .collect();
+        let mut indices: Vec<[u32; 3]> = vec![
+            [0, 11, 5],
+            [0, 5, 1],
+            [0, 1, 7],
+            [0, 7, 10],
+            [0, 10, 11],
+            [1, 5, 9],
+            [5, 11, 4],
+            [11, 10, 2],
+            [10, 7, 6],
+            [7, 1, 8],
+            [3, 9, 4],
+            [3, 4, 2],
+            [3, 2, 6],
+            [3, 6, 8],
+            [3, 8, 9],
+            [4, 9, 5],
+            [2, 4, 11],
+            [6, 2, 10],
+            [8, 6, 7],
+            [9, 8, 1],
+        ];
+
+        for _ in 0..subdivisions {
+            let mut midpoints = std::collections::HashMap::new();
+            let mut midpoint = |a: u32, b: u32| -> u32 {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    let m = (positions[a as usize] + positions[b as usize]) / 2.0;
+                    positions.push(unit_vector(&m));
+                    (positions.len() - 1) as u32
+                })
+            };
+            indices = indices
+                .iter()
+                .flat_map(|&[a, b, c]| {
+                    let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                    vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+                })
+                .collect();
+        }
+        TriangleMesh::new(positions, indices, mat_ptr)
+    }
+}
+
+// Tests the faces one after another; wrap the mesh in an acceleration
+// structure for anything large.
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
+        for face in 0..self.len() {
+            if self.hit_face(face, r, t_min, closest_so_far, rec) {
+                hit_anything = true;
+                closest_so_far = rec.t;
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        *output_box = Aabb::from_points(&self.positions);
+        true
+    }
+}
+
+// One face of a shared mesh.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshTriangle {
+    pub mesh: Arc<TriangleMesh>,
+    pub face: u32,
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.mesh.hit_face(self.face as usize, r, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        let face = self.mesh.indices[self.face as usize];
+        *output_box = Aabb::from_points(face.iter().map(|&i| &self.mesh.positions[i as usize]));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One face in z = 0 and another below it, one above the other.
+    fn stack() -> TriangleMesh {
+        let positions = vec![
+            Point3::of(0.0, 0.0, 0.0),
+            Point3::of(1.0, 0.0, 0.0),
+            Point3::of(0.0, 1.0, 0.0),
+            Point3::of(0.0, 0.0, -1.0),
+            Point3::of(1.0, 0.0, -1.0),
+            Point3::of(0.0, 1.0, -1.0),
+        ];
+        TriangleMesh::new(positions, vec![[0, 1, 2], [3, 4, 5]], 7)
+    }
+
+    fn hit<S: Hittable>(shape: &S, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        let r = Ray::of(origin, direction);
+        shape.hit(&r, 0.001, f64::INFINITY, &mut rec).then_some(rec)
+    }
+
+    #[test]
+    fn faces_look_up_materials_through_the_table() {
+        let plain = stack();
+        assert_eq!((plain.mat_ptr(0), plain.mat_ptr(1)), (7, 7));
+
+        let mesh = Arc::new(stack().with_face_materials(vec![1, 0], vec![5, 6]));
+        assert_eq!((mesh.mat_ptr(0), mesh.mat_ptr(1)), (6, 5));
+        let (above, down) = (Point3::of(0.25, 0.25, 1.0), Vec3::of(0.0, 0.0, -1.0));
+        assert_eq!(hit(&*mesh, above, down).unwrap().mat_ptr, 6);
+        let faces = TriangleMesh::triangles(&mesh);
+        assert_eq!(faces.len(), 2);
+        assert_eq!(hit(&faces[1], above, down).unwrap().mat_ptr, 5);
+    }
+
+    #[test]
+    fn normals_and_uvs_are_interpolated_across_a_face() {
+        let normals = vec![
+            Vec3::of(0.0, 0.0, 1.0),
+            unit_vector(&Vec3::of(1.0, 0.0, 1.0)),
+            unit_vector(&Vec3::of(0.0, 1.0, 1.0)),
+        ];
+        let uvs = vec![(0.2, 0.4), (0.6, 0.4), (0.2, 0.9)];
+        let positions = stack().positions[..3].to_vec();
+        let mesh = TriangleMesh::new(positions, vec![[0, 1, 2]], 0)
+            .with_normals(normals.clone())
+            .with_uvs(uvs);
+        let face = &TriangleMesh::triangles(&Arc::new(mesh))[0];
+
+        // Weights 0.5, 0.25 and 0.25 on the three corners.
+        let rec = hit(face, Point3::of(0.25, 0.25, 1.0), Vec3::of(0.0, 0.0, -1.0)).unwrap();
+        let expected = unit_vector(&(normals[0] * 0.5 + normals[1] * 0.25 + normals[2] * 0.25));
+        assert!((rec.normal - expected).length() < 1e-12);
+        assert!((rec.u - 0.3).abs() < 1e-12 && (rec.v - 0.525).abs() < 1e-12);
+        assert!(rec.front_face);
+
+        // From below the shading normal turns to face the ray too.
+        let rec = hit(face, Point3::of(0.25, 0.25, -1.0), Vec3::of(0.0, 0.0, 1.0)).unwrap();
+        assert!((rec.normal + expected).length() < 1e-12);
+        assert!(!rec.front_face);
+    }
+
+    #[test]
+    fn icospheres_split_every_face_in_four() {
+        for subdivisions in 0..4 {
+            let sphere = TriangleMesh::icosphere(subdivisions, 0);
+            let split = 4usize.pow(subdivisions);
+            assert_eq!(sphere.len(), 20 * split);
+            assert_eq!(sphere.positions.len(), 10 * split + 2);
+            assert!(sphere
+                .positions
+                .iter()
+                .all(|p| (p.length() - 1.0).abs() < 1e-12));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "one uv per vertex")]
+    fn mismatched_buffers_are_a_bug() {
+        stack().with_uvs(vec![(0.0, 0.0)]);
+    }
+}
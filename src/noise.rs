@@ -0,0 +1,81 @@
+// Deterministic gradient noise for procedural terrain and displacement.
+// Perlin's improved noise, with the permutation table replaced by an integer
+// hash so that different seeds give unrelated patterns without a table each.
+
+use crate::vec3::*;
+
+fn hash(x: i64, y: i64, z: i64, seed: u64) -> u64 {
+    let mut h = seed ^ 0x9e37_79b9_7f4a_7c15;
+    for k in [x, y, z] {
+        h ^= k as u64;
+        h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        h ^= h >> 33;
+    }
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+// Dot product with one of Perlin's twelve edge gradients.
+fn grad(h: u64, x: f64, y: f64, z: f64) -> f64 {
+    match h % 12 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+// Smooth noise in roughly [-1, 1], zero at integer lattice points.
+pub fn perlin(p: &Point3, seed: u64) -> f64 {
+    let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+    let (x, y, z) = (p.x() - fx, p.y() - fy, p.z() - fz);
+    let (ix, iy, iz) = (fx as i64, fy as i64, fz as i64);
+    let corner = |dx: i64, dy: i64, dz: i64| {
+        let h = hash(ix + dx, iy + dy, iz + dz, seed);
+        grad(h, x - dx as f64, y - dy as f64, z - dz as f64)
+    };
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, corner(0, 0, 0), corner(1, 0, 0)),
+            lerp(u, corner(0, 1, 0), corner(1, 1, 0)),
+        ),
+        lerp(
+            v,
+            lerp(u, corner(0, 0, 1), corner(1, 0, 1)),
+            lerp(u, corner(0, 1, 1), corner(1, 1, 1)),
+        ),
+    )
+}
+
+// Fractal sum of `octaves` layers of noise, each at twice the frequency and
+// half the amplitude of the last.
+pub fn fbm(p: &Point3, octaves: u32, seed: u64) -> f64 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut q = *p;
+    for octave in 0..octaves {
+        sum += amplitude * perlin(&q, seed.wrapping_add(octave as u64));
+        amplitude *= 0.5;
+        q = q * 2.0;
+    }
+    sum
+}
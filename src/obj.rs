@@ -0,0 +1,809 @@
+// Wavefront OBJ + MTL export for use in other tools. Curved primitives are
+// tessellated (spheres into latitude/longitude meshes, quadrics and tori into
+// grids over their surface parameters) and transforms are baked into the
+// vertices, so unlike the scene format this is lossy. Reading a file back
+// gives one mesh per `o` group.
+
+use crate::csg::CsgOp;
+use crate::mat4::Mat4;
+use crate::material::*;
+use crate::mesh::TriangleMesh;
+use crate::object::Object;
+use crate::plane::Plane;
+use crate::quad::Quad;
+use crate::quadric::Surface;
+use crate::scene_file::{invalid, Tokens};
+use crate::scenes::{Scene, View};
+use crate::sphere::Sphere;
+use crate::vec3::*;
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::PI;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+pub const SPHERE_SEGMENTS: usize = 32;
+pub const SPHERE_RINGS: usize = 16;
+// Half the side of the square an infinite plane is cut down to.
+pub const PLANE_EXTENT: f64 = 1000.0;
+
+pub fn write_mtl<W: Write>(scene: &Scene, out: &mut W) -> io::Result<()> {
+    for (i, m) in scene.materials.iter().enumerate() {
+        writeln!(out, "newmtl mat{}", i)?;
+        match m {
+            Mat::L(l) => {
+                writeln!(out, "Kd {}", l.albedo)?;
+                writeln!(out, "illum 1")?;
+            }
+            Mat::M(m) => {
+                writeln!(out, "Kd 0 0 0")?;
+                writeln!(out, "Ks {}", m.albedo)?;
+                // OBJ has no fuzz; map it onto the specular exponent.
+                writeln!(out, "Ns {}", 1000.0 * (1.0 - m.fuzz))?;
+                writeln!(out, "illum 3")?;
+            }
+            Mat::D(d) => {
+                writeln!(out, "Kd 0 0 0")?;
+                writeln!(out, "Ks 1 1 1")?;
+                writeln!(out, "Tf 1 1 1")?;
+                writeln!(out, "Ni {}", d.ir)?;
+                writeln!(out, "illum 7")?;
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+struct ObjWriter<'a, W: Write> {
+    out: &'a mut W,
+    // OBJ indices are 1-based and global across the file.
+    vertices: usize,
+    objects: usize,
+}
+
+// Sign of the determinant of the linear part; negative means a mirror.
+fn handedness(m: &Mat4) -> f64 {
+    let m = &m.m;
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+    det.signum()
+}
+
+// Position, normal and texture coordinate of one exported vertex.
+type Vertex = (Point3, Vec3, (f64, f64));
+
+impl<'a, W: Write> ObjWriter<'a, W> {
+    // `material` is an override from an enclosing instance; outer ones win,
+    // as they do when hitting.
+    fn object(
+        &mut self,
+        object: &Object,
+        to_world: &Mat4,
+        material: Option<usize>,
+    ) -> io::Result<()> {
+        match object {
+            Object::Sphere(s) => {
+                let (vertices, faces) = tessellate_sphere(s);
+                self.mesh(
+                    "sphere",
+                    material.unwrap_or(s.mat_ptr),
+                    to_world,
+                    &vertices,
+                    &faces,
+                )
+            }
+            Object::Triangle(tri) => {
+                let geometric = cross(&(tri.p[1] - tri.p[0]), &(tri.p[2] - tri.p[0]));
+                let vertices: Vec<Vertex> = (0..3)
+                    .map(|i| {
+                        let n = tri.normals.map_or(geometric, |n| n[i]);
+                        (tri.p[i], n, tri.uv[i])
+                    })
+                    .collect();
+                let mat_ptr = material.unwrap_or(tri.mat_ptr);
+                self.mesh("triangle", mat_ptr, to_world, &vertices, &[vec![0, 1, 2]])
+            }
+            Object::Plane(plane) => {
+                let mat_ptr = material.unwrap_or(plane.mat_ptr);
+                let vertices = plane_vertices(plane);
+                self.mesh("plane", mat_ptr, to_world, &vertices, &[vec![0, 1, 2, 3]])
+            }
+            Object::Quad(q) => {
+                let mat_ptr = material.unwrap_or(q.mat_ptr);
+                self.mesh(
+                    "quad",
+                    mat_ptr,
+                    to_world,
+                    &quad_vertices(q),
+                    &[vec![0, 1, 2, 3]],
+                )
+            }
+            Object::Rect(rect) => {
+                let q = rect.to_quad();
+                let mat_ptr = material.unwrap_or(q.mat_ptr);
+                self.mesh(
+                    "rect",
+                    mat_ptr,
+                    to_world,
+                    &quad_vertices(&q),
+                    &[vec![0, 1, 2, 3]],
+                )
+            }
+            Object::Cuboid(b) => {
+                let vertices: Vec<Vertex> = b.sides.iter().flat_map(quad_vertices).collect();
+                let faces: Vec<Vec<usize>> = (0..6).map(|i| (4 * i..4 * i + 4).collect()).collect();
+                let mat_ptr = material.unwrap_or(b.mat_ptr());
+                self.mesh("box", mat_ptr, to_world, &vertices, &faces)
+            }
+            Object::Cylinder(c) => {
+                let mat_ptr = material.unwrap_or(c.mat_ptr);
+                let (vertices, faces) = tessellate_surface(c);
+                self.mesh("cylinder", mat_ptr, to_world, &vertices, &faces)?;
+                if c.capped {
+                    for cap in &c.caps() {
+                        let (vertices, faces) = tessellate_surface(cap);
+                        self.mesh("disk", mat_ptr, to_world, &vertices, &faces)?;
+                    }
+                }
+                Ok(())
+            }
+            Object::Cone(c) => {
+                let (vertices, faces) = tessellate_surface(c);
+                let mat_ptr = material.unwrap_or(c.mat_ptr);
+                self.mesh("cone", mat_ptr, to_world, &vertices, &faces)
+            }
+            Object::Disk(d) => {
+                let (vertices, faces) = tessellate_surface(d);
+                let mat_ptr = material.unwrap_or(d.mat_ptr);
+                self.mesh("disk", mat_ptr, to_world, &vertices, &faces)
+            }
+            Object::Paraboloid(p) => {
+                let (vertices, faces) = tessellate_surface(p);
+                let mat_ptr = material.unwrap_or(p.mat_ptr);
+                self.mesh("paraboloid", mat_ptr, to_world, &vertices, &faces)
+            }
+            Object::Hyperboloid(h) => {
+                let (vertices, faces) = tessellate_surface(h);
+                let mat_ptr = material.unwrap_or(h.mat_ptr);
+                self.mesh("hyperboloid", mat_ptr, to_world, &vertices, &faces)
+            }
+            Object::Torus(x) => {
+                let (vertices, faces) = tessellate_surface(x);
+                let mat_ptr = material.unwrap_or(x.mat_ptr);
+                self.mesh("torus", mat_ptr, to_world, &vertices, &faces)
+            }
+            Object::Sdf(_) => writeln!(self.out, "# distance field left out"),
+            Object::Curve(x) => {
+                let mesh = x.tube_mesh(SPHERE_SEGMENTS, 8);
+                let faces: Vec<usize> = (0..mesh.len()).collect();
+                let (vertices, faces) = mesh_faces(&mesh, &faces);
+                let mat_ptr = material.unwrap_or(x.mat_ptr);
+                self.mesh("curve", mat_ptr, to_world, &vertices, &faces)
+            }
+            Object::Heightfield(x) => {
+                let mesh = x.to_mesh();
+                let faces: Vec<usize> = (0..mesh.len()).collect();
+                let (vertices, faces) = mesh_faces(&mesh, &faces);
+                let mat_ptr = material.unwrap_or(x.mat_ptr);
+                self.mesh("heightfield", mat_ptr, to_world, &vertices, &faces)
+            }
+            Object::Patch(x) => {
+                let mesh = x.tessellate(SPHERE_SEGMENTS / 2);
+                let faces: Vec<usize> = (0..mesh.len()).collect();
+                let (vertices, faces) = mesh_faces(&mesh, &faces);
+                let mat_ptr = material.unwrap_or(x.mat_ptr);
+                self.mesh("patch", mat_ptr, to_world, &vertices, &faces)
+            }
+            Object::Voxels(x) => {
+                let mesh = Object::from(Arc::new(x.to_mesh()));
+                self.object(&mesh, to_world, material)
+            }
+            Object::Mesh(mesh) => {
+                let mut by_material: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+                for face in 0..mesh.len() {
+                    let mat_ptr = material.unwrap_or_else(|| mesh.mat_ptr(face));
+                    by_material.entry(mat_ptr).or_default().push(face);
+                }
+                for (mat_ptr, faces) in by_material {
+                    let (vertices, faces) = mesh_faces(mesh, &faces);
+                    self.mesh("mesh", mat_ptr, to_world, &vertices, &faces)?;
+                }
+                Ok(())
+            }
+            Object::MeshTriangle(x) => {
+                let face = x.face as usize;
+                let mat_ptr = material.unwrap_or_else(|| x.mesh.mat_ptr(face));
+                let (vertices, faces) = mesh_faces(&x.mesh, &[face]);
+                self.mesh("triangle", mat_ptr, to_world, &vertices, &faces)
+            }
+            Object::Transformed(x) => {
+                self.object(&x.object, &(*to_world * *x.object_to_world()), material)
+            }
+            Object::Instance(x) => self.object(
+                &x.object.geometry,
+                &(*to_world * *x.object_to_world()),
+                material.or(x.object.material),
+            ),
+            // Only a union can be written as its operands; the other results
+            // would need their surfaces clipped against each other.
+            Object::Csg(x) => match x.op {
+                CsgOp::Union => {
+                    self.object(&x.a, to_world, material)?;
+                    self.object(&x.b, to_world, material)
+                }
+                _ => writeln!(self.out, "# {:?} of two solids left out", x.op),
+            },
+            Object::List(list) => {
+                for o in list {
+                    self.object(o, to_world, material)?;
+                }
+                Ok(())
+            }
+            Object::Bvh(bvh) => {
+                for o in bvh.objects() {
+                    self.object(o, to_world, material)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Writes one `o` group. `faces` index into `vertices` from zero and wind
+    // counter-clockwise seen from the front.
+    fn mesh(
+        &mut self,
+        name: &str,
+        mat_ptr: usize,
+        to_world: &Mat4,
+        vertices: &[Vertex],
+        faces: &[Vec<usize>],
+    ) -> io::Result<()> {
+        let normal_to_world = to_world
+            .inverse()
+            .map(|m| m.transpose())
+            .unwrap_or_default();
+        writeln!(self.out, "o {}{}", name, self.objects)?;
+        writeln!(self.out, "usemtl mat{}", mat_ptr)?;
+        self.objects += 1;
+
+        for (p, n, (u, v)) in vertices {
+            writeln!(self.out, "v {}", to_world.transform_point(p))?;
+            writeln!(
+                self.out,
+                "vn {}",
+                unit_vector(&normal_to_world.transform_vector(n))
+            )?;
+            writeln!(self.out, "vt {} {}", u, v)?;
+        }
+
+        let base = self.vertices + 1;
+        let mirrored = handedness(to_world) < 0.0;
+        for face in faces {
+            write!(self.out, "f")?;
+            let mut write_index = |i: &usize| write!(self.out, " {0}/{0}/{0}", base + i);
+            if mirrored {
+                face.iter().rev().try_for_each(&mut write_index)?;
+            } else {
+                face.iter().try_for_each(&mut write_index)?;
+            }
+            writeln!(self.out)?;
+        }
+        self.vertices += vertices.len();
+        Ok(())
+    }
+}
+
+// The given faces with their vertices renumbered from zero. Meshes without
+// normals are flat shaded, which needs separate vertices per face.
+fn mesh_faces(mesh: &TriangleMesh, faces: &[usize]) -> (Vec<Vertex>, Vec<Vec<usize>>) {
+    let uv = |i: usize, corner: usize| -> (f64, f64) {
+        if mesh.uvs.is_empty() {
+            [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)][corner]
+        } else {
+            mesh.uvs[i]
+        }
+    };
+    let mut vertices = Vec::new();
+    let mut out = Vec::new();
+    let mut remap: BTreeMap<u32, usize> = BTreeMap::new();
+    for &face in faces {
+        let idx = mesh.indices[face];
+        let [a, b, c] = idx.map(|i| mesh.positions[i as usize]);
+        let geometric = cross(&(b - a), &(c - a));
+        let mut corners = Vec::new();
+        for (corner, &i) in idx.iter().enumerate() {
+            let p = mesh.positions[i as usize];
+            if mesh.normals.is_empty() {
+                vertices.push((p, geometric, uv(i as usize, corner)));
+                corners.push(vertices.len() - 1);
+            } else {
+                let n = mesh.normals[i as usize];
+                let next = vertices.len();
+                let k = *remap.entry(i).or_insert(next);
+                if k == next {
+                    vertices.push((p, n, uv(i as usize, corner)));
+                }
+                corners.push(k);
+            }
+        }
+        out.push(corners);
+    }
+    (vertices, out)
+}
+
+// A PLANE_EXTENT square about `point`. Its UVs are left unwrapped so that
+// the texture repeats the way it does when rendering.
+fn plane_vertices(plane: &Plane) -> Vec<Vertex> {
+    let (t, b) = plane.tangents();
+    let n = unit_vector(&plane.normal);
+    [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+        .iter()
+        .map(|&(x, y)| {
+            let p = plane.point + t * (x * PLANE_EXTENT) + b * (y * PLANE_EXTENT);
+            let (u, v) = plane.plane_coordinates(&p);
+            (p, n, (u / plane.uv_scale, v / plane.uv_scale))
+        })
+        .collect()
+}
+
+// Corners counter-clockwise from `q`, with the quad's own UVs.
+fn quad_vertices(q: &Quad) -> Vec<Vertex> {
+    let n = q.normal();
+    vec![
+        (q.q, n, (0.0, 0.0)),
+        (q.q + q.u, n, (1.0, 0.0)),
+        (q.q + q.u + q.v, n, (1.0, 1.0)),
+        (q.q + q.v, n, (0.0, 1.0)),
+    ]
+}
+
+fn tessellate_sphere(s: &Sphere) -> (Vec<Vertex>, Vec<Vec<usize>>) {
+    let mut vertices = Vec::new();
+    for ring in 0..=SPHERE_RINGS {
+        let theta = PI * ring as f64 / SPHERE_RINGS as f64;
+        for seg in 0..=SPHERE_SEGMENTS {
+            let phi = 2.0 * PI * seg as f64 / SPHERE_SEGMENTS as f64;
+            let normal = Vec3::of(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                -theta.sin() * phi.sin(),
+            );
+            // A negative radius mirrors the positions through the centre,
+            // which turns both the winding and `normal` inwards.
+            let uv = (
+                seg as f64 / SPHERE_SEGMENTS as f64,
+                1.0 - ring as f64 / SPHERE_RINGS as f64,
+            );
+            vertices.push((s.center + normal * s.radius, normal, uv));
+        }
+    }
+
+    let index = |ring: usize, seg: usize| ring * (SPHERE_SEGMENTS + 1) + seg;
+    let mut faces = Vec::new();
+    for ring in 0..SPHERE_RINGS {
+        for seg in 0..SPHERE_SEGMENTS {
+            faces.push(vec![
+                index(ring, seg),
+                index(ring + 1, seg),
+                index(ring + 1, seg + 1),
+                index(ring, seg + 1),
+            ]);
+        }
+    }
+    (vertices, faces)
+}
+
+// A SPHERE_SEGMENTS by SPHERE_RINGS grid over (u, v). Each face is wound to
+// agree with the outward normal, whichever way round the parameters run.
+fn tessellate_surface<S: Surface>(s: &S) -> (Vec<Vertex>, Vec<Vec<usize>>) {
+    let mut vertices = Vec::new();
+    for ring in 0..=SPHERE_RINGS {
+        let v = ring as f64 / SPHERE_RINGS as f64;
+        for seg in 0..=SPHERE_SEGMENTS {
+            let u = seg as f64 / SPHERE_SEGMENTS as f64;
+            let p = s.point(u, v);
+            let mut n = s.outward_normal(&p);
+            if n.length_squared() == 0.0 {
+                // Borrow the normal from just beside a singular point.
+                let dv = if v > 0.5 { -1e-3 } else { 1e-3 };
+                n = s.outward_normal(&s.point(u, v + dv));
+            }
+            vertices.push((p, n, (u, v)));
+        }
+    }
+
+    let index = |ring: usize, seg: usize| ring * (SPHERE_SEGMENTS + 1) + seg;
+    let mut faces = Vec::new();
+    for ring in 0..SPHERE_RINGS {
+        for seg in 0..SPHERE_SEGMENTS {
+            let mut face = vec![
+                index(ring, seg),
+                index(ring, seg + 1),
+                index(ring + 1, seg + 1),
+                index(ring + 1, seg),
+            ];
+            // The diagonals stay apart even where one edge collapses to a
+            // point, as at a cone's apex.
+            let [a, b, c, d] = [0, 1, 2, 3].map(|i| vertices[face[i]].0);
+            let n = vertices[face[0]].1 + vertices[face[2]].1;
+            if dot(&cross(&(c - a), &(d - b)), &n) < 0.0 {
+                face.reverse();
+            }
+            faces.push(face);
+        }
+    }
+    (vertices, faces)
+}
+
+// `mtl_name` is the file name written into the `mtllib` statement.
+pub fn write_obj<W: Write>(scene: &Scene, mtl_name: &str, out: &mut W) -> io::Result<()> {
+    let v = &scene.view;
+    writeln!(
+        out,
+        "# camera lookfrom {} lookat {} vup {}",
+        v.lookfrom, v.lookat, v.vup
+    )?;
+    writeln!(
+        out,
+        "# camera vfov {} aspect {} aperture {} focus {}",
+        v.vfov, v.aspect_ratio, v.aperture, v.focus_dist
+    )?;
+    writeln!(out, "mtllib {}", mtl_name)?;
+
+    let mut writer = ObjWriter {
+        out,
+        vertices: 0,
+        objects: 0,
+    };
+    for object in &scene.world {
+        writer.object(object, &Mat4::identity(), None)?;
+    }
+    Ok(())
+}
+
+// Writes `path` and a `.mtl` file next to it with the same stem.
+pub fn save_obj(scene: &Scene, path: &Path) -> io::Result<()> {
+    let mtl_path = path.with_extension("mtl");
+    let mtl_name = mtl_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("scene.mtl");
+
+    let mut mtl = io::BufWriter::new(fs::File::create(&mtl_path)?);
+    write_mtl(scene, &mut mtl)?;
+    mtl.flush()?;
+
+    let mut obj = io::BufWriter::new(fs::File::create(path)?);
+    write_obj(scene, mtl_name, &mut obj)?;
+    obj.flush()
+}
+
+// Materials by name, read back the way `write_mtl` maps them: `illum 3` is a
+// metal, `illum 7` glass and anything else diffuse. Files from other tools
+// come out as near as those three allow.
+pub fn read_mtl(text: &str) -> io::Result<Vec<(String, Mat)>> {
+    // Name, Kd, Ks, Ns, Ni and illum of each material.
+    let mut entries: Vec<(String, Color, Color, f64, f64, u32)> = Vec::new();
+    for (i, raw) in text.lines().enumerate() {
+        let mut t = Tokens {
+            line: i + 1,
+            iter: raw.split('#').next().unwrap_or("").split_whitespace(),
+        };
+        let statement = match t.iter.next() {
+            Some(s) => s,
+            None => continue,
+        };
+        if statement == "newmtl" {
+            let grey = Color::of(0.5, 0.5, 0.5);
+            entries.push((t.word()?.to_string(), grey, grey, 0.0, 1.5, 1));
+            continue;
+        }
+        let entry = match entries.last_mut() {
+            Some(entry) => entry,
+            None => return Err(invalid(t.line, format!("'{}' before newmtl", statement))),
+        };
+        match statement {
+            "Kd" => entry.1 = t.vec3()?,
+            "Ks" => entry.2 = t.vec3()?,
+            "Ns" => entry.3 = t.number()?,
+            "Ni" => entry.4 = t.number()?,
+            "illum" => entry.5 = t.number()?,
+            _ => {}
+        }
+    }
+    Ok(entries
+        .into_iter()
+        .map(|(name, kd, ks, ns, ni, illum)| {
+            let m = match illum {
+                3 => Mat::M(Metal::new(ks, (1.0 - ns / 1000.0).clamp(0.0, 1.0))),
+                7 => Mat::D(Dielectric { ir: ni }),
+                _ => Mat::L(Lambertian { albedo: kd }),
+            };
+            (name, m)
+        })
+        .collect())
+}
+
+// The faces of one `o` group so far. Each corner is its position, UV and
+// normal indices, and each distinct corner becomes one mesh vertex.
+#[derive(Default)]
+struct Group {
+    corners: HashMap<(usize, Option<usize>, Option<usize>), u32>,
+    order: Vec<(usize, Option<usize>, Option<usize>)>,
+    indices: Vec<[u32; 3]>,
+}
+
+// One-based, or negative to count back from the latest.
+fn obj_index(word: &str, count: usize, line: usize) -> io::Result<usize> {
+    let i: i64 = word
+        .parse()
+        .map_err(|_| invalid(line, format!("bad index '{}'", word)))?;
+    // Positive indices count from one, negative ones back from the end.
+    let n = count as i64;
+    let k = match i {
+        0 => None,
+        1..=i64::MAX => Some(i - 1),
+        _ => n.checked_add(i),
+    };
+    match k {
+        Some(k) if (0..n).contains(&k) => Ok(k as usize),
+        _ => Err(invalid(line, format!("index {} out of range", i))),
+    }
+}
+
+// Reads `# camera` comments as `write_obj` writes them.
+fn read_camera(comment: &str, view: &mut View, line: usize) -> io::Result<()> {
+    let mut t = Tokens {
+        line,
+        iter: comment.split_whitespace(),
+    };
+    if t.iter.next() != Some("camera") {
+        return Ok(());
+    }
+    while let Some(key) = t.iter.next() {
+        match key {
+            "lookfrom" => view.lookfrom = t.vec3()?,
+            "lookat" => view.lookat = t.vec3()?,
+            "vup" => view.vup = t.vec3()?,
+            "vfov" => view.vfov = t.number()?,
+            "aspect" => view.aspect_ratio = t.number()?,
+            "aperture" => view.aperture = t.number()?,
+            "focus" => view.focus_dist = t.number()?,
+            // Written by something else.
+            _ => break,
+        }
+    }
+    Ok(())
+}
+
+impl Group {
+    // The group as a mesh, with normals and UVs if every corner has them.
+    fn into_mesh(
+        self,
+        positions: &[Point3],
+        normals: &[Vec3],
+        uvs: &[(f64, f64)],
+        mat_ptr: usize,
+    ) -> TriangleMesh {
+        let order = self.order;
+        let corners = order.iter().map(|c| positions[c.0]).collect();
+        let mut mesh = TriangleMesh::new(corners, self.indices, mat_ptr);
+        if let Some(n) = order.iter().map(|c| c.2.map(|k| normals[k])).collect() {
+            mesh = mesh.with_normals(n);
+        }
+        if let Some(uv) = order.iter().map(|c| c.1.map(|k| uvs[k])).collect() {
+            mesh = mesh.with_uvs(uv);
+        }
+        mesh
+    }
+}
+
+// A scene of one mesh per `o` group, with `materials` for `usemtl` to name.
+// Faces are fanned into triangles; groups without `usemtl` get a grey
+// diffuse material.
+pub fn read_obj(text: &str, materials: &[(String, Mat)]) -> io::Result<Scene> {
+    let mut scene = Scene::new(View::default());
+    let mut names = HashMap::new();
+    for (name, m) in materials {
+        names.insert(name.as_str(), scene.add_material(*m));
+    }
+    let (mut positions, mut normals, mut uvs) = (Vec::new(), Vec::new(), Vec::new());
+    // Finished groups with what `usemtl` last named for them.
+    let mut groups: Vec<(Group, Option<usize>)> = Vec::new();
+    let mut group = Group::default();
+    let mut current: Option<usize> = None;
+
+    for (i, raw) in text.lines().enumerate() {
+        let line = i + 1;
+        let mut parts = raw.splitn(2, '#');
+        let content = parts.next().unwrap_or("");
+        if let Some(comment) = parts.next() {
+            read_camera(comment, &mut scene.view, line)?;
+        }
+        let mut t = Tokens {
+            line,
+            iter: content.split_whitespace(),
+        };
+        match t.iter.next() {
+            Some("v") => positions.push(t.vec3()?),
+            Some("vn") => normals.push(t.vec3()?),
+            Some("vt") => uvs.push((t.number()?, t.number()?)),
+            Some("o") | Some("g") => groups.push((std::mem::take(&mut group), current)),
+            Some("usemtl") => {
+                groups.push((std::mem::take(&mut group), current));
+                let name = t.word()?;
+                let mat_ptr = names
+                    .get(name)
+                    .ok_or_else(|| invalid(line, format!("unknown material '{}'", name)))?;
+                current = Some(*mat_ptr);
+            }
+            Some("f") => {
+                let mut face = Vec::new();
+                for word in t.iter {
+                    let mut refs = word.split('/');
+                    let p = obj_index(refs.next().unwrap_or(""), positions.len(), line)?;
+                    let mut optional = |count| match refs.next() {
+                        Some(w) if !w.is_empty() => obj_index(w, count, line).map(Some),
+                        _ => Ok(None),
+                    };
+                    let corner = (p, optional(uvs.len())?, optional(normals.len())?);
+                    let next = group.order.len() as u32;
+                    let k = *group.corners.entry(corner).or_insert(next);
+                    if k == next {
+                        group.order.push(corner);
+                    }
+                    face.push(k);
+                }
+                if face.len() < 3 {
+                    return Err(invalid(line, String::from("face needs three corners")));
+                }
+                for k in 1..face.len() - 1 {
+                    group.indices.push([face[0], face[k], face[k + 1]]);
+                }
+            }
+            // Smoothing groups, lines, points and `mtllib`.
+            _ => {}
+        }
+    }
+    groups.push((group, current));
+
+    let mut fallback = None;
+    for (group, mat_ptr) in groups {
+        if group.indices.is_empty() {
+            continue;
+        }
+        let mat_ptr = mat_ptr.unwrap_or_else(|| {
+            *fallback.get_or_insert_with(|| {
+                let grey = Color::of(0.5, 0.5, 0.5);
+                scene.add_material(Mat::L(Lambertian { albedo: grey }))
+            })
+        });
+        let mesh = group.into_mesh(&positions, &normals, &uvs, mat_ptr);
+        scene.add(Object::Mesh(Arc::new(mesh)));
+    }
+    Ok(scene)
+}
+
+// Reads `path` and the `.mtl` file its `mtllib` names, looked up next to it.
+pub fn load_obj(path: &Path) -> io::Result<Scene> {
+    let text = fs::read_to_string(path)?;
+    let mtllib = text.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("mtllib"), Some(name)) => Some(name),
+            _ => None,
+        }
+    });
+    let materials = match mtllib {
+        Some(name) => read_mtl(&fs::read_to_string(path.with_file_name(name))?)?,
+        None => Vec::new(),
+    };
+    read_obj(&text, &materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::quad::Cuboid;
+    use crate::ray::Ray;
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let view = View {
+            lookfrom: Point3::of(1.0, 2.0, 9.0),
+            vfov: 35.0,
+            aperture: 0.1,
+            ..View::default()
+        };
+        let mut scene = Scene::new(view);
+        let matte = scene.add_material(Mat::L(Lambertian {
+            albedo: Color::of(0.25, 0.5, 0.75),
+        }));
+        let metal = scene.add_material(Mat::M(Metal::new(Color::of(0.8, 0.6, 0.2), 0.25)));
+        let glass = scene.add_material(Mat::D(Dielectric { ir: 1.5 }));
+        scene.add(Sphere::of(Point3::of(0.0, 0.0, 0.0), 1.0, matte));
+        scene.add(Cuboid::of(
+            Point3::of(2.0, 0.0, 0.0),
+            Point3::of(3.0, 1.0, 1.0),
+            metal,
+        ));
+        let side = Quad::of(
+            Point3::of(-3.0, 0.0, 0.0),
+            Vec3::of(1.0, 0.0, 0.0),
+            Vec3::of(0.0, 1.0, 0.0),
+            glass,
+        );
+        scene.add(side);
+        let ball = TriangleMesh::icosphere(1, matte).with_smooth_normals();
+        scene.add(Object::Mesh(Arc::new(ball)));
+
+        let mut mtl = Vec::new();
+        write_mtl(&scene, &mut mtl).unwrap();
+        let mut obj = Vec::new();
+        write_obj(&scene, "scene.mtl", &mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let materials = read_mtl(std::str::from_utf8(&mtl).unwrap()).unwrap();
+        let loaded = read_obj(&obj, &materials).unwrap();
+
+        assert_eq!(loaded.view, scene.view);
+        assert_eq!(loaded.materials, scene.materials);
+        let count = |statement: &str| {
+            obj.lines()
+                .filter(|l| l.split_whitespace().next() == Some(statement))
+                .count()
+        };
+        let faces: usize = obj
+            .lines()
+            .filter(|l| l.starts_with("f "))
+            .map(|l| l.split_whitespace().count() - 3)
+            .sum();
+        let meshes: Vec<&TriangleMesh> = loaded
+            .world
+            .iter()
+            .map(|o| match o {
+                Object::Mesh(m) => &**m,
+                other => panic!("{:?}", other),
+            })
+            .collect();
+        assert_eq!(meshes.len(), count("o"));
+        assert_eq!(meshes.len(), 4);
+        let positions: usize = meshes.iter().map(|m| m.positions.len()).sum();
+        assert_eq!(positions, count("v"));
+        assert_eq!(meshes.iter().map(|m| m.len()).sum::<usize>(), faces);
+
+        // The tessellated sphere is hit close to where the sphere is.
+        let r = Ray::of(Point3::of(0.0, 0.0, 5.0), Vec3::of(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        assert!(loaded.world[0].hit(&r, 0.001, f64::INFINITY, &mut rec));
+        assert!((rec.t - 4.0).abs() < 0.01, "{}", rec.t);
+        assert_eq!(rec.mat_ptr, matte);
+    }
+
+    #[test]
+    fn resolves_relative_indices_and_defaults() {
+        let text = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf -4 -3 -2 -1\n";
+        let scene = read_obj(text, &[]).unwrap();
+        assert_eq!(scene.materials.len(), 1);
+        match &scene.world[..] {
+            [Object::Mesh(m)] => assert_eq!((m.positions.len(), m.len()), (4, 2)),
+            other => panic!("{:?}", other),
+        }
+        let triangle = "v 0 0 0\nv 1 0 0\nv 1 1 0\n";
+        for face in &[
+            "1 2 4",
+            "0 1 2",
+            "-4 1 2",
+            "-9223372036854775808 1 2",
+            "9223372036854775807 1 2",
+        ] {
+            let err = read_obj(&format!("{}f {}\n", triangle, face), &[]).unwrap_err();
+            assert!(err.to_string().starts_with("line 4:"), "{}: {}", face, err);
+        }
+        let err = read_obj("usemtl missing\n", &[]).unwrap_err();
+        assert!(err.to_string().contains("unknown material"), "{}", err);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::csg::{march_spans, Csg, Solid, Span};
+use crate::curve::Curve;
+use crate::heightfield::Heightfield;
+use crate::hittable::{HitRecord, Hittable};
+use crate::mesh::{MeshTriangle, TriangleMesh};
+use crate::patch::BezierPatch;
+use crate::plane::Plane;
+use crate::quad::{AxisRect, Cuboid, Quad};
+use crate::quadric::{Cone, Cylinder, Disk, Hyperboloid, Paraboloid};
+use crate::ray::Ray;
+use crate::scene_graph::Instance;
+use crate::sdf::{Sdf, SdfShape};
+use crate::sphere::Sphere;
+use crate::torus::Torus;
+use crate::transform::Transformed;
+use crate::triangle::Triangle;
+use crate::voxel::VoxelGrid;
+use std::sync::Arc;
+
+// Any primitive that can go into a world. Like `Mat`, this is a closed enum
+// rather than a trait object, which keeps dispatch static and the world
+// `Send + Sync` for rayon.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Object {
+    Sphere(Sphere),
+    Triangle(Triangle),
+    Plane(Plane),
+    Quad(Quad),
+    Rect(AxisRect),
+    Cuboid(Box<Cuboid>),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Disk(Disk),
+    Paraboloid(Paraboloid),
+    Hyperboloid(Hyperboloid),
+    Torus(Torus),
+    Sdf(SdfShape<Sdf>),
+    Heightfield(Arc<Heightfield>),
+    Curve(Curve),
+    Patch(Box<BezierPatch>),
+    Voxels(Arc<VoxelGrid>),
+    Mesh(Arc<TriangleMesh>),
+    MeshTriangle(MeshTriangle),
+    Transformed(Transformed<Arc<Object>>),
+    Instance(Transformed<Instance<Object>>),
+    List(Vec<Object>),
+    // A group with its own hierarchy, as shared by instances.
+    Bvh(Arc<Bvh<Object>>),
+    Csg(Csg<Arc<Object>>),
+}
+
+impl Object {
+    // False for objects that extend to infinity, like `Plane` or a repeated
+    // distance field, and anything containing one. Bounding volume
+    // structures cannot hold these, so they have to be kept aside and tested
+    // against every ray.
+    pub fn is_bounded(&self) -> bool {
+        self.bounding_box(0.0, 0.0, &mut Aabb::empty())
+    }
+
+    // Whether the object encloses a volume whose inside can be told from its
+    // hits, as CSG needs. Open surfaces would give spans running off to
+    // infinity, and curves and voxels report every hit as an entry. Meshes
+    // are taken on trust. Swept cylinders and tori are open where the sweep
+    // stops, even capped.
+    pub fn is_closed(&self) -> bool {
+        match self {
+            Object::Sphere(_)
+            | Object::Plane(_)
+            | Object::Cuboid(_)
+            | Object::Sdf(_)
+            | Object::Mesh(_)
+            | Object::Csg(_) => true,
+            Object::Cylinder(x) => x.capped && x.phi_max >= 360.0,
+            Object::Torus(x) => x.phi_max >= 360.0,
+            Object::Transformed(x) => x.object.is_closed(),
+            Object::Instance(x) => x.object.geometry.is_closed(),
+            Object::List(x) => x.iter().all(Object::is_closed),
+            Object::Bvh(x) => x.objects().iter().all(Object::is_closed),
+            _ => false,
+        }
+    }
+}
+
+impl Hittable for Object {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        match self {
+            Object::Sphere(x) => x.hit(r, t_min, t_max, rec),
+            Object::Triangle(x) => x.hit(r, t_min, t_max, rec),
+            Object::Plane(x) => x.hit(r, t_min, t_max, rec),
+            Object::Quad(x) => x.hit(r, t_min, t_max, rec),
+            Object::Rect(x) => x.hit(r, t_min, t_max, rec),
+            Object::Cuboid(x) => x.hit(r, t_min, t_max, rec),
+            Object::Cylinder(x) => x.hit(r, t_min, t_max, rec),
+            Object::Cone(x) => x.hit(r, t_min, t_max, rec),
+            Object::Disk(x) => x.hit(r, t_min, t_max, rec),
+            Object::Paraboloid(x) => x.hit(r, t_min, t_max, rec),
+            Object::Hyperboloid(x) => x.hit(r, t_min, t_max, rec),
+            Object::Torus(x) => x.hit(r, t_min, t_max, rec),
+            Object::Sdf(x) => x.hit(r, t_min, t_max, rec),
+            Object::Heightfield(x) => x.hit(r, t_min, t_max, rec),
+            Object::Curve(x) => x.hit(r, t_min, t_max, rec),
+            Object::Patch(x) => x.hit(r, t_min, t_max, rec),
+            Object::Voxels(x) => x.hit(r, t_min, t_max, rec),
+            Object::Mesh(x) => x.hit(r, t_min, t_max, rec),
+            Object::MeshTriangle(x) => x.hit(r, t_min, t_max, rec),
+            Object::Transformed(x) => x.hit(r, t_min, t_max, rec),
+            Object::Instance(x) => x.hit(r, t_min, t_max, rec),
+            Object::List(x) => x.hit(r, t_min, t_max, rec),
+            Object::Bvh(x) => x.hit(r, t_min, t_max, rec),
+            Object::Csg(x) => x.hit(r, t_min, t_max, rec),
+        }
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        match self {
+            Object::Sphere(x) => x.bounding_box(time0, time1, output_box),
+            Object::Triangle(x) => x.bounding_box(time0, time1, output_box),
+            Object::Plane(x) => x.bounding_box(time0, time1, output_box),
+            Object::Quad(x) => x.bounding_box(time0, time1, output_box),
+            Object::Rect(x) => x.bounding_box(time0, time1, output_box),
+            Object::Cuboid(x) => x.bounding_box(time0, time1, output_box),
+            Object::Cylinder(x) => x.bounding_box(time0, time1, output_box),
+            Object::Cone(x) => x.bounding_box(time0, time1, output_box),
+            Object::Disk(x) => x.bounding_box(time0, time1, output_box),
+            Object::Paraboloid(x) => x.bounding_box(time0, time1, output_box),
+            Object::Hyperboloid(x) => x.bounding_box(time0, time1, output_box),
+            Object::Torus(x) => x.bounding_box(time0, time1, output_box),
+            Object::Sdf(x) => x.bounding_box(time0, time1, output_box),
+            Object::Heightfield(x) => x.bounding_box(time0, time1, output_box),
+            Object::Curve(x) => x.bounding_box(time0, time1, output_box),
+            Object::Patch(x) => x.bounding_box(time0, time1, output_box),
+            Object::Voxels(x) => x.bounding_box(time0, time1, output_box),
+            Object::Mesh(x) => x.bounding_box(time0, time1, output_box),
+            Object::MeshTriangle(x) => x.bounding_box(time0, time1, output_box),
+            Object::Transformed(x) => x.bounding_box(time0, time1, output_box),
+            Object::Instance(x) => x.bounding_box(time0, time1, output_box),
+            Object::List(x) => x.bounding_box(time0, time1, output_box),
+            Object::Bvh(x) => x.bounding_box(time0, time1, output_box),
+            Object::Csg(x) => x.bounding_box(time0, time1, output_box),
+        }
+    }
+}
+
+// Only closed objects make sense here, as `is_closed` checks: open surfaces
+// such as quads or triangles report spans that start or end at infinity.
+impl Solid for Object {
+    fn spans(&self, r: &Ray, out: &mut Vec<Span>) {
+        match self {
+            Object::Plane(x) => x.spans(r, out),
+            Object::Csg(x) => x.spans(r, out),
+            _ => march_spans(self, r, out),
+        }
+    }
+}
+
+impl From<Sphere> for Object {
+    fn from(x: Sphere) -> Object {
+        Object::Sphere(x)
+    }
+}
+
+impl From<Triangle> for Object {
+    fn from(x: Triangle) -> Object {
+        Object::Triangle(x)
+    }
+}
+
+impl From<Quad> for Object {
+    fn from(x: Quad) -> Object {
+        Object::Quad(x)
+    }
+}
+
+impl From<AxisRect> for Object {
+    fn from(x: AxisRect) -> Object {
+        Object::Rect(x)
+    }
+}
+
+impl From<Cuboid> for Object {
+    fn from(x: Cuboid) -> Object {
+        Object::Cuboid(Box::new(x))
+    }
+}
+
+impl From<Cylinder> for Object {
+    fn from(x: Cylinder) -> Object {
+        Object::Cylinder(x)
+    }
+}
+
+impl From<Cone> for Object {
+    fn from(x: Cone) -> Object {
+        Object::Cone(x)
+    }
+}
+
+impl From<Disk> for Object {
+    fn from(x: Disk) -> Object {
+        Object::Disk(x)
+    }
+}
+
+impl From<Paraboloid> for Object {
+    fn from(x: Paraboloid) -> Object {
+        Object::Paraboloid(x)
+    }
+}
+
+impl From<Hyperboloid> for Object {
+    fn from(x: Hyperboloid) -> Object {
+        Object::Hyperboloid(x)
+    }
+}
+
+impl From<Torus> for Object {
+    fn from(x: Torus) -> Object {
+        Object::Torus(x)
+    }
+}
+
+impl From<Plane> for Object {
+    fn from(x: Plane) -> Object {
+        Object::Plane(x)
+    }
+}
+
+impl From<SdfShape<Sdf>> for Object {
+    fn from(x: SdfShape<Sdf>) -> Object {
+        Object::Sdf(x)
+    }
+}
+
+impl From<Curve> for Object {
+    fn from(x: Curve) -> Object {
+        Object::Curve(x)
+    }
+}
+
+impl From<Heightfield> for Object {
+    fn from(x: Heightfield) -> Object {
+        Object::Heightfield(Arc::new(x))
+    }
+}
+
+impl From<BezierPatch> for Object {
+    fn from(x: BezierPatch) -> Object {
+        Object::Patch(Box::new(x))
+    }
+}
+
+impl From<VoxelGrid> for Object {
+    fn from(x: VoxelGrid) -> Object {
+        Object::Voxels(Arc::new(x))
+    }
+}
+
+impl From<Arc<TriangleMesh>> for Object {
+    fn from(x: Arc<TriangleMesh>) -> Object {
+        Object::Mesh(x)
+    }
+}
+
+impl From<TriangleMesh> for Object {
+    fn from(x: TriangleMesh) -> Object {
+        Object::Mesh(Arc::new(x))
+    }
+}
+
+impl From<MeshTriangle> for Object {
+    fn from(x: MeshTriangle) -> Object {
+        Object::MeshTriangle(x)
+    }
+}
+
+impl From<Transformed<Arc<Object>>> for Object {
+    fn from(x: Transformed<Arc<Object>>) -> Object {
+        Object::Transformed(x)
+    }
+}
+
+impl From<Transformed<Instance<Object>>> for Object {
+    fn from(x: Transformed<Instance<Object>>) -> Object {
+        Object::Instance(x)
+    }
+}
+
+impl From<Csg<Arc<Object>>> for Object {
+    fn from(x: Csg<Arc<Object>>) -> Object {
+        Object::Csg(x)
+    }
+}
+
+impl From<Bvh<Object>> for Object {
+    fn from(x: Bvh<Object>) -> Object {
+        Object::Bvh(Arc::new(x))
+    }
+}
+
+impl From<Vec<Object>> for Object {
+    fn from(x: Vec<Object>) -> Object {
+        Object::List(x)
+    }
+}
@@ -0,0 +1,507 @@
+// Bicubic Bézier patches, as in the Utah teapot and in CAD models. A patch is
+// either tessellated into a smooth mesh with normals and UVs taken from the
+// surface itself, or hit directly: its control grid is split until the
+// pieces that still straddle the ray are small, and Newton's method then
+// finds where the ray meets each.
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::mesh::TriangleMesh;
+use crate::ray::Ray;
+use crate::vec3::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Row by row: `points[4 * j + i]` is weighted by B_i(u) B_j(v). The normal is
+// dP/du x dP/dv, and the tangent on a hit follows u.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BezierPatch {
+    pub points: [Point3; 16],
+    pub mat_ptr: usize,
+}
+
+// Times each patch is split in both directions before Newton's method takes
+// over.
+const SPLIT_DEPTH: u32 = 4;
+const NEWTON_STEPS: usize = 12;
+
+fn bernstein(t: f64) -> [f64; 4] {
+    let s = 1.0 - t;
+    [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t]
+}
+
+fn bernstein_derivative(t: f64) -> [f64; 4] {
+    let s = 1.0 - t;
+    [
+        -3.0 * s * s,
+        3.0 * s * s - 6.0 * t * s,
+        6.0 * t * s - 3.0 * t * t,
+        3.0 * t * t,
+    ]
+}
+
+// Point and partial derivatives of the patch with control points `cp`.
+fn evaluate(cp: &[Point3; 16], u: f64, v: f64) -> (Point3, Vec3, Vec3) {
+    let (bu, bv) = (bernstein(u), bernstein(v));
+    let (du, dv) = (bernstein_derivative(u), bernstein_derivative(v));
+    let (mut p, mut dpdu, mut dpdv) = (Point3::new(), Vec3::new(), Vec3::new());
+    for j in 0..4 {
+        for i in 0..4 {
+            let c = cp[4 * j + i];
+            p = p + c * (bu[i] * bv[j]);
+            dpdu = dpdu + c * (du[i] * bv[j]);
+            dpdv = dpdv + c * (bu[i] * dv[j]);
+        }
+    }
+    (p, dpdu, dpdv)
+}
+
+// Halves of a cubic either side of 1/2 (de Casteljau).
+fn split_curve(p: [Point3; 4]) -> [[Point3; 4]; 2] {
+    let mid = |a: Point3, b: Point3| (a + b) * 0.5;
+    let (a, b, c) = (mid(p[0], p[1]), mid(p[1], p[2]), mid(p[2], p[3]));
+    let (ab, bc) = (mid(a, b), mid(b, c));
+    let m = mid(ab, bc);
+    [[p[0], a, ab, m], [m, bc, c, p[3]]]
+}
+
+// The four quarters of a patch, split at u = 1/2 and v = 1/2, in the order
+// (low u, low v), (high u, low v), (low u, high v), (high u, high v).
+fn split_patch(cp: &[Point3; 16]) -> [[Point3; 16]; 4] {
+    let mut halves = [[Point3::new(); 16]; 2];
+    for j in 0..4 {
+        let row = [cp[4 * j], cp[4 * j + 1], cp[4 * j + 2], cp[4 * j + 3]];
+        for (h, half) in split_curve(row).iter().enumerate() {
+            halves[h][4 * j..4 * j + 4].copy_from_slice(half);
+        }
+    }
+    let mut quarters = [[Point3::new(); 16]; 4];
+    for (h, half) in halves.iter().enumerate() {
+        for i in 0..4 {
+            let column = [half[i], half[4 + i], half[8 + i], half[12 + i]];
+            for (k, part) in split_curve(column).iter().enumerate() {
+                for (j, &p) in part.iter().enumerate() {
+                    quarters[2 * k + h][4 * j + i] = p;
+                }
+            }
+        }
+    }
+    quarters
+}
+
+impl BezierPatch {
+    pub fn new(points: [Point3; 16], mat_ptr: usize) -> BezierPatch {
+        BezierPatch { points, mat_ptr }
+    }
+
+    pub fn point(&self, u: f64, v: f64) -> Point3 {
+        evaluate(&self.points, u, v).0
+    }
+
+    // Unit normal and tangent at (u, v). Where an edge collapses to a point,
+    // as at the top of the teapot's lid, they are taken from just inside.
+    pub fn frame(&self, u: f64, v: f64) -> (Vec3, Vec3) {
+        for &nudge in &[0.0, 1e-6, 1e-4, 1e-2] {
+            let (u, v) = (u + (0.5 - u) * nudge, v + (0.5 - v) * nudge);
+            let (_, dpdu, dpdv) = evaluate(&self.points, u, v);
+            let n = cross(&dpdu, &dpdv);
+            if n.length_squared() > 1e-24 && dpdu.length_squared() > 1e-24 {
+                return (unit_vector(&n), unit_vector(&dpdu));
+            }
+        }
+        (Vec3::of(0.0, 1.0, 0.0), Vec3::of(1.0, 0.0, 0.0))
+    }
+
+    pub fn normal(&self, u: f64, v: f64) -> Vec3 {
+        self.frame(u, v).0
+    }
+
+    // A grid of `n` by `n` quads, each cut in two, with the patch's own
+    // normals and its (u, v) as UVs.
+    pub fn tessellate(&self, n: usize) -> TriangleMesh {
+        tessellate_patches(std::slice::from_ref(self), n)
+    }
+
+    // Searches the piece `cp` covering `[u0, u1] x [v0, v1]` for the nearest
+    // hit. `planes` are two planes that meet along the ray, as (normal,
+    // offset). A hit lowers `t_max` and records its (u, v).
+    #[allow(clippy::too_many_arguments)]
+    fn search(
+        &self,
+        cp: &[Point3; 16],
+        (u0, u1, v0, v1): (f64, f64, f64, f64),
+        depth: u32,
+        r: &Ray,
+        planes: &[(Vec3, f64); 2],
+        t_min: f64,
+        t_max: &mut f64,
+        found: &mut Option<(f64, f64)>,
+    ) {
+        // The piece lies in the hull of its control points, so it can only
+        // meet the ray if they straddle both planes and reach the range.
+        let speed2 = r.direction.length_squared();
+        for (n, d) in planes {
+            let (lo, hi) = cp
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| {
+                    let x = dot(n, p) - d;
+                    (lo.min(x), hi.max(x))
+                });
+            if lo > 0.0 || hi < 0.0 {
+                return;
+            }
+        }
+        let (lo, hi) = cp
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| {
+                let t = dot(&(*p - r.origin), &r.direction) / speed2;
+                (lo.min(t), hi.max(t))
+            });
+        if hi < t_min || lo > *t_max {
+            return;
+        }
+
+        if depth > 0 {
+            let (um, vm) = (0.5 * (u0 + u1), 0.5 * (v0 + v1));
+            let ranges = [
+                (u0, um, v0, vm),
+                (um, u1, v0, vm),
+                (u0, um, vm, v1),
+                (um, u1, vm, v1),
+            ];
+            for (piece, range) in split_patch(cp).iter().zip(ranges) {
+                self.search(piece, range, depth - 1, r, planes, t_min, t_max, found);
+            }
+            return;
+        }
+
+        // Newton's method on the distances to the two planes, from the
+        // middle of the piece.
+        let (mut s, mut t) = (0.5, 0.5);
+        let tolerance = 1e-9 * (1.0 + cp[0].length());
+        let mut converged = false;
+        for _ in 0..NEWTON_STEPS {
+            let (p, dpds, dpdt) = evaluate(cp, s, t);
+            let f = [
+                dot(&planes[0].0, &p) - planes[0].1,
+                dot(&planes[1].0, &p) - planes[1].1,
+            ];
+            if f[0].abs() < tolerance && f[1].abs() < tolerance {
+                converged = true;
+                break;
+            }
+            let (a, b) = (dot(&planes[0].0, &dpds), dot(&planes[0].0, &dpdt));
+            let (c, d) = (dot(&planes[1].0, &dpds), dot(&planes[1].0, &dpdt));
+            let det = a * d - b * c;
+            if det.abs() < 1e-300 {
+                return;
+            }
+            s -= (d * f[0] - b * f[1]) / det;
+            t -= (a * f[1] - c * f[0]) / det;
+        }
+        const SLACK: f64 = 1e-6;
+        if !converged
+            || !(-SLACK..=1.0 + SLACK).contains(&s)
+            || !(-SLACK..=1.0 + SLACK).contains(&t)
+        {
+            return;
+        }
+        let p = evaluate(cp, s, t).0;
+        let hit_t = dot(&(p - r.origin), &r.direction) / speed2;
+        if hit_t < t_min || hit_t > *t_max {
+            return;
+        }
+        *t_max = hit_t;
+        *found = Some((u0 + s * (u1 - u0), v0 + t * (v1 - v0)));
+    }
+}
+
+impl Hittable for BezierPatch {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let helper = if r.direction.x().abs() < 0.5 * r.direction.length() {
+            Vec3::of(1.0, 0.0, 0.0)
+        } else {
+            Vec3::of(0.0, 1.0, 0.0)
+        };
+        let n1 = unit_vector(&cross(&r.direction, &helper));
+        let n2 = unit_vector(&cross(&r.direction, &n1));
+        let planes = [(n1, dot(&n1, &r.origin)), (n2, dot(&n2, &r.origin))];
+
+        let mut t = t_max;
+        let mut found = None;
+        self.search(
+            &self.points,
+            (0.0, 1.0, 0.0, 1.0),
+            SPLIT_DEPTH,
+            r,
+            &planes,
+            t_min,
+            &mut t,
+            &mut found,
+        );
+        let (u, v) = match found {
+            Some(uv) => uv,
+            None => return false,
+        };
+
+        let (outward_normal, tangent) = self.frame(u.clamp(0.0, 1.0), v.clamp(0.0, 1.0));
+        let front_face = dot(&r.direction, &outward_normal) < 0.0;
+        *rec = HitRecord {
+            p: r.at(t),
+            normal: if front_face {
+                outward_normal
+            } else {
+                -outward_normal
+            },
+            mat_ptr: self.mat_ptr,
+            t,
+            u,
+            v,
+            tangent,
+            front_face,
+        };
+        true
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        *output_box = Aabb::from_points(&self.points);
+        true
+    }
+}
+
+// All the patches as one mesh of `n` by `n` quads each. Patches keep their
+// own materials and UVs; vertices along shared edges are not merged.
+pub fn tessellate_patches(patches: &[BezierPatch], n: usize) -> TriangleMesh {
+    assert!(n > 0, "need at least one quad per side");
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+    let mut ids = Vec::new();
+    let mut table: Vec<usize> = Vec::new();
+    for patch in patches {
+        let slot = match table.iter().position(|&m| m == patch.mat_ptr) {
+            Some(slot) => slot,
+            None => {
+                table.push(patch.mat_ptr);
+                table.len() - 1
+            }
+        };
+        let first = positions.len();
+        for j in 0..=n {
+            for i in 0..=n {
+                let (u, v) = (i as f64 / n as f64, j as f64 / n as f64);
+                positions.push(patch.point(u, v));
+                normals.push(patch.normal(u, v));
+                uvs.push((u, v));
+            }
+        }
+        let index = |i: usize, j: usize| (first + j * (n + 1) + i) as u32;
+        for j in 0..n {
+            for i in 0..n {
+                indices.push([index(i, j), index(i + 1, j), index(i + 1, j + 1)]);
+                indices.push([index(i, j), index(i + 1, j + 1), index(i, j + 1)]);
+                ids.extend([slot as u32, slot as u32]);
+            }
+        }
+    }
+    let mut mesh = TriangleMesh::new(positions, indices, table.first().cloned().unwrap_or(0))
+        .with_normals(normals)
+        .with_uvs(uvs);
+    if table.len() > 1 {
+        mesh = mesh.with_face_materials(ids, table);
+    }
+    mesh
+}
+
+// Reads Newell's patch format: the number of patches, then for each the
+// degrees in u and v, which must be "3 3", and its 16 control points.
+pub fn read_bpt(text: &str, mat_ptr: usize) -> io::Result<Vec<BezierPatch>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut numbers = text.split_whitespace().map(|w| {
+        w.parse::<f64>()
+            .map_err(|_| invalid(&format!("bad number '{}'", w)))
+    });
+    let mut next = || {
+        numbers
+            .next()
+            .unwrap_or_else(|| Err(invalid("truncated patch file")))
+    };
+    let count = next()?;
+    if count < 0.0 || count.fract() != 0.0 {
+        return Err(invalid("bad patch count"));
+    }
+    // Not sized from the count, which may be anything.
+    let mut patches = Vec::new();
+    for _ in 0..count as usize {
+        if (next()?, next()?) != (3.0, 3.0) {
+            return Err(invalid("only bicubic patches are supported"));
+        }
+        let mut points = [Point3::new(); 16];
+        for p in points.iter_mut() {
+            *p = Point3::of(next()?, next()?, next()?);
+        }
+        patches.push(BezierPatch::new(points, mat_ptr));
+    }
+    Ok(patches)
+}
+
+pub fn load_bpt(path: &Path, mat_ptr: usize) -> io::Result<Vec<BezierPatch>> {
+    read_bpt(&fs::read_to_string(path)?, mat_ptr)
+}
+
+// Newell's teapot, 32 patches, standing on the xz plane with its spout
+// towards +x. It is 3.15 tall, as in the original data, which is z-up; here
+// y is up.
+pub fn teapot(mat_ptr: usize) -> Vec<BezierPatch> {
+    // Profiles of the turned parts, as (radius, height) from top to bottom.
+    const TURNED: [[(f64, f64); 4]; 6] = [
+        [(1.4, 2.4), (1.3375, 2.53125), (1.4375, 2.53125), (1.5, 2.4)],
+        [(1.5, 2.4), (1.75, 1.875), (2.0, 1.35), (2.0, 0.9)],
+        [(2.0, 0.9), (2.0, 0.45), (1.5, 0.225), (1.5, 0.15)],
+        [(0.0, 3.15), (0.8, 3.15), (0.0, 2.85), (0.2, 2.7)],
+        [(0.2, 2.7), (0.4, 2.55), (1.3, 2.55), (1.3, 2.4)],
+        [(1.5, 0.15), (1.5, 0.075), (1.425, 0.0), (0.0, 0.0)],
+    ];
+    // Handle and spout pieces: two curves in the plane of symmetry, as (x,
+    // height), and how far the cross-section bulges out of that plane at
+    // each point along them.
+    type Swept = ([(f64, f64); 4], [(f64, f64); 4], [f64; 4]);
+    const SWEPT: [Swept; 4] = [
+        (
+            [(-1.6, 2.025), (-2.3, 2.025), (-2.7, 2.025), (-2.7, 1.6875)],
+            [(-1.5, 2.25), (-2.5, 2.25), (-3.0, 2.25), (-3.0, 1.6875)],
+            [0.3; 4],
+        ),
+        (
+            [(-2.7, 1.6875), (-2.7, 1.35), (-2.5, 0.975), (-2.0, 0.75)],
+            [(-3.0, 1.6875), (-3.0, 1.125), (-2.65, 0.9375), (-1.9, 0.6)],
+            [0.3; 4],
+        ),
+        (
+            [(1.7, 0.45), (3.1, 0.675), (2.4, 1.875), (3.3, 2.25)],
+            [(1.7, 1.275), (2.6, 1.275), (2.3, 1.95), (2.7, 2.25)],
+            [0.66, 0.66, 0.25, 0.25],
+        ),
+        (
+            [(3.3, 2.25), (3.525, 2.34375), (3.45, 2.3625), (3.2, 2.25)],
+            [(2.7, 2.25), (2.8, 2.325), (2.9, 2.325), (2.8, 2.25)],
+            [0.25, 0.25, 0.15, 0.15],
+        ),
+    ];
+    // Control points of a quarter circle of radius 1 from +x to +z.
+    const K: f64 = 0.56;
+    const QUARTER: [(f64, f64); 4] = [(1.0, 0.0), (1.0, K), (K, 1.0), (0.0, 1.0)];
+
+    let mut patches = Vec::new();
+    for profile in &TURNED {
+        for quarter in 0..4 {
+            let mut points = [Point3::new(); 16];
+            for (j, &(radius, height)) in profile.iter().enumerate() {
+                for (i, &(x, z)) in QUARTER.iter().enumerate() {
+                    // Turn by a quarter at a time: (x, z) -> (-z, x).
+                    let (mut x, mut z) = (x, z);
+                    for _ in 0..quarter {
+                        let turned = (-z, x);
+                        x = turned.0;
+                        z = turned.1;
+                    }
+                    points[4 * j + i] = Point3::of(radius * x, height, radius * z);
+                }
+            }
+            patches.push(BezierPatch::new(points, mat_ptr));
+        }
+    }
+    for (inner, outer, bulge) in &SWEPT {
+        for &side in &[1.0, -1.0] {
+            let mut points = [Point3::new(); 16];
+            for j in 0..4 {
+                let ((xi, yi), (xo, yo), w) = (inner[j], outer[j], side * bulge[j]);
+                let mut section = [
+                    Point3::of(xi, yi, 0.0),
+                    Point3::of(xi, yi, w),
+                    Point3::of(xo, yo, w),
+                    Point3::of(xo, yo, 0.0),
+                ];
+                // The mirrored half runs the other way to face outwards too.
+                if side < 0.0 {
+                    section.reverse();
+                }
+                points[4 * j..4 * j + 4].copy_from_slice(&section);
+            }
+            patches.push(BezierPatch::new(points, mat_ptr));
+        }
+    }
+    patches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newton_hits_agree_with_the_surface() {
+        let patches = teapot(0);
+        assert_eq!(patches.len(), 32);
+        // Rays aimed at points on every patch hit at or before them, on the
+        // surface, and with a normal facing the ray.
+        for (k, patch) in patches.iter().enumerate() {
+            for &(u, v) in &[(0.3, 0.4), (0.7, 0.6), (0.5, 0.1)] {
+                let target = patch.point(u, v);
+                let n = patch.normal(u, v);
+                let origin = target + n * 5.0 + Vec3::of(0.1, 0.2, 0.3);
+                let r = Ray::of(origin, target - origin);
+                let mut rec = HitRecord::default();
+                let mut nearest = None;
+                for p in &patches {
+                    let t_max = nearest.map_or(f64::INFINITY, |(t, _)| t);
+                    if p.hit(&r, 1e-9, t_max, &mut rec) {
+                        nearest = Some((rec.t, p));
+                    }
+                }
+                let (t, hit) = nearest.unwrap_or_else(|| panic!("patch {} missed", k));
+                assert!(t <= 1.0 + 1e-6, "patch {}: t = {}", k, t);
+                let off = (rec.p - hit.point(rec.u, rec.v)).length();
+                assert!(off < 1e-6, "patch {}: {} off the surface", k, off);
+                assert!(dot(&rec.normal, &r.direction) < 0.0, "patch {}", k);
+            }
+        }
+        // The tessellated normals point out of the body.
+        let mesh = tessellate_patches(&patches[4..12], 4);
+        for (p, n) in mesh.positions.iter().zip(&mesh.normals) {
+            let radial = Vec3::of(p.x(), 0.0, p.z());
+            assert!(dot(&radial, n) > 0.0);
+        }
+    }
+
+    #[test]
+    fn reads_newell_patch_files() {
+        let mut text = String::from("2\n");
+        for patch in &teapot(0)[..2] {
+            text.push_str("3 3\n");
+            for p in &patch.points {
+                text.push_str(&format!("{}\n", p));
+            }
+        }
+        assert_eq!(read_bpt(&text, 0).unwrap(), teapot(0)[..2].to_vec());
+
+        let flat: Vec<String> = (0..16).map(|k| format!("{} {} 0", k % 4, k / 4)).collect();
+        let patches = read_bpt(&format!("1 3 3 {}", flat.join(" ")), 7).unwrap();
+        assert_eq!(patches[0].mat_ptr, 7);
+        assert_eq!(patches[0].point(0.5, 0.5), Point3::of(1.5, 1.5, 0.0));
+
+        let quadratic = format!("1 2 2 {}", flat.join(" "));
+        for bad in &[
+            "",
+            "1.5",
+            "-1",
+            "1 3 3 0 0",
+            "1 3 3 x",
+            &quadratic,
+            "1e18 3 3",
+        ] {
+            assert!(read_bpt(bad, 0).is_err(), "{:?}", bad);
+        }
+    }
+}
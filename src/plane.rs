@@ -0,0 +1,230 @@
+use crate::aabb::Aabb;
+use crate::csg::{at_infinity, Solid, Span};
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Infinite plane through `point`, facing along `normal`. UVs are planar: the
+// distance along two fixed tangent directions, divided by `uv_scale` and
+// wrapped into [0, 1) so textures tile every `uv_scale` units. It has no
+// bounding box; see `Object::is_bounded`. As a solid it is the half-space
+// behind it, which is handy for cutting things open.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plane {
+    pub point: Point3,
+    pub normal: Vec3,
+    pub uv_scale: f64,
+    pub mat_ptr: usize,
+    unit_normal: Vec3,
+    tangent: Vec3,
+    bitangent: Vec3,
+}
+
+impl Plane {
+    pub fn of(point: Point3, normal: Vec3, mat_ptr: usize) -> Plane {
+        let unit_normal = unit_vector(&normal);
+        // Any direction not parallel to the normal gives a tangent.
+        let helper = if unit_normal.x().abs() > 0.9 {
+            Vec3::of(0.0, 1.0, 0.0)
+        } else {
+            Vec3::of(1.0, 0.0, 0.0)
+        };
+        let bitangent = unit_vector(&cross(&unit_normal, &helper));
+        let tangent = cross(&bitangent, &unit_normal);
+        Plane {
+            point,
+            normal,
+            uv_scale: 1.0,
+            mat_ptr,
+            unit_normal,
+            tangent,
+            bitangent,
+        }
+    }
+
+    pub fn with_uv_scale(mut self, uv_scale: f64) -> Plane {
+        self.uv_scale = uv_scale;
+        self
+    }
+
+    // The plane's own coordinates of `p`, before wrapping into [0, 1).
+    pub fn plane_coordinates(&self, p: &Point3) -> (f64, f64) {
+        let d = *p - self.point;
+        (dot(&d, &self.tangent), dot(&d, &self.bitangent))
+    }
+
+    pub fn tangents(&self) -> (Vec3, Vec3) {
+        (self.tangent, self.bitangent)
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let denom = dot(&self.unit_normal, &r.direction);
+        if denom == 0.0 {
+            return false;
+        }
+        let t = dot(&(self.point - r.origin), &self.unit_normal) / denom;
+        if !(t >= t_min && t <= t_max) {
+            return false;
+        }
+
+        let p = r.at(t);
+        let (x, y) = self.plane_coordinates(&p);
+        let front_face = denom < 0.0;
+        *rec = HitRecord {
+            p,
+            normal: if front_face {
+                self.unit_normal
+            } else {
+                -self.unit_normal
+            },
+            mat_ptr: self.mat_ptr,
+            t,
+            u: (x / self.uv_scale).rem_euclid(1.0),
+            v: (y / self.uv_scale).rem_euclid(1.0),
+            tangent: Vec3::new(),
+            front_face,
+        };
+        true
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, _output_box: &mut Aabb) -> bool {
+        false
+    }
+}
+
+impl Solid for Plane {
+    fn spans(&self, r: &Ray, out: &mut Vec<Span>) {
+        let mut rec = HitRecord::default();
+        if !self.hit(r, f64::NEG_INFINITY, f64::INFINITY, &mut rec) {
+            // Parallel: either entirely behind the plane or entirely in front.
+            if dot(&(r.origin - self.point), &self.unit_normal) < 0.0 {
+                out.push(Span {
+                    enter: at_infinity(f64::NEG_INFINITY),
+                    exit: at_infinity(f64::INFINITY),
+                });
+            }
+            return;
+        }
+        rec.normal = self.unit_normal;
+        out.push(if rec.front_face {
+            Span {
+                enter: rec,
+                exit: at_infinity(f64::INFINITY),
+            }
+        } else {
+            Span {
+                enter: at_infinity(f64::NEG_INFINITY),
+                exit: rec,
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(plane: &Plane, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        let r = Ray::of(origin, direction);
+        plane.hit(&r, 0.001, f64::INFINITY, &mut rec).then_some(rec)
+    }
+
+    fn spans(plane: &Plane, origin: Point3, direction: Vec3) -> Vec<(f64, f64)> {
+        let mut out = Vec::new();
+        plane.spans(&Ray::of(origin, direction), &mut out);
+        out.iter().map(|s| (s.enter.t, s.exit.t)).collect()
+    }
+
+    #[test]
+    fn hits_from_either_side_face_the_ray() {
+        let plane = Plane::of(Point3::of(0.0, 1.0, 0.0), Vec3::of(0.0, 2.0, 0.0), 3);
+        let up = Vec3::of(0.0, 1.0, 0.0);
+
+        let rec = hit(&plane, Point3::of(0.5, 3.0, -2.0), -up).unwrap();
+        assert_eq!(
+            (rec.t, rec.normal, rec.front_face, rec.mat_ptr),
+            (2.0, up, true, 3)
+        );
+        assert_eq!(rec.p, Point3::of(0.5, 1.0, -2.0));
+
+        let rec = hit(&plane, Point3::of(0.5, -1.0, -2.0), up * 4.0).unwrap();
+        assert_eq!((rec.t, rec.normal, rec.front_face), (0.5, -up, false));
+
+        // Behind the origin is out of range.
+        assert!(hit(&plane, Point3::of(0.0, 3.0, 0.0), up).is_none());
+    }
+
+    #[test]
+    fn parallel_rays_miss_without_touching_the_record() {
+        let plane = Plane::of(Point3::new(), Vec3::of(0.0, 1.0, 0.0), 0);
+        for &y in &[0.0, 1.0, -1.0] {
+            let mut rec = HitRecord::default();
+            let r = Ray::of(Point3::of(0.0, y, 0.0), Vec3::of(1.0, 0.0, 2.0));
+            assert!(!plane.hit(&r, 0.001, f64::INFINITY, &mut rec));
+            assert_eq!(
+                (rec.p, rec.normal, rec.t),
+                (Point3::new(), Vec3::new(), 0.0)
+            );
+        }
+    }
+
+    #[test]
+    fn uvs_tile_every_uv_scale_units() {
+        let plane = Plane::of(Point3::new(), Vec3::of(0.0, 1.0, 0.0), 0).with_uv_scale(2.0);
+        let (tangent, bitangent) = plane.tangents();
+        assert_eq!(dot(&tangent, &bitangent), 0.0);
+        assert_eq!(dot(&tangent, &plane.normal), 0.0);
+        assert_eq!(dot(&bitangent, &plane.normal), 0.0);
+
+        let down = Vec3::of(0.0, -1.0, 0.0);
+        let at = |p: Point3| hit(&plane, p + Vec3::of(0.0, 1.0, 0.0), down).unwrap();
+        let origin = at(Point3::new());
+        assert_eq!((origin.u, origin.v), (0.0, 0.0));
+
+        // A quarter and three quarters of a tile along each tangent, and the
+        // same points whole tiles away in both directions.
+        let p = tangent * 0.5 - bitangent * 0.5;
+        for &tiles in &[0.0, 1.0, -3.0] {
+            let rec = at(p + (tangent + bitangent) * (2.0 * tiles));
+            assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.75).abs() < 1e-12);
+        }
+        assert_eq!(plane.plane_coordinates(&(p + tangent * 2.0)), (2.5, -0.5));
+    }
+
+    #[test]
+    fn has_no_bounding_box() {
+        let plane = Plane::of(Point3::new(), Vec3::of(0.0, 0.0, 1.0), 0);
+        assert!(!plane.bounding_box(0.0, 1.0, &mut Aabb::empty()));
+    }
+
+    #[test]
+    fn spans_cover_the_half_space_behind() {
+        let plane = Plane::of(Point3::of(0.0, 0.0, 1.0), Vec3::of(0.0, 0.0, 1.0), 0);
+        let z = Vec3::of(0.0, 0.0, 1.0);
+        let inf = f64::INFINITY;
+
+        // Entering through the front, the ray stays inside for good; leaving
+        // through the back, it was inside all along.
+        assert_eq!(
+            spans(&plane, Point3::of(0.0, 0.0, 3.0), -z),
+            vec![(2.0, inf)]
+        );
+        assert_eq!(
+            spans(&plane, Point3::of(0.0, 0.0, -3.0), z),
+            vec![(-inf, 4.0)]
+        );
+
+        // Parallel rays are wholly inside or wholly outside.
+        let across = Vec3::of(1.0, 0.0, 0.0);
+        assert_eq!(spans(&plane, Point3::new(), across), vec![(-inf, inf)]);
+        assert!(spans(&plane, Point3::of(0.0, 0.0, 2.0), across).is_empty());
+
+        // Both boundaries report the plane's outward normal.
+        let mut out = Vec::new();
+        plane.spans(&Ray::of(Point3::new(), z), &mut out);
+        assert_eq!(out[0].exit.normal, z);
+    }
+}
@@ -0,0 +1,161 @@
+// Real roots of low degree polynomials, for intersecting implicit surfaces.
+// The closed forms follow Schwarze's "Cubic and Quartic Roots" (Graphics
+// Gems I); callers that need full precision polish the roots afterwards.
+
+use std::f64::consts::PI;
+
+// Coefficients smaller than this, after normalising the leading one to 1,
+// are treated as zero so that double roots are not lost to rounding.
+const EPSILON: f64 = 1e-9;
+
+fn is_zero(x: f64) -> bool {
+    x.abs() < EPSILON
+}
+
+// Real roots of a t^2 + b t + c in increasing order. Uses the form that
+// avoids cancellation when b^2 is much larger than 4ac.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a == 0.0 {
+        if b == 0.0 {
+            return None;
+        }
+        let t = -c / b;
+        return Some((t, t));
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    let q = if b < 0.0 {
+        -0.5 * (b - root)
+    } else {
+        -0.5 * (b + root)
+    };
+    if q == 0.0 {
+        return Some((0.0, 0.0));
+    }
+    let (t0, t1) = (q / a, c / q);
+    Some(if t0 <= t1 { (t0, t1) } else { (t1, t0) })
+}
+
+// Real roots of x^3 + a x^2 + b x + c, unsorted.
+pub fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    // Substitute x = y - a/3 to get y^3 + 3p y + 2q.
+    let sq_a = a * a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + c) / 2.0;
+    let cb_p = p * p * p;
+    let d = q * q + cb_p;
+
+    let roots = if is_zero(d) {
+        if is_zero(q) {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if d < 0.0 {
+        // Three distinct real roots.
+        let phi = (-q / (-cb_p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![
+            t * phi.cos(),
+            -t * (phi + PI / 3.0).cos(),
+            -t * (phi - PI / 3.0).cos(),
+        ]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+    roots.into_iter().map(|y| y - a / 3.0).collect()
+}
+
+// Real roots of x^4 + a x^3 + b x^2 + c x + d in increasing order, by
+// Ferrari's method. Double roots, as from grazing rays, come back once or
+// twice depending on rounding.
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // Substitute x = y - a/4 to get y^4 + p y^2 + q y + r.
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + c;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * c / 4.0 + d;
+
+    let mut roots = if is_zero(r) {
+        // y (y^3 + p y + q) = 0
+        let mut roots = solve_cubic(0.0, p, q);
+        roots.push(0.0);
+        roots
+    } else {
+        // Any real root of the resolvent cubic splits the quartic into two
+        // quadratics; the largest is the best conditioned.
+        let z = solve_cubic(-p / 2.0, -r, r * p / 2.0 - q * q / 8.0)
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+        // The quadratics are y^2 + v y + z - u and y^2 - v y + z + u, with
+        // u^2 = z^2 - r, v^2 = 2z - p and 2uv = q. The larger of u and v is
+        // taken from its square and the other from q, so that rounding in z
+        // can't make a near-zero square negative and lose a pair of roots.
+        let (u2, v2) = ((z * z - r).max(0.0), (2.0 * z - p).max(0.0));
+        let (u, v) = if v2 >= u2 {
+            let v = v2.sqrt();
+            (if v > 0.0 { q / (2.0 * v) } else { 0.0 }, v)
+        } else {
+            let u = u2.sqrt();
+            (u, q / (2.0 * u))
+        };
+        let mut roots = Vec::new();
+        for (b, c) in [(v, z - u), (-v, z + u)] {
+            if let Some((y0, y1)) = solve_quadratic(1.0, b, c) {
+                roots.push(y0);
+                roots.push(y1);
+            }
+        }
+        roots
+    };
+    for x in roots.iter_mut() {
+        *x -= a / 4.0;
+    }
+    roots.sort_by(|x, y| x.total_cmp(y));
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(found: &[f64], expected: &[f64]) {
+        assert_eq!(found.len(), expected.len(), "{:?}", found);
+        for (x, y) in found.iter().zip(expected) {
+            assert!((x - y).abs() < 1e-6, "{:?} != {:?}", found, expected);
+        }
+    }
+
+    #[test]
+    fn quartic_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(
+            &solve_quartic(-10.0, 35.0, -50.0, 24.0),
+            &[1.0, 2.0, 3.0, 4.0],
+        );
+        // (x^2 + 1)(x - 2)(x + 3)
+        assert_roots(&solve_quartic(1.0, -5.0, 1.0, -6.0), &[-3.0, 2.0]);
+        // (x^2 + 1)(x^2 + 4)
+        assert_roots(&solve_quartic(0.0, 5.0, 0.0, 4.0), &[]);
+        // (x - 1)^2 (x + 1)^2, the tangent case
+        let roots = solve_quartic(0.0, -2.0, 0.0, 1.0);
+        assert!(!roots.is_empty());
+        assert!(
+            roots.iter().all(|x| (x.abs() - 1.0).abs() < 1e-4),
+            "{:?}",
+            roots
+        );
+        // ((x - 1)^2 - d^2)((x + 1)^2 - d^2): two close pairs, as from a ray
+        // just inside the top of a torus.
+        for &d in &[1e-2, 1e-3, 1e-4, 1e-5] {
+            let (e, f) = (1.0 - d * d, 1.0 + d * d);
+            let roots = solve_quartic(0.0, -2.0 * f, 0.0, e * e);
+            assert_roots(&roots, &[-1.0 - d, -1.0 + d, 1.0 - d, 1.0 + d]);
+        }
+    }
+}
@@ -0,0 +1,422 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Parallelogram with corner `q` and edges `u` and `v`. The front faces along
+// cross(u, v), and (u, v) on a hit are the coordinates along the two edges.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quad {
+    pub q: Point3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub mat_ptr: usize,
+    normal: Vec3,
+    d: f64,
+    // cross(u, v) / |cross(u, v)|^2, for projecting onto the edges.
+    w: Vec3,
+}
+
+impl Quad {
+    pub fn of(q: Point3, u: Vec3, v: Vec3, mat_ptr: usize) -> Quad {
+        let n = cross(&u, &v);
+        let normal = unit_vector(&n);
+        Quad {
+            q,
+            u,
+            v,
+            mat_ptr,
+            normal,
+            d: dot(&normal, &q),
+            w: n / dot(&n, &n),
+        }
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let denom = dot(&self.normal, &r.direction);
+        // Parallel to the plane.
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+        let t = (self.d - dot(&self.normal, &r.origin)) / denom;
+        if t < t_min || t > t_max {
+            return false;
+        }
+
+        let p = r.at(t);
+        let planar = p - self.q;
+        let alpha = dot(&self.w, &cross(&planar, &self.v));
+        let beta = dot(&self.w, &cross(&self.u, &planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        let front_face = denom < 0.0;
+        *rec = HitRecord {
+            p,
+            normal: if front_face {
+                self.normal
+            } else {
+                -self.normal
+            },
+            mat_ptr: self.mat_ptr,
+            t,
+            u: alpha,
+            v: beta,
+            tangent: Vec3::new(),
+            front_face,
+        };
+        true
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        let (q, u, v) = (self.q, self.u, self.v);
+        *output_box = Aabb::from_points(&[q, q + u, q + v, q + u + v]);
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RectPlane {
+    XY,
+    XZ,
+    YZ,
+}
+
+impl RectPlane {
+    // Axes of the two in-plane coordinates and of the normal.
+    fn axes(self) -> (usize, usize, usize) {
+        match self {
+            RectPlane::XY => (0, 1, 2),
+            RectPlane::XZ => (0, 2, 1),
+            RectPlane::YZ => (1, 2, 0),
+        }
+    }
+}
+
+// Rectangle at `k` along the normal axis spanning [a0, a1] x [b0, b1] in the
+// plane's two coordinates, e.g. x and y for `XY`. It faces +z, +y or +x
+// unless `flipped`. Cheaper to hit than the equivalent `Quad`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AxisRect {
+    pub plane: RectPlane,
+    pub a0: f64,
+    pub a1: f64,
+    pub b0: f64,
+    pub b1: f64,
+    pub k: f64,
+    pub flipped: bool,
+    pub mat_ptr: usize,
+}
+
+impl AxisRect {
+    pub fn xy(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, mat_ptr: usize) -> AxisRect {
+        AxisRect::of(RectPlane::XY, (x0, x1), (y0, y1), k, mat_ptr)
+    }
+
+    pub fn xz(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, mat_ptr: usize) -> AxisRect {
+        AxisRect::of(RectPlane::XZ, (x0, x1), (z0, z1), k, mat_ptr)
+    }
+
+    pub fn yz(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, mat_ptr: usize) -> AxisRect {
+        AxisRect::of(RectPlane::YZ, (y0, y1), (z0, z1), k, mat_ptr)
+    }
+
+    fn of(plane: RectPlane, a: (f64, f64), b: (f64, f64), k: f64, mat_ptr: usize) -> AxisRect {
+        AxisRect {
+            plane,
+            a0: a.0,
+            a1: a.1,
+            b0: b.0,
+            b1: b.1,
+            k,
+            flipped: false,
+            mat_ptr,
+        }
+    }
+
+    pub fn flip(mut self) -> AxisRect {
+        self.flipped = !self.flipped;
+        self
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        let (_, _, axis) = self.plane.axes();
+        let sign = if self.flipped { -1.0 } else { 1.0 };
+        let mut n = [0.0; 3];
+        n[axis] = sign;
+        Vec3::of(n[0], n[1], n[2])
+    }
+
+    // The same rectangle as a general quad, facing the same way.
+    pub fn to_quad(&self) -> Quad {
+        let (a, b, axis) = self.plane.axes();
+        let point = |x: f64, y: f64, z: f64| {
+            let mut p = [0.0; 3];
+            p[a] = x;
+            p[b] = y;
+            p[axis] = z;
+            Vec3::of(p[0], p[1], p[2])
+        };
+        let q = point(self.a0, self.b0, self.k);
+        let u = point(self.a1 - self.a0, 0.0, 0.0);
+        let v = point(0.0, self.b1 - self.b0, 0.0);
+        // XZ is the one plane where cross(a, b) points down its normal axis.
+        let swap = (self.plane == RectPlane::XZ) != self.flipped;
+        if swap {
+            Quad::of(q, v, u, self.mat_ptr)
+        } else {
+            Quad::of(q, u, v, self.mat_ptr)
+        }
+    }
+}
+
+impl Hittable for AxisRect {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (a, b, axis) = self.plane.axes();
+        let t = (self.k - r.origin[axis]) / r.direction[axis];
+        if !(t >= t_min && t <= t_max) {
+            return false;
+        }
+        let x = r.origin[a] + t * r.direction[a];
+        let y = r.origin[b] + t * r.direction[b];
+        if x < self.a0 || x > self.a1 || y < self.b0 || y > self.b1 {
+            return false;
+        }
+
+        let outward_normal = self.normal();
+        let front_face = dot(&r.direction, &outward_normal) < 0.0;
+        *rec = HitRecord {
+            p: r.at(t),
+            normal: if front_face {
+                outward_normal
+            } else {
+                -outward_normal
+            },
+            mat_ptr: self.mat_ptr,
+            t,
+            u: (x - self.a0) / (self.a1 - self.a0),
+            v: (y - self.b0) / (self.b1 - self.b0),
+            tangent: Vec3::new(),
+            front_face,
+        };
+        true
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        let (a, b, axis) = self.plane.axes();
+        let (mut lo, mut hi) = ([0.0; 3], [0.0; 3]);
+        lo[a] = self.a0;
+        hi[a] = self.a1;
+        lo[b] = self.b0;
+        hi[b] = self.b1;
+        lo[axis] = self.k;
+        hi[axis] = self.k;
+        *output_box = Aabb::of(
+            Point3::of(lo[0], lo[1], lo[2]),
+            Point3::of(hi[0], hi[1], hi[2]),
+        );
+        true
+    }
+}
+
+// Axis-aligned box between two corners, made of six outward facing quads.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cuboid {
+    pub min: Point3,
+    pub max: Point3,
+    pub sides: [Quad; 6],
+}
+
+impl Cuboid {
+    pub fn of(a: Point3, b: Point3, mat_ptr: usize) -> Cuboid {
+        let min = Point3::of(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max = Point3::of(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+        let dx = Vec3::of(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3::of(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3::of(0.0, 0.0, max.z() - min.z());
+
+        let sides = [
+            Quad::of(Point3::of(min.x(), min.y(), max.z()), dx, dy, mat_ptr), // front
+            Quad::of(Point3::of(max.x(), min.y(), max.z()), -dz, dy, mat_ptr), // right
+            Quad::of(Point3::of(max.x(), min.y(), min.z()), -dx, dy, mat_ptr), // back
+            Quad::of(Point3::of(min.x(), min.y(), min.z()), dz, dy, mat_ptr), // left
+            Quad::of(Point3::of(min.x(), max.y(), max.z()), dx, -dz, mat_ptr), // top
+            Quad::of(Point3::of(min.x(), min.y(), min.z()), dx, dz, mat_ptr), // bottom
+        ];
+        Cuboid { min, max, sides }
+    }
+
+    pub fn mat_ptr(&self) -> usize {
+        self.sides[0].mat_ptr
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = t_max;
+        for side in &self.sides {
+            if side.hit(r, t_min, closest_so_far, rec) {
+                hit_anything = true;
+                closest_so_far = rec.t;
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        *output_box = Aabb::of(self.min, self.max);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit<S: Hittable>(shape: &S, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        let r = Ray::of(origin, direction);
+        shape.hit(&r, 0.001, f64::INFINITY, &mut rec).then_some(rec)
+    }
+
+    #[test]
+    fn quads_face_along_the_cross_of_their_edges() {
+        let quad = Quad::of(
+            Point3::new(),
+            Vec3::of(2.0, 0.0, 0.0),
+            Vec3::of(0.0, 1.0, 0.0),
+            0,
+        );
+        assert_eq!(quad.normal(), Vec3::of(0.0, 0.0, 1.0));
+
+        let down = Vec3::of(0.0, 0.0, -1.0);
+        let front = hit(&quad, Point3::of(0.5, 0.25, 1.0), down).unwrap();
+        assert_eq!((front.t, front.u, front.v), (1.0, 0.25, 0.25));
+        assert!(front.front_face);
+        assert_eq!(front.normal, quad.normal());
+
+        let back = hit(&quad, Point3::of(0.5, 0.25, -1.0), -down).unwrap();
+        assert!(!back.front_face);
+        assert_eq!(back.normal, -quad.normal());
+
+        // Edges are inclusive, and the corners are the corners of UV space.
+        for &(x, y) in &[(0.0, 0.0), (2.0, 0.0), (0.0, 1.0), (2.0, 1.0)] {
+            let rec = hit(&quad, Point3::of(x, y, 1.0), down).unwrap();
+            assert_eq!((rec.u, rec.v), (x / 2.0, y));
+        }
+        for &(x, y) in &[(-0.01, 0.5), (2.01, 0.5), (1.0, -0.01), (1.0, 1.01)] {
+            assert!(hit(&quad, Point3::of(x, y, 1.0), down).is_none());
+        }
+
+        // Parallel rays miss whether or not they lie in the plane.
+        for &z in &[0.0, 1.0] {
+            assert!(hit(&quad, Point3::of(-1.0, 0.5, z), Vec3::of(1.0, 0.0, 0.0)).is_none());
+        }
+    }
+
+    #[test]
+    fn axis_rects_match_their_quads() {
+        let rects = [
+            AxisRect::xy(0.0, 2.0, -1.0, 1.0, 0.5, 0),
+            AxisRect::xz(0.0, 2.0, -1.0, 1.0, 0.5, 0),
+            AxisRect::yz(0.0, 2.0, -1.0, 1.0, 0.5, 0),
+        ];
+        for rect in rects.iter().flat_map(|r| vec![r.clone(), r.clone().flip()]) {
+            let quad = rect.to_quad();
+            assert_eq!(quad.normal(), rect.normal());
+            let n = rect.normal();
+            let inside = quad.q + quad.u * 0.25 + quad.v * 0.75;
+            for &side in &[1.0, -1.0] {
+                let origin = inside + n * (2.0 * side);
+                let rec = hit(&rect, origin, n * -side).unwrap();
+                let same = hit(&quad, origin, n * -side).unwrap();
+                assert_eq!(
+                    (rec.t, rec.normal, rec.front_face),
+                    (2.0, n * side, side > 0.0)
+                );
+                assert_eq!(
+                    (same.t, same.normal, same.front_face),
+                    (rec.t, rec.normal, rec.front_face)
+                );
+                assert!((rec.p - inside).length() < 1e-12);
+            }
+
+            // UVs follow the rectangle's own two axes, corner to corner.
+            let (a, b, _) = rect.plane.axes();
+            for &(s, t) in &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+                let corner = quad.q + quad.u * s + quad.v * t;
+                let rec = hit(&rect, corner + n, -n).unwrap();
+                let uv = (
+                    (corner[a] - rect.a0) / (rect.a1 - rect.a0),
+                    (corner[b] - rect.b0) / (rect.b1 - rect.b0),
+                );
+                assert_eq!((rec.u, rec.v), uv);
+            }
+
+            // Just outside each edge, and along the plane.
+            for &(s, t) in &[(-0.01, 0.5), (1.01, 0.5), (0.5, -0.01), (0.5, 1.01)] {
+                let outside = quad.q + quad.u * s + quad.v * t;
+                assert!(hit(&rect, outside + n, -n).is_none());
+                assert!(hit(&quad, outside + n, -n).is_none());
+            }
+            let along = quad.q - quad.u * 0.5 + quad.v * 0.5;
+            assert!(hit(&rect, along, quad.u).is_none());
+            assert!(hit(&rect, along + n, quad.u).is_none());
+        }
+    }
+
+    #[test]
+    fn cuboids_face_out_on_every_side() {
+        let cuboid = Cuboid::of(Point3::of(1.0, 2.0, 3.0), Point3::of(-1.0, 0.0, 1.0), 0);
+        assert_eq!(
+            (cuboid.min, cuboid.max),
+            (Point3::of(-1.0, 0.0, 1.0), Point3::of(1.0, 2.0, 3.0))
+        );
+        let centre = Point3::of(0.0, 1.0, 2.0);
+        for axis in 0..3 {
+            for &sign in &[1.0, -1.0] {
+                let mut d = [0.0; 3];
+                d[axis] = sign;
+                let out = Vec3::of(d[0], d[1], d[2]);
+
+                let rec = hit(&cuboid, centre + out * 5.0, -out).unwrap();
+                assert_eq!((rec.t, rec.normal), (4.0, out));
+                assert!(rec.front_face);
+                assert!((0.0..=1.0).contains(&rec.u) && (0.0..=1.0).contains(&rec.v));
+
+                // From the centre the same side is hit from behind.
+                let rec = hit(&cuboid, centre, out).unwrap();
+                assert_eq!((rec.t, rec.normal), (1.0, -out));
+                assert!(!rec.front_face);
+
+                // Past the side, a ray parallel to it misses the box.
+                let beside = centre + out * 1.01;
+                for other in (0..3).filter(|&o| o != axis) {
+                    let mut e = [0.0; 3];
+                    e[other] = 1.0;
+                    let along = Vec3::of(e[0], e[1], e[2]);
+                    assert!(hit(&cuboid, beside - along * 5.0, along).is_none());
+                }
+            }
+        }
+        // Each face's UVs run across it from one corner to the opposite one.
+        for side in &cuboid.sides {
+            let n = side.normal();
+            assert!(dot(&n, &(side.q - centre)) > 0.0);
+            for &(s, t) in &[(0.0, 0.0), (1.0, 1.0)] {
+                let corner = side.q + side.u * s + side.v * t;
+                let inward = (centre - corner) * 1e-3;
+                let rec = hit(side, corner + n + inward, -n).unwrap();
+                assert!((rec.u - s).abs() < 0.01 && (rec.v - t).abs() < 0.01);
+            }
+        }
+    }
+}
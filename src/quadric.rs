@@ -0,0 +1,733 @@
+// Quadric surfaces of revolution about the z axis, after pbrt. Each can be
+// swept through `phi_max` degrees rather than the full circle and, where it
+// makes sense, clipped to a range of z. Place them in a scene with
+// `Transformed`. Hits report u = phi / phi_max and v along the profile.
+
+use crate::aabb::Aabb;
+use crate::camera::degrees_to_radians;
+use crate::hittable::{HitRecord, Hittable};
+use crate::poly::solve_quadratic;
+use crate::ray::Ray;
+use crate::vec3::*;
+use std::f64::consts::PI;
+
+// Angle of `p` around the z axis in [0, 2pi).
+pub fn phi_of(p: &Point3) -> f64 {
+    let phi = p.y().atan2(p.x());
+    if phi < 0.0 {
+        phi + 2.0 * PI
+    } else {
+        phi
+    }
+}
+
+// The nearer of the two roots inside [t_min, t_max] whose point survives
+// clipping.
+fn first_root<F: Fn(&Point3) -> bool>(
+    r: &Ray,
+    roots: Option<(f64, f64)>,
+    t_min: f64,
+    t_max: f64,
+    keep: F,
+) -> Option<(f64, Point3)> {
+    let (t0, t1) = roots?;
+    [t0, t1]
+        .iter()
+        .filter(|&&t| t >= t_min && t <= t_max)
+        .map(|&t| (t, r.at(t)))
+        .find(|(_, p)| keep(p))
+}
+
+pub fn fill_hit(
+    r: &Ray,
+    (t, p): (f64, Point3),
+    outward_normal: Vec3,
+    (u, v): (f64, f64),
+    mat_ptr: usize,
+    rec: &mut HitRecord,
+) {
+    let front_face = dot(&r.direction, &outward_normal) < 0.0;
+    *rec = HitRecord {
+        p,
+        normal: if front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        },
+        mat_ptr,
+        t,
+        u,
+        v,
+        tangent: Vec3::new(),
+        front_face,
+    };
+}
+
+// Parametric form of a quadric, for tessellating it. `point` takes the same
+// (u, v) that a hit there reports.
+pub trait Surface {
+    fn point(&self, u: f64, v: f64) -> Point3;
+    // Unit length, except where the surface has no normal, e.g. a cone's apex.
+    fn outward_normal(&self, p: &Point3) -> Vec3;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cylinder {
+    pub radius: f64,
+    pub z_min: f64,
+    pub z_max: f64,
+    pub phi_max: f64,
+    // Closed at both ends by disks.
+    pub capped: bool,
+    pub mat_ptr: usize,
+}
+
+impl Cylinder {
+    pub fn of(radius: f64, z_min: f64, z_max: f64, mat_ptr: usize) -> Cylinder {
+        Cylinder {
+            radius,
+            z_min: z_min.min(z_max),
+            z_max: z_min.max(z_max),
+            phi_max: 360.0,
+            capped: false,
+            mat_ptr,
+        }
+    }
+
+    pub fn with_phi_max(mut self, degrees: f64) -> Cylinder {
+        self.phi_max = degrees.clamp(0.0, 360.0);
+        self
+    }
+
+    pub fn with_caps(mut self) -> Cylinder {
+        self.capped = true;
+        self
+    }
+
+    // Bottom and top caps, facing out of the cylinder.
+    pub fn caps(&self) -> [Disk; 2] {
+        let cap = |z| Disk::of(z, self.radius, self.mat_ptr).with_phi_max(self.phi_max);
+        [cap(self.z_min).flip(), cap(self.z_max)]
+    }
+}
+
+impl Surface for Cylinder {
+    fn point(&self, u: f64, v: f64) -> Point3 {
+        let phi = u * degrees_to_radians(self.phi_max);
+        let z = self.z_min + v * (self.z_max - self.z_min);
+        Point3::of(self.radius * phi.cos(), self.radius * phi.sin(), z)
+    }
+
+    fn outward_normal(&self, p: &Point3) -> Vec3 {
+        Vec3::of(p.x(), p.y(), 0.0) / self.radius
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (o, d) = (&r.origin, &r.direction);
+        let phi_max = degrees_to_radians(self.phi_max);
+        let a = d.x() * d.x() + d.y() * d.y();
+        let b = 2.0 * (d.x() * o.x() + d.y() * o.y());
+        let c = o.x() * o.x() + o.y() * o.y() - self.radius * self.radius;
+        // Rays along the axis miss the side but may still hit a cap.
+        let roots = if a == 0.0 {
+            None
+        } else {
+            solve_quadratic(a, b, c)
+        };
+        let hit = first_root(r, roots, t_min, t_max, |p| {
+            p.z() >= self.z_min && p.z() <= self.z_max && phi_of(p) <= phi_max
+        });
+
+        let mut closest_so_far = t_max;
+        let mut hit_anything = false;
+        if let Some((t, p)) = hit {
+            let uv = (
+                phi_of(&p) / phi_max,
+                (p.z() - self.z_min) / (self.z_max - self.z_min),
+            );
+            fill_hit(r, (t, p), self.outward_normal(&p), uv, self.mat_ptr, rec);
+            closest_so_far = t;
+            hit_anything = true;
+        }
+        if self.capped {
+            for cap in &self.caps() {
+                if cap.hit(r, t_min, closest_so_far, rec) {
+                    closest_so_far = rec.t;
+                    hit_anything = true;
+                }
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        let r = self.radius.abs();
+        *output_box = Aabb::of(Point3::of(-r, -r, self.z_min), Point3::of(r, r, self.z_max));
+        true
+    }
+}
+
+// Cone with its base of `radius` at z = 0 and its apex at z = `height`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cone {
+    pub radius: f64,
+    pub height: f64,
+    pub z_min: f64,
+    pub z_max: f64,
+    pub phi_max: f64,
+    pub mat_ptr: usize,
+}
+
+impl Cone {
+    pub fn of(radius: f64, height: f64, mat_ptr: usize) -> Cone {
+        Cone {
+            radius,
+            height,
+            z_min: 0.0,
+            z_max: height,
+            phi_max: 360.0,
+            mat_ptr,
+        }
+    }
+
+    pub fn with_phi_max(mut self, degrees: f64) -> Cone {
+        self.phi_max = degrees.clamp(0.0, 360.0);
+        self
+    }
+
+    // Kept within [0, height] so that only one nappe is ever hit.
+    pub fn with_z_range(mut self, z_min: f64, z_max: f64) -> Cone {
+        self.z_min = z_min.min(z_max).clamp(0.0, self.height);
+        self.z_max = z_min.max(z_max).clamp(0.0, self.height);
+        self
+    }
+
+    fn slope_squared(&self) -> f64 {
+        let k = self.radius / self.height;
+        k * k
+    }
+}
+
+impl Surface for Cone {
+    fn point(&self, u: f64, v: f64) -> Point3 {
+        let phi = u * degrees_to_radians(self.phi_max);
+        let z = self.z_min + v * (self.z_max - self.z_min);
+        let rz = self.radius * (1.0 - z / self.height);
+        Point3::of(rz * phi.cos(), rz * phi.sin(), z)
+    }
+
+    fn outward_normal(&self, p: &Point3) -> Vec3 {
+        let n = Vec3::of(p.x(), p.y(), self.slope_squared() * (self.height - p.z()));
+        if n.length_squared() > 0.0 {
+            unit_vector(&n)
+        } else {
+            n
+        }
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (o, d) = (&r.origin, &r.direction);
+        let phi_max = degrees_to_radians(self.phi_max);
+        let k = self.slope_squared();
+        let oz = o.z() - self.height;
+        let a = d.x() * d.x() + d.y() * d.y() - k * d.z() * d.z();
+        let b = 2.0 * (d.x() * o.x() + d.y() * o.y() - k * d.z() * oz);
+        let c = o.x() * o.x() + o.y() * o.y() - k * oz * oz;
+        let hit = first_root(r, solve_quadratic(a, b, c), t_min, t_max, |p| {
+            p.z() >= self.z_min && p.z() <= self.z_max && phi_of(p) <= phi_max
+        });
+        match hit {
+            Some((t, p)) => {
+                let uv = (
+                    phi_of(&p) / phi_max,
+                    (p.z() - self.z_min) / (self.z_max - self.z_min),
+                );
+                fill_hit(r, (t, p), self.outward_normal(&p), uv, self.mat_ptr, rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        let r = self.radius.abs();
+        *output_box = Aabb::of(Point3::of(-r, -r, self.z_min), Point3::of(r, r, self.z_max));
+        true
+    }
+}
+
+// Disk at z = `height` facing +z (-z if flipped). A non-zero `inner_radius`
+// makes it an annulus.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Disk {
+    pub height: f64,
+    pub radius: f64,
+    pub inner_radius: f64,
+    pub phi_max: f64,
+    pub flipped: bool,
+    pub mat_ptr: usize,
+}
+
+impl Disk {
+    pub fn of(height: f64, radius: f64, mat_ptr: usize) -> Disk {
+        Disk {
+            height,
+            radius,
+            inner_radius: 0.0,
+            phi_max: 360.0,
+            flipped: false,
+            mat_ptr,
+        }
+    }
+
+    pub fn with_inner_radius(mut self, inner_radius: f64) -> Disk {
+        self.inner_radius = inner_radius.clamp(0.0, self.radius);
+        self
+    }
+
+    pub fn with_phi_max(mut self, degrees: f64) -> Disk {
+        self.phi_max = degrees.clamp(0.0, 360.0);
+        self
+    }
+
+    pub fn flip(mut self) -> Disk {
+        self.flipped = !self.flipped;
+        self
+    }
+}
+
+impl Surface for Disk {
+    fn point(&self, u: f64, v: f64) -> Point3 {
+        let phi = u * degrees_to_radians(self.phi_max);
+        let rh = self.radius - v * (self.radius - self.inner_radius);
+        Point3::of(rh * phi.cos(), rh * phi.sin(), self.height)
+    }
+
+    fn outward_normal(&self, _p: &Point3) -> Vec3 {
+        Vec3::of(0.0, 0.0, if self.flipped { -1.0 } else { 1.0 })
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if r.direction.z() == 0.0 {
+            return false;
+        }
+        let t = (self.height - r.origin.z()) / r.direction.z();
+        if t < t_min || t > t_max {
+            return false;
+        }
+        let p = r.at(t);
+        let dist2 = p.x() * p.x() + p.y() * p.y();
+        let phi = phi_of(&p);
+        if dist2 > self.radius * self.radius
+            || dist2 < self.inner_radius * self.inner_radius
+            || phi > degrees_to_radians(self.phi_max)
+        {
+            return false;
+        }
+        let uv = (
+            phi / degrees_to_radians(self.phi_max),
+            (self.radius - dist2.sqrt()) / (self.radius - self.inner_radius),
+        );
+        fill_hit(r, (t, p), self.outward_normal(&p), uv, self.mat_ptr, rec);
+        true
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        let r = self.radius.abs();
+        *output_box = Aabb::of(
+            Point3::of(-r, -r, self.height),
+            Point3::of(r, r, self.height),
+        );
+        true
+    }
+}
+
+// z = k (x^2 + y^2), opening upwards with `radius` at z_max.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Paraboloid {
+    pub radius: f64,
+    pub z_min: f64,
+    pub z_max: f64,
+    pub phi_max: f64,
+    pub mat_ptr: usize,
+}
+
+impl Paraboloid {
+    pub fn of(radius: f64, z_min: f64, z_max: f64, mat_ptr: usize) -> Paraboloid {
+        Paraboloid {
+            radius,
+            z_min: z_min.min(z_max).max(0.0),
+            z_max: z_min.max(z_max).max(0.0),
+            phi_max: 360.0,
+            mat_ptr,
+        }
+    }
+
+    pub fn with_phi_max(mut self, degrees: f64) -> Paraboloid {
+        self.phi_max = degrees.clamp(0.0, 360.0);
+        self
+    }
+
+    fn k(&self) -> f64 {
+        self.z_max / (self.radius * self.radius)
+    }
+}
+
+impl Surface for Paraboloid {
+    fn point(&self, u: f64, v: f64) -> Point3 {
+        let phi = u * degrees_to_radians(self.phi_max);
+        let z = self.z_min + v * (self.z_max - self.z_min);
+        let rz = (z / self.k()).sqrt();
+        Point3::of(rz * phi.cos(), rz * phi.sin(), z)
+    }
+
+    fn outward_normal(&self, p: &Point3) -> Vec3 {
+        let k = self.k();
+        unit_vector(&Vec3::of(2.0 * k * p.x(), 2.0 * k * p.y(), -1.0))
+    }
+}
+
+impl Hittable for Paraboloid {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (o, d) = (&r.origin, &r.direction);
+        let phi_max = degrees_to_radians(self.phi_max);
+        let k = self.k();
+        let a = k * (d.x() * d.x() + d.y() * d.y());
+        let b = 2.0 * k * (d.x() * o.x() + d.y() * o.y()) - d.z();
+        let c = k * (o.x() * o.x() + o.y() * o.y()) - o.z();
+        let hit = first_root(r, solve_quadratic(a, b, c), t_min, t_max, |p| {
+            p.z() >= self.z_min && p.z() <= self.z_max && phi_of(p) <= phi_max
+        });
+        match hit {
+            Some((t, p)) => {
+                let uv = (
+                    phi_of(&p) / phi_max,
+                    (p.z() - self.z_min) / (self.z_max - self.z_min),
+                );
+                fill_hit(r, (t, p), self.outward_normal(&p), uv, self.mat_ptr, rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        let r = self.radius.abs();
+        *output_box = Aabb::of(Point3::of(-r, -r, self.z_min), Point3::of(r, r, self.z_max));
+        true
+    }
+}
+
+// Surface swept by rotating the segment p1..p2 about the z axis: a
+// hyperboloid of one sheet in general, a cone if the segment's line meets the
+// axis or a cylinder if it runs parallel to it. Along the line x^2 + y^2 is a
+// quadratic in z, which gives the implicit form
+// x^2 + y^2 = k2 w^2 + k1 w + k0 with w = z - p1.z.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hyperboloid {
+    pub p1: Point3,
+    pub p2: Point3,
+    pub phi_max: f64,
+    pub mat_ptr: usize,
+    k: [f64; 3],
+}
+
+impl Hyperboloid {
+    pub fn of(p1: Point3, p2: Point3, mat_ptr: usize) -> Hyperboloid {
+        Hyperboloid::try_of(p1, p2, mat_ptr).expect("hyperboloid segment is flat")
+    }
+
+    // None if both ends are at the same z, which would sweep an annulus.
+    pub fn try_of(p1: Point3, p2: Point3, mat_ptr: usize) -> Option<Hyperboloid> {
+        let dz = p2.z() - p1.z();
+        if dz == 0.0 {
+            return None;
+        }
+        let (x0, y0) = (p1.x(), p1.y());
+        let (dx, dy) = ((p2.x() - x0) / dz, (p2.y() - y0) / dz);
+        Some(Hyperboloid {
+            p1,
+            p2,
+            phi_max: 360.0,
+            mat_ptr,
+            k: [
+                x0 * x0 + y0 * y0,
+                2.0 * (x0 * dx + y0 * dy),
+                dx * dx + dy * dy,
+            ],
+        })
+    }
+
+    pub fn with_phi_max(mut self, degrees: f64) -> Hyperboloid {
+        self.phi_max = degrees.clamp(0.0, 360.0);
+        self
+    }
+
+    fn z_range(&self) -> (f64, f64) {
+        (self.p1.z().min(self.p2.z()), self.p1.z().max(self.p2.z()))
+    }
+
+    // Where the segment itself crosses height z, as a fraction along it.
+    fn profile(&self, z: f64) -> (Point3, f64) {
+        let v = (z - self.p1.z()) / (self.p2.z() - self.p1.z());
+        (self.p1 * (1.0 - v) + self.p2 * v, v)
+    }
+}
+
+impl Surface for Hyperboloid {
+    fn point(&self, u: f64, v: f64) -> Point3 {
+        let phi = u * degrees_to_radians(self.phi_max);
+        let pr = self.p1 * (1.0 - v) + self.p2 * v;
+        let (sin, cos) = phi.sin_cos();
+        Point3::of(
+            pr.x() * cos - pr.y() * sin,
+            pr.x() * sin + pr.y() * cos,
+            pr.z(),
+        )
+    }
+
+    fn outward_normal(&self, p: &Point3) -> Vec3 {
+        let w = p.z() - self.p1.z();
+        let n = Vec3::of(p.x(), p.y(), -(self.k[2] * w + 0.5 * self.k[1]));
+        if n.length_squared() > 0.0 {
+            unit_vector(&n)
+        } else {
+            n
+        }
+    }
+}
+
+impl Hittable for Hyperboloid {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (o, d) = (&r.origin, &r.direction);
+        let [k0, k1, k2] = self.k;
+        let ow = o.z() - self.p1.z();
+        let (z_min, z_max) = self.z_range();
+        let a = d.x() * d.x() + d.y() * d.y() - k2 * d.z() * d.z();
+        let b = 2.0 * (d.x() * o.x() + d.y() * o.y()) - (2.0 * k2 * ow + k1) * d.z();
+        let c = o.x() * o.x() + o.y() * o.y() - (k2 * ow + k1) * ow - k0;
+
+        // The sweep angle is measured from the segment at the same height,
+        // so that it matches `point`.
+        let phi_at = |p: &Point3| {
+            let (pr, v) = self.profile(p.z());
+            let phi = (pr.x() * p.y() - p.x() * pr.y()).atan2(p.x() * pr.x() + p.y() * pr.y());
+            let phi = if phi < 0.0 { phi + 2.0 * PI } else { phi };
+            (phi, v)
+        };
+        let phi_max = degrees_to_radians(self.phi_max);
+        let hit = first_root(r, solve_quadratic(a, b, c), t_min, t_max, |p| {
+            p.z() >= z_min && p.z() <= z_max && phi_at(p).0 <= phi_max
+        });
+        match hit {
+            Some((t, p)) => {
+                let (phi, v) = phi_at(&p);
+                let normal = self.outward_normal(&p);
+                fill_hit(r, (t, p), normal, (phi / phi_max, v), self.mat_ptr, rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        // The squared radius is a convex quadratic along the axis, so it is
+        // largest at one end.
+        let radius = |p: &Point3| (p.x() * p.x() + p.y() * p.y()).sqrt();
+        let r = radius(&self.p1).max(radius(&self.p2));
+        let (z_min, z_max) = self.z_range();
+        *output_box = Aabb::of(Point3::of(-r, -r, z_min), Point3::of(r, r, z_max));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit<S: Hittable>(shape: &S, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        let r = Ray::of(origin, direction);
+        shape.hit(&r, 0.001, f64::INFINITY, &mut rec).then_some(rec)
+    }
+
+    fn near(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    // Checks `t`, and that `point` gives back where the hit is for its UVs.
+    fn check<S: Hittable + Surface>(shape: &S, rec: &HitRecord, t: f64, uv: (f64, f64)) {
+        assert!(near(rec.t, t), "t {} not {}", rec.t, t);
+        assert!(
+            near(rec.u, uv.0) && near(rec.v, uv.1),
+            "uv {:?}",
+            (rec.u, rec.v)
+        );
+        assert!((shape.point(rec.u, rec.v) - rec.p).length() < 1e-9);
+        assert!(near(rec.normal.length(), 1.0));
+    }
+
+    #[test]
+    fn cylinders_clip_to_their_height_and_sweep() {
+        let cylinder = Cylinder::of(1.0, 0.0, 2.0, 0);
+        let side = hit(
+            &cylinder,
+            Point3::of(0.0, 3.0, 1.0),
+            Vec3::of(0.0, -1.0, 0.0),
+        )
+        .unwrap();
+        check(&cylinder, &side, 2.0, (0.25, 0.5));
+        assert!(side.front_face);
+        assert_eq!(side.normal, Vec3::of(0.0, 1.0, 0.0));
+        assert!(hit(
+            &cylinder,
+            Point3::of(3.0, 0.0, 3.0),
+            Vec3::of(-1.0, 0.0, 0.0)
+        )
+        .is_none());
+
+        // The way in is above the top, so the hit is on the way out.
+        let inside = hit(
+            &cylinder,
+            Point3::of(-2.0, 0.0, 4.0),
+            Vec3::of(1.0, 0.0, -1.0),
+        )
+        .unwrap();
+        check(&cylinder, &inside, 3.0, (0.0, 0.5));
+        assert!(!inside.front_face);
+        assert_eq!(inside.normal, Vec3::of(-1.0, 0.0, 0.0));
+
+        // Half a cylinder, open towards -y: the near side is cut away.
+        let half = cylinder.clone().with_phi_max(180.0);
+        let far = hit(&half, Point3::of(0.5, -3.0, 1.0), Vec3::of(0.0, 1.0, 0.0)).unwrap();
+        check(&half, &far, 3.0 + 0.75f64.sqrt(), (1.0 / 3.0, 0.5));
+        assert!(!far.front_face);
+    }
+
+    #[test]
+    fn caps_close_the_ends() {
+        let down = Vec3::of(0.0, 0.0, -1.0);
+        let open = Cylinder::of(1.0, 0.0, 2.0, 0);
+        assert!(hit(&open, Point3::of(0.2, 0.3, 5.0), down).is_none());
+
+        let capped = open.with_caps();
+        let top = hit(&capped, Point3::of(0.2, 0.3, 5.0), down).unwrap();
+        assert!(near(top.t, 3.0) && top.front_face);
+        assert_eq!(top.normal, -down);
+        let bottom = hit(&capped, Point3::of(0.2, 0.3, -1.0), -down).unwrap();
+        assert!(near(bottom.t, 1.0) && bottom.front_face);
+        assert_eq!(bottom.normal, down);
+        let from_inside = hit(&capped, Point3::of(0.2, 0.3, 1.0), -down).unwrap();
+        assert!(near(from_inside.t, 1.0) && !from_inside.front_face);
+        assert_eq!(from_inside.normal, down);
+        // The side is still nearer than a cap behind it.
+        let side = hit(
+            &capped,
+            Point3::of(3.0, 0.0, 1.5),
+            Vec3::of(-1.0, 0.0, -0.1),
+        )
+        .unwrap();
+        assert!(near(side.t, 2.0) && near(side.normal.x(), 1.0));
+    }
+
+    #[test]
+    fn annuli_have_a_hole() {
+        let down = Vec3::of(0.0, 0.0, -1.0);
+        let annulus = Disk::of(0.0, 2.0, 0).with_inner_radius(1.0);
+        assert!(hit(&annulus, Point3::of(0.5, 0.0, 1.0), down).is_none());
+        assert!(hit(&annulus, Point3::of(2.5, 0.0, 1.0), down).is_none());
+        let ring = hit(&annulus, Point3::of(0.0, 1.5, 1.0), down).unwrap();
+        check(&annulus, &ring, 1.0, (0.25, 0.5));
+        assert!(ring.front_face);
+        let below = hit(&annulus, Point3::of(0.0, 1.5, -1.0), -down).unwrap();
+        assert!(!below.front_face);
+        assert_eq!(below.normal, down);
+    }
+
+    #[test]
+    fn cones_keep_to_one_nappe() {
+        let cone = Cone::of(1.0, 2.0, 0);
+        let side = hit(&cone, Point3::of(3.0, 0.0, 1.0), Vec3::of(-1.0, 0.0, 0.0)).unwrap();
+        check(&cone, &side, 2.5, (0.0, 0.5));
+        assert!(side.front_face);
+        assert!((side.normal - unit_vector(&Vec3::of(1.0, 0.0, 0.5))).length() < 1e-9);
+        // Above the apex is the other nappe, which isn't there.
+        assert!(hit(&cone, Point3::of(3.0, 0.0, 2.5), Vec3::of(-1.0, 0.0, 0.0)).is_none());
+
+        let band = cone.with_z_range(0.5, 1.5);
+        assert!(hit(&band, Point3::of(3.0, 0.0, 1.8), Vec3::of(-1.0, 0.0, 0.0)).is_none());
+        let low = hit(&band, Point3::of(3.0, 0.0, 0.5), Vec3::of(-1.0, 0.0, 0.0)).unwrap();
+        check(&band, &low, 2.25, (0.0, 0.0));
+    }
+
+    #[test]
+    fn paraboloids_open_upwards() {
+        // z = x^2 + y^2 up to z = 1.
+        let bowl = Paraboloid::of(1.0, 0.0, 1.0, 0);
+        let side = hit(&bowl, Point3::of(-3.0, 0.0, 0.25), Vec3::of(1.0, 0.0, 0.0)).unwrap();
+        check(&bowl, &side, 2.5, (0.5, 0.25));
+        assert!(side.front_face);
+        assert!(hit(&bowl, Point3::of(-3.0, 0.0, 1.5), Vec3::of(1.0, 0.0, 0.0)).is_none());
+        // Looking down into the bowl sees its inside.
+        let inside = hit(&bowl, Point3::of(0.0, 0.0, 2.0), Vec3::of(0.0, 0.0, -1.0)).unwrap();
+        assert!(near(inside.t, 2.0) && !inside.front_face);
+        assert_eq!(inside.normal, Vec3::of(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn hyperboloids_sweep_their_segment() {
+        let flat = Hyperboloid::try_of(Point3::of(1.0, 0.0, 1.0), Point3::of(0.0, 2.0, 1.0), 0);
+        assert!(flat.is_none());
+
+        // Twisted so that the waist, of radius 1, is at z = 0.
+        let p1 = Point3::of(1.0, -1.0, -1.0);
+        let p2 = Point3::of(1.0, 1.0, 1.0);
+        let hourglass = Hyperboloid::try_of(p1, p2, 0).unwrap();
+        let waist = hit(
+            &hourglass,
+            Point3::of(3.0, 0.0, 0.0),
+            Vec3::of(-1.0, 0.0, 0.0),
+        )
+        .unwrap();
+        check(&hourglass, &waist, 2.0, (0.0, 0.5));
+        assert!(waist.front_face);
+        assert!((waist.normal - Vec3::of(1.0, 0.0, 0.0)).length() < 1e-9);
+        // Just outside the waist at its height, but inside near the ends.
+        assert!(hit(
+            &hourglass,
+            Point3::of(1.1, 3.0, 0.0),
+            Vec3::of(0.0, -1.0, 0.0)
+        )
+        .is_none());
+        let end = hit(
+            &hourglass,
+            Point3::of(1.1, 3.0, 0.9),
+            Vec3::of(0.0, -1.0, 0.0),
+        )
+        .unwrap();
+        assert!((hourglass.point(end.u, end.v) - end.p).length() < 1e-9);
+        assert!(hit(
+            &hourglass,
+            Point3::of(0.0, 3.0, 1.5),
+            Vec3::of(0.0, -1.0, 0.0)
+        )
+        .is_none());
+
+        // Inside, a quarter turn sees only the part swept first.
+        let quarter = hourglass.with_phi_max(90.0);
+        let seen = hit(&quarter, Point3::of(0.0, 0.0, 0.0), Vec3::of(1.0, 0.5, 0.0)).unwrap();
+        assert!(!seen.front_face);
+        assert!(hit(
+            &quarter,
+            Point3::of(0.0, 0.0, 0.0),
+            Vec3::of(-1.0, -0.5, 0.0)
+        )
+        .is_none());
+    }
+}
@@ -0,0 +1,22 @@
+use crate::vec3::*;
+
+#[derive(Clone, Copy, Default)]
+pub struct Ray {
+    pub origin: Point3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new() -> Ray {
+        Ray {
+            origin: Vec3::new(),
+            direction: Vec3::new(),
+        }
+    }
+    pub fn of(origin: Point3, direction: Vec3) -> Ray {
+        Ray { origin, direction }
+    }
+    pub fn at(&self, t: f64) -> Point3 {
+        self.origin + (self.direction * t)
+    }
+}
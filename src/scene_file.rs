@@ -0,0 +1,1276 @@
+// Plain text scene format, one statement per line:
+//
+//   camera lookfrom X Y Z lookat X Y Z vup X Y Z vfov F aspect F aperture F focus F
+//   material lambertian R G B
+//   material metal R G B FUZZ
+//   material dielectric IR
+//   sphere X Y Z RADIUS MATERIAL
+//   triangle X Y Z X Y Z X Y Z MATERIAL [normals X Y Z X Y Z X Y Z] [uv U V U V U V]
+//   plane X Y Z NX NY NZ MATERIAL [scale S]
+//   quad X Y Z UX UY UZ VX VY VZ MATERIAL
+//   rect xy|xz|yz A0 A1 B0 B1 K MATERIAL [flip]
+//   box X Y Z X Y Z MATERIAL           (opposite corners)
+//   cylinder RADIUS ZMIN ZMAX PHIMAX MATERIAL [capped]
+//   cone RADIUS HEIGHT ZMIN ZMAX PHIMAX MATERIAL
+//   disk HEIGHT RADIUS INNER PHIMAX MATERIAL [flip]
+//   paraboloid RADIUS ZMIN ZMAX PHIMAX MATERIAL
+//   hyperboloid X Y Z X Y Z PHIMAX MATERIAL
+//   torus MAJOR MINOR PHIMAX MATERIAL
+//   sdf MATERIAL STEPS EPSILON STEPSCALE MAXDIST FIELD
+//   curve flat|round X Y Z X Y Z X Y Z X Y Z WIDTH0 WIDTH1 MATERIAL
+//   patch X Y Z .. MATERIAL            (16 control points, row by row)
+//   heightfield NX NZ X Y Z SX SY SZ MATERIAL H ..  (NX * NZ heights by row)
+//   heightfield_pgm FILE X Y Z SX SY SZ MATERIAL  (heights from a PGM image)
+//   transform M00 M01 .. M33          (block of one object)
+//   instance M00 M01 .. M33 MATERIAL  (block of one object, MATERIAL may be -)
+//   instance M00 M01 .. M33 MATERIAL NAME  (placing the object of a `define`)
+//   define NAME                       (block of one object for instances)
+//   group                             (block of any number of objects)
+//   csg union|intersection|difference (block of two closed objects, A then B)
+//   mesh MATERIAL [MATERIAL ..]       (block of the statements below)
+//     v X Y Z | vn X Y Z | vt U V | f A B C [SLOT]
+//   end
+//   subdiv catmull_clark|loop LEVELS MATERIAL  (block of the statements below)
+//     v X Y Z | f A B C [D ..] | crease A B SHARPNESS
+//   end
+//   voxels X Y Z SIZE MATERIAL [MATERIAL ..]  (block of the statements below)
+//     voxel I J K SLOT
+//   end
+//   vox FILE X Y Z SIZE               (MagicaVoxel model and its materials)
+//
+// The quadrics and tori sit around the z axis in their own space and are
+// placed with a `transform` block; PHIMAX is in degrees. Mesh faces index the
+// `v` lines from zero, and `SLOT` picks an entry of the mesh's material list.
+// A face referencing a shared mesh is written out as a plain `triangle`, and
+// a group with its own hierarchy as a plain `group`. Instances are written as
+// references to a `define` of their geometry, so geometry shared between
+// them is written once and shared again on loading.
+//
+// A `subdiv` cage is refined when it is read, at most `MAX_LEVELS` times and
+// to no more than `subdiv::MAX_FACES` faces, and written back as the mesh it
+// refines to. A `vox` file is likewise written back as `voxels` and the
+// materials it added, and a `heightfield_pgm` image as a `heightfield`; their
+// paths are taken from the working directory.
+//
+// A distance field is written in prefix form, one of
+//
+//   sphere R | round_box X Y Z R | torus MAJOR MINOR | mandelbulb POWER N
+//   union A B | intersection A B | difference A B | smooth_union K A B
+//   translate X Y Z A | scale S A | twist RATE A | repeat X Y Z A
+//
+// Materials are numbered from zero in the order they appear. Numbers are
+// written with Rust's shortest round-trip formatting, so loading an exported
+// scene gives back bit-identical values. `#` starts a comment.
+
+use crate::csg::{Csg, CsgOp};
+use crate::curve::{Curve, CurveKind};
+use crate::heightfield::Heightfield;
+use crate::mat4::Mat4;
+use crate::material::*;
+use crate::mesh::TriangleMesh;
+use crate::object::Object;
+use crate::patch::BezierPatch;
+use crate::plane::Plane;
+use crate::quad::{AxisRect, Cuboid, Quad, RectPlane};
+use crate::quadric::{Cone, Cylinder, Disk, Hyperboloid, Paraboloid};
+use crate::scene_graph::Instance;
+use crate::scenes::{Scene, View};
+use crate::sdf::{Sdf, SdfShape};
+use crate::sphere::Sphere;
+use crate::subdiv::{self, check_face, Cage, Scheme};
+use crate::torus::Torus;
+use crate::transform::Transformed;
+use crate::triangle::Triangle;
+use crate::vec3::*;
+use crate::voxel::{load_vox, VoxelGrid, MAX_CELL};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+// Each level of subdivision has about four times the faces of the last, so
+// this many already turn a cage of a few hundred faces into millions.
+const MAX_LEVELS: u32 = 8;
+
+pub fn write_scene<W: Write>(scene: &Scene, out: &mut W) -> io::Result<()> {
+    let v = &scene.view;
+    writeln!(out, "# raytracing scene")?;
+    writeln!(
+        out,
+        "camera lookfrom {} lookat {} vup {} vfov {} aspect {} aperture {} focus {}",
+        v.lookfrom, v.lookat, v.vup, v.vfov, v.aspect_ratio, v.aperture, v.focus_dist
+    )?;
+    for m in &scene.materials {
+        match m {
+            Mat::L(l) => writeln!(out, "material lambertian {}", l.albedo)?,
+            Mat::M(m) => writeln!(out, "material metal {} {}", m.albedo, m.fuzz)?,
+            Mat::D(d) => writeln!(out, "material dielectric {}", d.ir)?,
+        }
+    }
+    let mut shared = Shared::new();
+    let mut order = Vec::new();
+    for object in &scene.world {
+        find_shared(object, &mut shared, &mut order);
+    }
+    for (k, geometry) in order.iter().enumerate() {
+        writeln!(out, "define shared{}", k)?;
+        write_object(geometry, &shared, out)?;
+        writeln!(out, "end")?;
+    }
+    for object in &scene.world {
+        write_object(object, &shared, out)?;
+    }
+    Ok(())
+}
+
+// Definition numbers of instanced geometry, keyed by address.
+type Shared = HashMap<*const Object, usize>;
+
+// Numbers the geometry behind each instance once however many instances
+// share it, after any geometry it instances in turn.
+
+fn find_shared<'a>(object: &'a Object, shared: &mut Shared, order: &mut Vec<&'a Object>) {
+    match object {
+        Object::Transformed(x) => find_shared(&x.object, shared, order),
+        Object::Instance(x) => {
+            let geometry = &x.object.geometry;
+            if !shared.contains_key(&Arc::as_ptr(geometry)) {
+                find_shared(geometry, shared, order);
+                shared.insert(Arc::as_ptr(geometry), order.len());
+                order.push(geometry);
+            }
+        }
+        Object::Csg(x) => {
+            find_shared(&x.a, shared, order);
+            find_shared(&x.b, shared, order);
+        }
+        Object::List(list) => list.iter().for_each(|o| find_shared(o, shared, order)),
+        Object::Bvh(bvh) => bvh
+            .objects()
+            .iter()
+            .for_each(|o| find_shared(o, shared, order)),
+        _ => {}
+    }
+}
+
+fn write_matrix<W: Write>(m: &Mat4, out: &mut W) -> io::Result<()> {
+    for x in m.m.iter().flatten() {
+        write!(out, " {}", x)?;
+    }
+    Ok(())
+}
+
+fn write_triangle<W: Write>(
+    [p0, p1, p2]: [&Point3; 3],
+    normals: Option<[&Vec3; 3]>,
+    uv: [(f64, f64); 3],
+    mat_ptr: usize,
+    out: &mut W,
+) -> io::Result<()> {
+    write!(out, "triangle {} {} {} {}", p0, p1, p2, mat_ptr)?;
+    if let Some([n0, n1, n2]) = normals {
+        write!(out, " normals {} {} {}", n0, n1, n2)?;
+    }
+    if uv != Triangle::of(*p0, *p1, *p2, 0).uv {
+        write!(out, " uv")?;
+        for (u, v) in &uv {
+            write!(out, " {} {}", u, v)?;
+        }
+    }
+    writeln!(out)
+}
+
+fn write_sdf<W: Write>(sdf: &Sdf, out: &mut W) -> io::Result<()> {
+    match sdf {
+        Sdf::Sphere(r) => write!(out, " sphere {}", r),
+        Sdf::RoundBox(b, r) => write!(out, " round_box {} {}", b, r),
+        Sdf::Torus(major, minor) => write!(out, " torus {} {}", major, minor),
+        Sdf::Mandelbulb(power, n) => write!(out, " mandelbulb {} {}", power, n),
+        Sdf::Union(a, b) | Sdf::Intersection(a, b) | Sdf::Difference(a, b) => {
+            let op = match sdf {
+                Sdf::Union(..) => "union",
+                Sdf::Intersection(..) => "intersection",
+                _ => "difference",
+            };
+            write!(out, " {}", op)?;
+            write_sdf(a, out)?;
+            write_sdf(b, out)
+        }
+        Sdf::SmoothUnion(a, b, k) => {
+            write!(out, " smooth_union {}", k)?;
+            write_sdf(a, out)?;
+            write_sdf(b, out)
+        }
+        Sdf::Translate(a, offset) => {
+            write!(out, " translate {}", offset)?;
+            write_sdf(a, out)
+        }
+        Sdf::Scale(a, s) => {
+            write!(out, " scale {}", s)?;
+            write_sdf(a, out)
+        }
+        Sdf::Twist(a, rate) => {
+            write!(out, " twist {}", rate)?;
+            write_sdf(a, out)
+        }
+        Sdf::Repeat(a, period) => {
+            write!(out, " repeat {}", period)?;
+            write_sdf(a, out)
+        }
+    }
+}
+
+fn write_object<W: Write>(object: &Object, shared: &Shared, out: &mut W) -> io::Result<()> {
+    match object {
+        Object::Sphere(s) => writeln!(out, "sphere {} {} {}", s.center, s.radius, s.mat_ptr)?,
+        Object::Triangle(tri) => {
+            let normals = tri.normals.as_ref().map(|[a, b, c]| [a, b, c]);
+            let [p0, p1, p2] = &tri.p;
+            write_triangle([p0, p1, p2], normals, tri.uv, tri.mat_ptr, out)?;
+        }
+        Object::Plane(x) => {
+            write!(out, "plane {} {} {}", x.point, x.normal, x.mat_ptr)?;
+            if x.uv_scale != 1.0 {
+                write!(out, " scale {}", x.uv_scale)?;
+            }
+            writeln!(out)?;
+        }
+        Object::Quad(x) => writeln!(out, "quad {} {} {} {}", x.q, x.u, x.v, x.mat_ptr)?,
+        Object::Rect(x) => {
+            let plane = match x.plane {
+                RectPlane::XY => "xy",
+                RectPlane::XZ => "xz",
+                RectPlane::YZ => "yz",
+            };
+            write!(
+                out,
+                "rect {} {} {} {} {} {} {}",
+                plane, x.a0, x.a1, x.b0, x.b1, x.k, x.mat_ptr
+            )?;
+            if x.flipped {
+                write!(out, " flip")?;
+            }
+            writeln!(out)?;
+        }
+        Object::Cuboid(x) => writeln!(out, "box {} {} {}", x.min, x.max, x.mat_ptr())?,
+        Object::Cylinder(x) => {
+            write!(
+                out,
+                "cylinder {} {} {} {} {}",
+                x.radius, x.z_min, x.z_max, x.phi_max, x.mat_ptr
+            )?;
+            if x.capped {
+                write!(out, " capped")?;
+            }
+            writeln!(out)?;
+        }
+        Object::Cone(x) => writeln!(
+            out,
+            "cone {} {} {} {} {} {}",
+            x.radius, x.height, x.z_min, x.z_max, x.phi_max, x.mat_ptr
+        )?,
+        Object::Disk(x) => {
+            write!(
+                out,
+                "disk {} {} {} {} {}",
+                x.height, x.radius, x.inner_radius, x.phi_max, x.mat_ptr
+            )?;
+            if x.flipped {
+                write!(out, " flip")?;
+            }
+            writeln!(out)?;
+        }
+        Object::Paraboloid(x) => writeln!(
+            out,
+            "paraboloid {} {} {} {} {}",
+            x.radius, x.z_min, x.z_max, x.phi_max, x.mat_ptr
+        )?,
+        Object::Hyperboloid(x) => writeln!(
+            out,
+            "hyperboloid {} {} {} {}",
+            x.p1, x.p2, x.phi_max, x.mat_ptr
+        )?,
+        Object::Torus(x) => writeln!(
+            out,
+            "torus {} {} {} {}",
+            x.major_radius, x.minor_radius, x.phi_max, x.mat_ptr
+        )?,
+        Object::Sdf(x) => {
+            write!(
+                out,
+                "sdf {} {} {} {} {}",
+                x.mat_ptr, x.max_steps, x.epsilon, x.step_scale, x.max_distance
+            )?;
+            write_sdf(&x.field, out)?;
+            writeln!(out)?;
+        }
+        Object::Curve(x) => {
+            let kind = match x.kind {
+                CurveKind::Flat => "flat",
+                CurveKind::Round => "round",
+            };
+            write!(out, "curve {}", kind)?;
+            for p in &x.points {
+                write!(out, " {}", p)?;
+            }
+            writeln!(out, " {} {} {}", x.width0, x.width1, x.mat_ptr)?;
+        }
+        Object::Patch(x) => {
+            write!(out, "patch")?;
+            for p in &x.points {
+                write!(out, " {}", p)?;
+            }
+            writeln!(out, " {}", x.mat_ptr)?;
+        }
+        Object::Heightfield(x) => {
+            write!(
+                out,
+                "heightfield {} {} {} {} {}",
+                x.nx, x.nz, x.corner, x.size, x.mat_ptr
+            )?;
+            for h in &x.heights {
+                write!(out, " {}", h)?;
+            }
+            writeln!(out)?;
+        }
+        Object::Voxels(x) => {
+            write!(out, "voxels {} {}", x.origin, x.size)?;
+            for m in &x.materials {
+                write!(out, " {}", m)?;
+            }
+            writeln!(out)?;
+            for ([i, j, k], slot) in x.voxels() {
+                writeln!(out, "voxel {} {} {} {}", i, j, k, slot)?;
+            }
+            writeln!(out, "end")?;
+        }
+        Object::MeshTriangle(x) => {
+            let mesh = &x.mesh;
+            let face = mesh.indices[x.face as usize].map(|i| i as usize);
+            let [p0, p1, p2] = face.map(|i| &mesh.positions[i]);
+            let normals = if mesh.normals.is_empty() {
+                None
+            } else {
+                Some(face.map(|i| &mesh.normals[i]))
+            };
+            let uv = if mesh.uvs.is_empty() {
+                Triangle::of(*p0, *p1, *p2, 0).uv
+            } else {
+                face.map(|i| mesh.uvs[i])
+            };
+            let mat_ptr = mesh.mat_ptr(x.face as usize);
+            write_triangle([p0, p1, p2], normals, uv, mat_ptr, out)?;
+        }
+        Object::Mesh(mesh) => {
+            write!(out, "mesh")?;
+            for m in &mesh.materials {
+                write!(out, " {}", m)?;
+            }
+            writeln!(out)?;
+            for p in &mesh.positions {
+                writeln!(out, "v {}", p)?;
+            }
+            for n in &mesh.normals {
+                writeln!(out, "vn {}", n)?;
+            }
+            for (u, v) in &mesh.uvs {
+                writeln!(out, "vt {} {}", u, v)?;
+            }
+            for (i, [a, b, c]) in mesh.indices.iter().enumerate() {
+                match mesh.face_materials.get(i) {
+                    Some(slot) => writeln!(out, "f {} {} {} {}", a, b, c, slot)?,
+                    None => writeln!(out, "f {} {} {}", a, b, c)?,
+                }
+            }
+            writeln!(out, "end")?;
+        }
+        Object::Transformed(x) => {
+            write!(out, "transform")?;
+            write_matrix(x.object_to_world(), out)?;
+            writeln!(out)?;
+            write_object(&x.object, shared, out)?;
+            writeln!(out, "end")?;
+        }
+        Object::Instance(x) => {
+            write!(out, "instance")?;
+            write_matrix(x.object_to_world(), out)?;
+            match x.object.material {
+                Some(m) => write!(out, " {}", m)?,
+                None => write!(out, " -")?,
+            }
+            writeln!(out, " shared{}", shared[&Arc::as_ptr(&x.object.geometry)])?;
+        }
+        Object::Csg(x) => {
+            let op = match x.op {
+                CsgOp::Union => "union",
+                CsgOp::Intersection => "intersection",
+                CsgOp::Difference => "difference",
+            };
+            writeln!(out, "csg {}", op)?;
+            write_object(&x.a, shared, out)?;
+            write_object(&x.b, shared, out)?;
+            writeln!(out, "end")?;
+        }
+        Object::List(list) => {
+            writeln!(out, "group")?;
+            for o in list {
+                write_object(o, shared, out)?;
+            }
+            writeln!(out, "end")?;
+        }
+        Object::Bvh(bvh) => {
+            writeln!(out, "group")?;
+            for o in bvh.objects() {
+                write_object(o, shared, out)?;
+            }
+            writeln!(out, "end")?;
+        }
+    }
+    Ok(())
+}
+
+pub fn save_scene(scene: &Scene, path: &Path) -> io::Result<()> {
+    let mut out = io::BufWriter::new(fs::File::create(path)?);
+    write_scene(scene, &mut out)?;
+    out.flush()
+}
+
+pub(crate) fn invalid(line: usize, msg: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line, msg),
+    )
+}
+
+pub(crate) struct Tokens<'a> {
+    pub(crate) line: usize,
+    pub(crate) iter: std::str::SplitWhitespace<'a>,
+}
+
+impl<'a> Tokens<'a> {
+    pub(crate) fn word(&mut self) -> io::Result<&'a str> {
+        self.iter
+            .next()
+            .ok_or_else(|| invalid(self.line, String::from("unexpected end of line")))
+    }
+
+    fn keyword(&mut self, expected: &str) -> io::Result<()> {
+        let w = self.word()?;
+        if w == expected {
+            Ok(())
+        } else {
+            Err(invalid(
+                self.line,
+                format!("expected '{}', found '{}'", expected, w),
+            ))
+        }
+    }
+
+    pub(crate) fn number<T: std::str::FromStr>(&mut self) -> io::Result<T> {
+        let w = self.word()?;
+        w.parse()
+            .map_err(|_| invalid(self.line, format!("bad number '{}'", w)))
+    }
+
+    pub(crate) fn vec3(&mut self) -> io::Result<Vec3> {
+        Ok(Vec3::of(self.number()?, self.number()?, self.number()?))
+    }
+
+    fn matrix(&mut self) -> io::Result<Mat4> {
+        let mut m = Mat4::identity();
+        for row in m.m.iter_mut() {
+            for x in row.iter_mut() {
+                *x = self.number()?;
+            }
+        }
+        Ok(m)
+    }
+
+    fn material(&mut self, defined: usize) -> io::Result<usize> {
+        let mat_ptr: usize = self.number()?;
+        if mat_ptr >= defined {
+            return Err(invalid(
+                self.line,
+                format!("material {} is not defined", mat_ptr),
+            ));
+        }
+        Ok(mat_ptr)
+    }
+
+    fn sdf(&mut self) -> io::Result<Sdf> {
+        Ok(match self.word()? {
+            "sphere" => Sdf::Sphere(self.number()?),
+            "round_box" => Sdf::RoundBox(self.vec3()?, self.number()?),
+            "torus" => Sdf::Torus(self.number()?, self.number()?),
+            "mandelbulb" => Sdf::Mandelbulb(self.number()?, self.number()?),
+            "union" => Sdf::Union(self.sdf()?.into(), self.sdf()?.into()),
+            "intersection" => Sdf::Intersection(self.sdf()?.into(), self.sdf()?.into()),
+            "difference" => Sdf::Difference(self.sdf()?.into(), self.sdf()?.into()),
+            "smooth_union" => {
+                let k = self.number()?;
+                Sdf::SmoothUnion(self.sdf()?.into(), self.sdf()?.into(), k)
+            }
+            "translate" => {
+                let offset = self.vec3()?;
+                Sdf::Translate(self.sdf()?.into(), offset)
+            }
+            "scale" => {
+                let s = self.number()?;
+                Sdf::Scale(self.sdf()?.into(), s)
+            }
+            "twist" => {
+                let rate = self.number()?;
+                Sdf::Twist(self.sdf()?.into(), rate)
+            }
+            "repeat" => {
+                let period = self.vec3()?;
+                Sdf::Repeat(self.sdf()?.into(), period)
+            }
+            other => return Err(invalid(self.line, format!("unknown field '{}'", other))),
+        })
+    }
+
+    // Consumes `word` if it is next.
+    fn optional(&mut self, word: &str) -> bool {
+        if self.iter.clone().next() == Some(word) {
+            self.iter.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.iter.next() {
+            None => Ok(()),
+            Some(w) => Err(invalid(self.line, format!("unexpected '{}'", w))),
+        }
+    }
+}
+
+// An open block and the objects read so far.
+enum Block {
+    Transform(Mat4),
+    Instance(Mat4, Option<usize>),
+    Group,
+    Csg(CsgOp),
+    Mesh(TriangleMesh),
+    Subdiv(Scheme, u32, usize, Cage),
+    Voxels(VoxelGrid),
+    Define(String),
+}
+
+fn close(block: Block, mut objects: Vec<Object>, line: usize) -> io::Result<Object> {
+    let single = |objects: &mut Vec<Object>| -> io::Result<Object> {
+        match (objects.pop(), objects.is_empty()) {
+            (Some(o), true) => Ok(o),
+            _ => Err(invalid(
+                line,
+                String::from("block must hold exactly one object"),
+            )),
+        }
+    };
+    let inverse = |m: &Mat4| {
+        m.inverse()
+            .ok_or_else(|| invalid(line, String::from("transform is not invertible")))
+    };
+    Ok(match block {
+        Block::Transform(m) => {
+            Transformed::with_inverse(Arc::new(single(&mut objects)?), m, inverse(&m)?).into()
+        }
+        Block::Instance(m, material) => {
+            let instance = Instance {
+                geometry: Arc::new(single(&mut objects)?),
+                material,
+            };
+            Transformed::with_inverse(instance, m, inverse(&m)?).into()
+        }
+        Block::Group => Object::List(objects),
+        Block::Define(_) => single(&mut objects)?,
+        Block::Csg(op) => {
+            if objects.len() != 2 {
+                return Err(invalid(line, String::from("csg needs exactly two objects")));
+            }
+            if !objects.iter().all(Object::is_closed) {
+                return Err(invalid(line, String::from("csg objects must be closed")));
+            }
+            let b = objects.pop().unwrap();
+            let a = objects.pop().unwrap();
+            Csg::new(op, Arc::new(a), Arc::new(b)).into()
+        }
+        Block::Mesh(mesh) => {
+            let n = mesh.positions.len();
+            if (!mesh.normals.is_empty() && mesh.normals.len() != n)
+                || (!mesh.uvs.is_empty() && mesh.uvs.len() != n)
+            {
+                return Err(invalid(line, String::from("need one vn and vt per v")));
+            }
+            if !mesh.face_materials.is_empty() && mesh.face_materials.len() != mesh.len() {
+                return Err(invalid(
+                    line,
+                    String::from("need a slot on every face or none"),
+                ));
+            }
+            Object::Mesh(Arc::new(mesh))
+        }
+        Block::Subdiv(scheme, levels, mat_ptr, cage) => {
+            if scheme == Scheme::Loop && !cage.is_triangles() {
+                return Err(invalid(line, String::from("loop needs triangles")));
+            }
+            if cage.faces_after(scheme, levels) > subdiv::MAX_FACES {
+                return Err(invalid(
+                    line,
+                    format!("more than {} faces after subdivision", subdiv::MAX_FACES),
+                ));
+            }
+            Object::Mesh(Arc::new(cage.subdivide(scheme, levels).to_mesh(mat_ptr)))
+        }
+        Block::Voxels(grid) => grid.into(),
+    })
+}
+
+// Adds to the innermost open block, or to the world at the top level.
+fn add(scene: &mut Scene, open: &mut [(Block, Vec<Object>)], object: Object) {
+    match open.last_mut() {
+        Some((_, objects)) => objects.push(object),
+        None => scene.world.push(object),
+    }
+}
+
+pub fn read_scene(text: &str) -> io::Result<Scene> {
+    let mut scene = Scene::new(View::default());
+    let mut open: Vec<(Block, Vec<Object>)> = Vec::new();
+    let mut definitions: HashMap<String, Arc<Object>> = HashMap::new();
+    let mut last_line = 0;
+    for (i, raw) in text.lines().enumerate() {
+        let content = raw.split('#').next().unwrap_or("");
+        let mut t = Tokens {
+            line: i + 1,
+            iter: content.split_whitespace(),
+        };
+        let statement = match t.iter.next() {
+            Some(s) => s,
+            None => continue,
+        };
+        match statement {
+            "camera" => {
+                t.keyword("lookfrom")?;
+                let lookfrom = t.vec3()?;
+                t.keyword("lookat")?;
+                let lookat = t.vec3()?;
+                t.keyword("vup")?;
+                let vup = t.vec3()?;
+                t.keyword("vfov")?;
+                let vfov = t.number()?;
+                t.keyword("aspect")?;
+                let aspect_ratio = t.number()?;
+                t.keyword("aperture")?;
+                let aperture = t.number()?;
+                t.keyword("focus")?;
+                let focus_dist = t.number()?;
+                scene.view = View {
+                    lookfrom,
+                    lookat,
+                    vup,
+                    vfov,
+                    aspect_ratio,
+                    aperture,
+                    focus_dist,
+                };
+            }
+            "material" => {
+                let m = match t.word()? {
+                    "lambertian" => Mat::L(Lambertian { albedo: t.vec3()? }),
+                    "metal" => Mat::M(Metal::new(t.vec3()?, t.number()?)),
+                    "dielectric" => Mat::D(Dielectric { ir: t.number()? }),
+                    other => return Err(invalid(t.line, format!("unknown material '{}'", other))),
+                };
+                scene.add_material(m);
+            }
+            "sphere" => {
+                let center = t.vec3()?;
+                let radius = t.number()?;
+                let mat_ptr = t.material(scene.materials.len())?;
+                let sphere = Sphere::of(center, radius, mat_ptr);
+                add(&mut scene, &mut open, sphere.into());
+            }
+            "triangle" => {
+                let (p0, p1, p2) = (t.vec3()?, t.vec3()?, t.vec3()?);
+                let mut tri = Triangle::of(p0, p1, p2, t.material(scene.materials.len())?);
+                if t.optional("normals") {
+                    tri = tri.with_normals(t.vec3()?, t.vec3()?, t.vec3()?);
+                }
+                if t.optional("uv") {
+                    let mut uv = [(0.0, 0.0); 3];
+                    for x in uv.iter_mut() {
+                        *x = (t.number()?, t.number()?);
+                    }
+                    tri = tri.with_uv(uv);
+                }
+                add(&mut scene, &mut open, tri.into());
+            }
+            "plane" => {
+                let (point, normal) = (t.vec3()?, t.vec3()?);
+                if normal.length_squared() == 0.0 {
+                    return Err(invalid(t.line, String::from("plane normal is zero")));
+                }
+                let mut plane = Plane::of(point, normal, t.material(scene.materials.len())?);
+                if t.optional("scale") {
+                    plane = plane.with_uv_scale(t.number()?);
+                }
+                add(&mut scene, &mut open, plane.into());
+            }
+            "quad" => {
+                let (q, u, v) = (t.vec3()?, t.vec3()?, t.vec3()?);
+                let quad = Quad::of(q, u, v, t.material(scene.materials.len())?);
+                add(&mut scene, &mut open, quad.into());
+            }
+            "rect" => {
+                let make = match t.word()? {
+                    "xy" => AxisRect::xy,
+                    "xz" => AxisRect::xz,
+                    "yz" => AxisRect::yz,
+                    other => return Err(invalid(t.line, format!("unknown plane '{}'", other))),
+                };
+                let (a0, a1, b0, b1, k) = (
+                    t.number()?,
+                    t.number()?,
+                    t.number()?,
+                    t.number()?,
+                    t.number()?,
+                );
+                let mut rect = make(a0, a1, b0, b1, k, t.material(scene.materials.len())?);
+                if t.optional("flip") {
+                    rect = rect.flip();
+                }
+                add(&mut scene, &mut open, rect.into());
+            }
+            "box" => {
+                let (a, b) = (t.vec3()?, t.vec3()?);
+                let cuboid = Cuboid::of(a, b, t.material(scene.materials.len())?);
+                add(&mut scene, &mut open, cuboid.into());
+            }
+            "cylinder" => {
+                let (radius, z_min, z_max) = (t.number()?, t.number()?, t.number()?);
+                let phi_max = t.number()?;
+                let mut cylinder =
+                    Cylinder::of(radius, z_min, z_max, t.material(scene.materials.len())?)
+                        .with_phi_max(phi_max);
+                if t.optional("capped") {
+                    cylinder = cylinder.with_caps();
+                }
+                add(&mut scene, &mut open, cylinder.into());
+            }
+            "cone" => {
+                let (radius, height) = (t.number()?, t.number()?);
+                let (z_min, z_max, phi_max) = (t.number()?, t.number()?, t.number()?);
+                let cone = Cone::of(radius, height, t.material(scene.materials.len())?)
+                    .with_z_range(z_min, z_max)
+                    .with_phi_max(phi_max);
+                add(&mut scene, &mut open, cone.into());
+            }
+            "disk" => {
+                let (height, radius, inner) = (t.number()?, t.number()?, t.number()?);
+                let phi_max = t.number()?;
+                let mut disk = Disk::of(height, radius, t.material(scene.materials.len())?)
+                    .with_inner_radius(inner)
+                    .with_phi_max(phi_max);
+                if t.optional("flip") {
+                    disk = disk.flip();
+                }
+                add(&mut scene, &mut open, disk.into());
+            }
+            "paraboloid" => {
+                let (radius, z_min, z_max) = (t.number()?, t.number()?, t.number()?);
+                let phi_max = t.number()?;
+                let paraboloid =
+                    Paraboloid::of(radius, z_min, z_max, t.material(scene.materials.len())?)
+                        .with_phi_max(phi_max);
+                add(&mut scene, &mut open, paraboloid.into());
+            }
+            "hyperboloid" => {
+                let (p1, p2, phi_max) = (t.vec3()?, t.vec3()?, t.number()?);
+                let mat_ptr = t.material(scene.materials.len())?;
+                let hyperboloid = Hyperboloid::try_of(p1, p2, mat_ptr)
+                    .ok_or_else(|| invalid(t.line, String::from("hyperboloid ends at the same z")))?
+                    .with_phi_max(phi_max);
+                add(&mut scene, &mut open, hyperboloid.into());
+            }
+            "torus" => {
+                let (major, minor, phi_max) = (t.number()?, t.number()?, t.number()?);
+                let torus = Torus::of(major, minor, t.material(scene.materials.len())?)
+                    .with_phi_max(phi_max);
+                add(&mut scene, &mut open, torus.into());
+            }
+            "sdf" => {
+                let mat_ptr = t.material(scene.materials.len())?;
+                let (max_steps, epsilon) = (t.number()?, t.number()?);
+                let (step_scale, max_distance) = (t.number()?, t.number()?);
+                let shape = SdfShape::new(t.sdf()?, mat_ptr)
+                    .with_max_steps(max_steps)
+                    .with_epsilon(epsilon)
+                    .with_step_scale(step_scale)
+                    .with_max_distance(max_distance);
+                add(&mut scene, &mut open, shape.into());
+            }
+            "curve" => {
+                let make = match t.word()? {
+                    "flat" => Curve::flat,
+                    "round" => Curve::round,
+                    other => return Err(invalid(t.line, format!("unknown curve '{}'", other))),
+                };
+                let points = [t.vec3()?, t.vec3()?, t.vec3()?, t.vec3()?];
+                let (width0, width1) = (t.number()?, t.number()?);
+                let curve = make(points, width0, width1, t.material(scene.materials.len())?);
+                add(&mut scene, &mut open, curve.into());
+            }
+            "patch" => {
+                let mut points = [Point3::new(); 16];
+                for p in points.iter_mut() {
+                    *p = t.vec3()?;
+                }
+                let patch = BezierPatch::new(points, t.material(scene.materials.len())?);
+                add(&mut scene, &mut open, patch.into());
+            }
+            "heightfield" => {
+                let (nx, nz): (usize, usize) = (t.number()?, t.number()?);
+                if nx < 2 || nz < 2 {
+                    return Err(invalid(
+                        t.line,
+                        String::from("heightfield needs at least 2 x 2 samples"),
+                    ));
+                }
+                let (corner, size) = (t.vec3()?, t.vec3()?);
+                let mat_ptr = t.material(scene.materials.len())?;
+                let count = nx.checked_mul(nz).ok_or_else(|| {
+                    invalid(t.line, format!("heightfield too large: {} x {}", nx, nz))
+                })?;
+                let mut heights = Vec::new();
+                for _ in 0..count {
+                    heights.push(t.number()?);
+                }
+                let field = Heightfield::new(nx, nz, heights, corner, size, mat_ptr);
+                add(&mut scene, &mut open, field.into());
+            }
+            "heightfield_pgm" => {
+                let path = t.word()?.to_string();
+                let (corner, size) = (t.vec3()?, t.vec3()?);
+                let mat_ptr = t.material(scene.materials.len())?;
+                let field = Heightfield::load_pgm(Path::new(&path), corner, size, mat_ptr)
+                    .map_err(|e| invalid(t.line, format!("{}: {}", path, e)))?;
+                add(&mut scene, &mut open, field.into());
+            }
+            "transform" => open.push((Block::Transform(t.matrix()?), Vec::new())),
+            "instance" => {
+                let m = t.matrix()?;
+                let material = if t.optional("-") {
+                    None
+                } else {
+                    Some(t.material(scene.materials.len())?)
+                };
+                if t.iter.clone().next().is_none() {
+                    open.push((Block::Instance(m, material), Vec::new()));
+                } else {
+                    let name = t.word()?;
+                    let geometry = definitions
+                        .get(name)
+                        .cloned()
+                        .ok_or_else(|| invalid(t.line, format!("'{}' is not defined", name)))?;
+                    let inverse = m.inverse().ok_or_else(|| {
+                        invalid(t.line, String::from("transform is not invertible"))
+                    })?;
+                    let instance = Instance { geometry, material };
+                    add(
+                        &mut scene,
+                        &mut open,
+                        Transformed::with_inverse(instance, m, inverse).into(),
+                    );
+                }
+            }
+            "define" => open.push((Block::Define(t.word()?.to_string()), Vec::new())),
+            "group" => open.push((Block::Group, Vec::new())),
+            "csg" => {
+                let op = match t.word()? {
+                    "union" => CsgOp::Union,
+                    "intersection" => CsgOp::Intersection,
+                    "difference" => CsgOp::Difference,
+                    other => return Err(invalid(t.line, format!("unknown operation '{}'", other))),
+                };
+                open.push((Block::Csg(op), Vec::new()));
+            }
+            "mesh" => {
+                let mut materials = vec![t.material(scene.materials.len())?];
+                while t.iter.clone().next().is_some() {
+                    materials.push(t.material(scene.materials.len())?);
+                }
+                let mut mesh = TriangleMesh::new(Vec::new(), Vec::new(), 0);
+                mesh.materials = materials;
+                open.push((Block::Mesh(mesh), Vec::new()));
+            }
+            "subdiv" => {
+                let scheme = match t.word()? {
+                    "catmull_clark" => Scheme::CatmullClark,
+                    "loop" => Scheme::Loop,
+                    other => return Err(invalid(t.line, format!("unknown scheme '{}'", other))),
+                };
+                let levels = t.number()?;
+                if levels > MAX_LEVELS {
+                    return Err(invalid(
+                        t.line,
+                        format!("at most {} levels of subdivision", MAX_LEVELS),
+                    ));
+                }
+                let mat_ptr = t.material(scene.materials.len())?;
+                let cage = Cage::default();
+                open.push((Block::Subdiv(scheme, levels, mat_ptr, cage), Vec::new()));
+            }
+            "voxels" => {
+                let (origin, size): (Point3, f64) = (t.vec3()?, t.number()?);
+                if size <= 0.0 {
+                    return Err(invalid(t.line, String::from("voxel size must be positive")));
+                }
+                let mut materials = vec![t.material(scene.materials.len())?];
+                while t.iter.clone().next().is_some() {
+                    materials.push(t.material(scene.materials.len())?);
+                }
+                if materials.len() > 256 {
+                    return Err(invalid(t.line, String::from("at most 256 voxel materials")));
+                }
+                open.push((
+                    Block::Voxels(VoxelGrid::new(origin, size, materials)),
+                    Vec::new(),
+                ));
+            }
+            "voxel" => {
+                let grid = match open.last_mut() {
+                    Some((Block::Voxels(grid), _)) => grid,
+                    _ => return Err(invalid(t.line, String::from("'voxel' outside voxels"))),
+                };
+                let cell: [i32; 3] = [t.number()?, t.number()?, t.number()?];
+                if cell.iter().any(|c| c.unsigned_abs() > MAX_CELL as u32) {
+                    return Err(invalid(
+                        t.line,
+                        format!("voxel cells lie within ±{}", MAX_CELL),
+                    ));
+                }
+                let slot: u8 = t.number()?;
+                if slot as usize >= grid.materials.len() {
+                    return Err(invalid(t.line, format!("no material slot {}", slot)));
+                }
+                grid.set(cell, slot);
+            }
+            "vox" => {
+                let path = t.word()?.to_string();
+                let (origin, size): (Point3, f64) = (t.vec3()?, t.number()?);
+                if size <= 0.0 {
+                    return Err(invalid(t.line, String::from("voxel size must be positive")));
+                }
+                let model = load_vox(Path::new(&path))
+                    .map_err(|e| invalid(t.line, format!("{}: {}", path, e)))?;
+                let grid = model.to_grid(&mut scene, origin, size);
+                add(&mut scene, &mut open, grid.into());
+            }
+            "v" | "f" | "crease" if matches!(open.last(), Some((Block::Subdiv(..), _))) => {
+                let cage = match open.last_mut() {
+                    Some((Block::Subdiv(_, _, _, cage), _)) => cage,
+                    _ => unreachable!(),
+                };
+                let index = |t: &mut Tokens, n: usize| -> io::Result<u32> {
+                    let i: u32 = t.number()?;
+                    if i as usize >= n {
+                        return Err(invalid(t.line, String::from("vertex index out of range")));
+                    }
+                    Ok(i)
+                };
+                let n = cage.positions.len();
+                match statement {
+                    "v" => cage.positions.push(t.vec3()?),
+                    "f" => {
+                        let mut face =
+                            vec![index(&mut t, n)?, index(&mut t, n)?, index(&mut t, n)?];
+                        while t.iter.clone().next().is_some() {
+                            face.push(index(&mut t, n)?);
+                        }
+                        check_face(&face, n).map_err(|e| invalid(t.line, e))?;
+                        cage.faces.push(face);
+                    }
+                    _ => {
+                        let (a, b) = (index(&mut t, n)?, index(&mut t, n)?);
+                        let sharpness = t.number()?;
+                        cage.creases.insert((a.min(b), a.max(b)), sharpness);
+                    }
+                }
+            }
+            "v" | "vn" | "vt" | "f" => {
+                let mesh = match open.last_mut() {
+                    Some((Block::Mesh(mesh), _)) => mesh,
+                    _ => return Err(invalid(t.line, format!("'{}' outside a mesh", statement))),
+                };
+                match statement {
+                    "v" => mesh.positions.push(t.vec3()?),
+                    "vn" => mesh.normals.push(t.vec3()?),
+                    "vt" => mesh.uvs.push((t.number()?, t.number()?)),
+                    _ => {
+                        let face: [u32; 3] = [t.number()?, t.number()?, t.number()?];
+                        if face.iter().any(|&i| i as usize >= mesh.positions.len()) {
+                            return Err(invalid(t.line, String::from("vertex index out of range")));
+                        }
+                        mesh.indices.push(face);
+                        if t.iter.clone().next().is_some() {
+                            let slot: u32 = t.number()?;
+                            if slot as usize >= mesh.materials.len() {
+                                return Err(invalid(t.line, format!("no material slot {}", slot)));
+                            }
+                            mesh.face_materials.push(slot);
+                        }
+                    }
+                }
+            }
+            "end" => {
+                let (block, objects) = open
+                    .pop()
+                    .ok_or_else(|| invalid(t.line, String::from("'end' without a block")))?;
+                let name = match &block {
+                    Block::Define(name) => Some(name.clone()),
+                    _ => None,
+                };
+                let object = close(block, objects, t.line)?;
+                match name {
+                    Some(name) => {
+                        if definitions.insert(name.clone(), Arc::new(object)).is_some() {
+                            return Err(invalid(t.line, format!("'{}' is defined twice", name)));
+                        }
+                    }
+                    None => add(&mut scene, &mut open, object),
+                }
+            }
+            other => return Err(invalid(t.line, format!("unknown statement '{}'", other))),
+        }
+        t.finish()?;
+        last_line = t.line;
+    }
+    if !open.is_empty() {
+        return Err(invalid(last_line, String::from("missing 'end'")));
+    }
+    Ok(scene)
+}
+
+pub fn load_scene(path: &Path) -> io::Result<Scene> {
+    read_scene(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenes;
+
+    #[test]
+    fn round_trip_is_exact() {
+        for entry in scenes::small_scenes() {
+            let scene = entry.build(42);
+            let mut text = Vec::new();
+            write_scene(&scene, &mut text).unwrap();
+            let loaded = read_scene(std::str::from_utf8(&text).unwrap()).unwrap();
+
+            assert_eq!(loaded.view, scene.view, "{}", entry.name);
+            assert_eq!(loaded.materials, scene.materials, "{}", entry.name);
+            assert_eq!(loaded.world, scene.world, "{}", entry.name);
+        }
+    }
+
+    #[test]
+    fn round_trip_keeps_instances() {
+        let scene = scenes::grove(42, 3);
+        let mut text = Vec::new();
+        write_scene(&scene, &mut text).unwrap();
+        let loaded = read_scene(std::str::from_utf8(&text).unwrap()).unwrap();
+
+        assert_eq!(loaded.materials, scene.materials);
+        assert_eq!(loaded.world, scene.world);
+        let geometry = |world: &[Object]| -> Vec<Arc<Object>> {
+            let instances = world.iter().filter_map(|o| match o {
+                Object::Instance(x) => Some(Arc::clone(&x.object.geometry)),
+                _ => None,
+            });
+            instances.collect()
+        };
+        let (before, after) = (geometry(&scene.world), geometry(&loaded.world));
+        assert_eq!(after.len(), 9);
+
+        // Shared geometry is written once and shared again on loading.
+        let mut distinct = 0;
+        for (i, (a, b)) in before.iter().zip(&after).enumerate() {
+            if !before[..i].iter().any(|c| Arc::ptr_eq(a, c)) {
+                distinct += 1;
+            }
+            for (c, d) in before.iter().zip(&after) {
+                assert_eq!(Arc::ptr_eq(a, c), Arc::ptr_eq(b, d));
+            }
+        }
+        assert!(distinct < before.len());
+        let text = std::str::from_utf8(&text).unwrap();
+        assert_eq!(text.matches("\ndefine ").count(), distinct);
+    }
+
+    #[test]
+    fn instances_name_their_definitions() {
+        let identity = "1 0 0 0 0 1 0 0 0 0 1 0 0 0 0 1";
+        let scene = |body: &str| {
+            format!(
+                "material lambertian 1 1 1\ndefine ball\nsphere 0 0 0 1 0\nend\n{}",
+                body.replace("M", identity)
+            )
+        };
+        let loaded = read_scene(&scene("instance M - ball\ninstance M 0 ball\n")).unwrap();
+        match &loaded.world[..] {
+            [Object::Instance(a), Object::Instance(b)] => {
+                assert!(Arc::ptr_eq(&a.object.geometry, &b.object.geometry));
+                assert_eq!((a.object.material, b.object.material), (None, Some(0)));
+            }
+            other => panic!("{:?}", other),
+        }
+        for body in &[
+            "instance M - cube\n",
+            "define ball\nsphere 0 0 0 2 0\nend\n",
+            "define empty\nend\n",
+        ] {
+            assert!(read_scene(&scene(body)).is_err(), "{}", body);
+        }
+    }
+
+    #[test]
+    fn reads_heightfields_from_images() {
+        let path = std::env::temp_dir().join(format!("heights-{}.pgm", std::process::id()));
+        fs::write(&path, b"P5 3 2 255\n\x00\x80\xff\x40\x00\x20").unwrap();
+        let text = format!(
+            "material lambertian 1 1 1\nheightfield_pgm {} 0 0 0 2 1 1 0\n",
+            path.display()
+        );
+        let scene = read_scene(&text);
+        let expected = Heightfield::load_pgm(&path, Point3::new(), Vec3::of(2.0, 1.0, 1.0), 0);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(scene.unwrap().world, vec![Object::from(expected.unwrap())]);
+
+        let err =
+            read_scene("material lambertian 1 1 1\nheightfield_pgm missing.pgm 0 0 0 1 1 1 0\n")
+                .unwrap_err();
+        assert!(
+            err.to_string().starts_with("line 2: missing.pgm:"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn rejects_heightfields_too_large_to_count() {
+        let text = format!(
+            "material lambertian 1 1 1\nheightfield {} 4 0 0 0 1 1 1 0 0\n",
+            usize::MAX / 2
+        );
+        let err = read_scene(&text).unwrap_err();
+        assert!(err.to_string().contains("too large"), "{}", err);
+        // Heights run out long before a big count is filled.
+        let err =
+            read_scene("material lambertian 1 1 1\nheightfield 100000 100000 0 0 0 1 1 1 0 0\n");
+        assert!(err.unwrap_err().to_string().starts_with("line 2:"));
+    }
+
+    #[test]
+    fn reports_line_of_error() {
+        let err = read_scene("material lambertian 1 1 1\nsphere 0 0 0 1 3\n").unwrap_err();
+        assert!(err.to_string().starts_with("line 2:"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_open_csg_objects() {
+        let curve = "curve flat 0 0 0 1 0 0 2 0 0 3 0 0 0.1 0.1 0";
+        let open = [
+            curve,
+            "quad 0 0 0 1 0 0 0 1 0 0",
+            "cylinder 1 0 1 360 0",
+            "cylinder 1 0 1 270 0 capped",
+            "torus 2 0.5 180 0",
+        ];
+        for open in &open {
+            let text = format!(
+                "material lambertian 1 1 1\ncsg union\nsphere 0 0 0 1 0\n{}\nend\n",
+                open
+            );
+            let err = read_scene(&text).unwrap_err();
+            assert!(err.to_string().contains("must be closed"), "{}", err);
+        }
+        for closed in &["cylinder 1 0 1 360 0 capped", "torus 2 0.5 360 0"] {
+            let text = format!(
+                "material lambertian 1 1 1\ncsg union\nsphere 0 0 0 1 0\n{}\nend\n",
+                closed
+            );
+            assert!(read_scene(&text).is_ok(), "{}", closed);
+        }
+    }
+
+    #[test]
+    fn limits_subdivision_levels() {
+        let cage = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\nend\n";
+        let scene = |levels| {
+            format!(
+                "material lambertian 1 1 1\nsubdiv loop {} 0\n{}",
+                levels, cage
+            )
+        };
+        assert!(read_scene(&scene(MAX_LEVELS)).is_ok());
+        let err = read_scene(&scene(30)).unwrap_err();
+        assert!(err.to_string().starts_with("line 2:"), "{}", err);
+
+        // Few levels of a big cage are as bad as many of a small one.
+        let mut big = String::from("material lambertian 1 1 1\nsubdiv loop 8 0\n");
+        for i in 0..300 {
+            big += &format!("v {} 0 0\nv {} 1 0\nv {} 0 1\n", i, i, i);
+            big += &format!("f {} {} {}\n", 3 * i, 3 * i + 1, 3 * i + 2);
+        }
+        let err = read_scene(&(big + "end\n")).unwrap_err();
+        assert!(
+            err.to_string().contains("faces after subdivision"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn rejects_faces_that_repeat_a_vertex() {
+        let text = "material lambertian 1 1 1\nsubdiv loop 1 0\n\
+                    v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 0 1\nend\n";
+        let err = read_scene(text).unwrap_err();
+        assert!(err.to_string().starts_with("line 6:"), "{}", err);
+        assert!(err.to_string().contains("repeated"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_voxels_far_from_the_origin() {
+        let grid = |cell: &str| {
+            format!(
+                "material lambertian 1 1 1\nvoxels 0 0 0 1 0\nvoxel {} 0\nend\n",
+                cell
+            )
+        };
+        assert!(read_scene(&grid("-16777216 0 16777216")).is_ok());
+        for cell in &["2147483647 0 0", "0 -2147483648 0", "0 0 16777217"] {
+            let err = read_scene(&grid(cell)).unwrap_err();
+            assert!(err.to_string().starts_with("line 3:"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn rejects_singular_transforms() {
+        let zero = ["0"; 16].join(" ");
+        for block in &["transform", "instance"] {
+            let material = if *block == "instance" { " -" } else { "" };
+            let text = format!(
+                "material lambertian 1 1 1\n{} {}{}\nsphere 0 0 0 1 0\nend\n",
+                block, zero, material
+            );
+            let err = read_scene(&text).unwrap_err();
+            assert!(err.to_string().contains("not invertible"), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::mat4::Mat4;
+use crate::ray::Ray;
+use crate::transform::Transformed;
+use std::sync::Arc;
+
+// A tree of transforms. Each node's transform is relative to its parent, and
+// a material override applies to everything below it unless a descendant sets
+// its own. Geometry is held by `Arc`, so the same object can hang off many
+// nodes without being copied.
+#[derive(Clone, Debug)]
+pub struct Node<G: Hittable> {
+    pub transform: Mat4,
+    pub geometry: Option<Arc<G>>,
+    pub material: Option<usize>,
+    pub children: Vec<Node<G>>,
+}
+
+impl<G: Hittable> Default for Node<G> {
+    fn default() -> Node<G> {
+        Node::new(Mat4::identity())
+    }
+}
+
+impl<G: Hittable> Node<G> {
+    pub fn new(transform: Mat4) -> Node<G> {
+        Node {
+            transform,
+            geometry: None,
+            material: None,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_geometry(mut self, geometry: Arc<G>) -> Node<G> {
+        self.geometry = Some(geometry);
+        self
+    }
+
+    pub fn with_material(mut self, mat_ptr: usize) -> Node<G> {
+        self.material = Some(mat_ptr);
+        self
+    }
+
+    pub fn with_child(mut self, child: Node<G>) -> Node<G> {
+        self.children.push(child);
+        self
+    }
+
+    // One world-space instance per node that carries geometry, or `None` if
+    // any of them ends up with a transform that can't be inverted.
+    pub fn instances(&self) -> Option<Vec<Transformed<Instance<G>>>> {
+        let mut out = Vec::new();
+        self.collect(&Mat4::identity(), None, &mut out)?;
+        Some(out)
+    }
+
+    fn collect(
+        &self,
+        parent: &Mat4,
+        inherited: Option<usize>,
+        out: &mut Vec<Transformed<Instance<G>>>,
+    ) -> Option<()> {
+        let to_world = *parent * self.transform;
+        let material = self.material.or(inherited);
+        if let Some(geometry) = &self.geometry {
+            let instance = Instance {
+                geometry: Arc::clone(geometry),
+                material,
+            };
+            out.push(Transformed::with_inverse(
+                instance,
+                to_world,
+                to_world.inverse()?,
+            ));
+        }
+        for child in &self.children {
+            child.collect(&to_world, material, out)?;
+        }
+        Some(())
+    }
+}
+
+// Shared geometry as placed by one scene graph node.
+#[derive(Debug, PartialEq)]
+pub struct Instance<G: Hittable> {
+    pub geometry: Arc<G>,
+    pub material: Option<usize>,
+}
+
+impl<G: Hittable> Clone for Instance<G> {
+    fn clone(&self) -> Instance<G> {
+        Instance {
+            geometry: Arc::clone(&self.geometry),
+            material: self.material,
+        }
+    }
+}
+
+impl<G: Hittable> Hittable for Instance<G> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.geometry.hit(r, t_min, t_max, rec) {
+            return false;
+        }
+        if let Some(mat_ptr) = self.material {
+            rec.mat_ptr = mat_ptr;
+        }
+        true
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        self.geometry.bounding_box(time0, time1, output_box)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::Sphere;
+    use crate::vec3::*;
+
+    #[test]
+    fn children_compose_with_their_parents() {
+        let unit = Arc::new(Sphere::of(Point3::new(), 1.0, 0));
+        let parent = Mat4::translation(Vec3::of(5.0, 0.0, 0.0));
+        let child = Mat4::scaling(Vec3::of(2.0, 2.0, 2.0));
+        let grandchild = Mat4::translation(Vec3::of(0.0, 1.0, 0.0));
+        let root = Node::new(parent).with_child(
+            Node::new(child)
+                .with_geometry(Arc::clone(&unit))
+                .with_child(Node::new(grandchild).with_geometry(Arc::clone(&unit))),
+        );
+        let instances = root.instances().unwrap();
+        assert_eq!(instances.len(), 2);
+        assert_eq!(*instances[0].object_to_world(), parent * child);
+        assert_eq!(*instances[1].object_to_world(), parent * child * grandchild);
+
+        // The grandchild's sphere has radius 2 about (5, 2, 0).
+        let r = Ray::of(Point3::of(5.0, 2.0, 10.0), Vec3::of(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        assert!(instances[1].hit(&r, 0.001, f64::INFINITY, &mut rec));
+        assert!((rec.t - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn materials_pass_down_until_overridden() {
+        let unit = Arc::new(Sphere::of(Point3::new(), 1.0, 0));
+        let leaf = || Node::new(Mat4::identity()).with_geometry(Arc::clone(&unit));
+        let root = Node::new(Mat4::identity())
+            .with_geometry(Arc::clone(&unit))
+            .with_child(
+                Node::new(Mat4::identity())
+                    .with_material(3)
+                    .with_child(leaf())
+                    .with_child(leaf().with_material(5)),
+            );
+        let materials: Vec<_> = root
+            .instances()
+            .unwrap()
+            .iter()
+            .map(|x| x.object.material)
+            .collect();
+        assert_eq!(materials, vec![None, Some(3), Some(5)]);
+
+        let r = Ray::of(Point3::of(0.0, 0.0, 5.0), Vec3::of(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        let instances = root.instances().unwrap();
+        assert!(instances[0].hit(&r, 0.001, f64::INFINITY, &mut rec));
+        assert_eq!(rec.mat_ptr, 0);
+        assert!(instances[1].hit(&r, 0.001, f64::INFINITY, &mut rec));
+        assert_eq!(rec.mat_ptr, 3);
+    }
+
+    #[test]
+    fn instances_share_geometry() {
+        let unit = Arc::new(Sphere::of(Point3::new(), 1.0, 0));
+        let mut root = Node::new(Mat4::identity());
+        for i in 0..10 {
+            let place = Mat4::translation(Vec3::of(3.0 * i as f64, 0.0, 0.0));
+            root = root.with_child(Node::new(place).with_geometry(Arc::clone(&unit)));
+        }
+        let instances = root.instances().unwrap();
+        assert_eq!(instances.len(), 10);
+        assert!(instances
+            .iter()
+            .all(|x| Arc::ptr_eq(&x.object.geometry, &unit)));
+        // `unit` itself, and one for each node and each instance.
+        assert_eq!(Arc::strong_count(&unit), 21);
+    }
+
+    #[test]
+    fn singular_transforms_give_no_instances() {
+        let unit = Arc::new(Sphere::of(Point3::new(), 1.0, 0));
+        let flat = Mat4::scaling(Vec3::of(1.0, 0.0, 1.0));
+        let root = Node::new(flat).with_child(Node::new(Mat4::identity()).with_geometry(unit));
+        assert!(root.instances().is_none());
+    }
+}
@@ -0,0 +1,1136 @@
+use crate::csg::{Csg, CsgOp};
+use crate::curve::Curve;
+use crate::displace::{self, NoiseTexture};
+use crate::generator::{generate, RandomSceneConfig};
+use crate::heightfield::Heightfield;
+use crate::mat4::Mat4;
+use crate::material::*;
+use crate::mesh::TriangleMesh;
+use crate::noise::fbm;
+use crate::object::Object;
+use crate::patch::{teapot, tessellate_patches};
+use crate::plane::Plane;
+use crate::quad::{AxisRect, Cuboid, Quad};
+use crate::quadric::{Cone, Cylinder, Disk, Hyperboloid, Paraboloid};
+use crate::scene_graph::{Instance, Node};
+use crate::sdf::{Sdf, SdfShape};
+use crate::sphere::Sphere;
+use crate::subdiv::{Cage, Scheme};
+use crate::torus::Torus;
+use crate::transform::Transformed;
+use crate::vec3::*;
+use crate::voxel::VoxelGrid;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f64::consts::TAU;
+use std::sync::Arc;
+
+// Camera placement for a scene; the image size is chosen by the renderer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    pub vfov: f64,
+    pub aspect_ratio: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+}
+
+impl Default for View {
+    fn default() -> View {
+        View {
+            lookfrom: Point3::of(0.0, 0.0, 0.0),
+            lookat: Point3::of(0.0, 0.0, -1.0),
+            vup: Vec3::of(0.0, 1.0, 0.0),
+            vfov: 90.0,
+            aspect_ratio: 16.0 / 9.0,
+            aperture: 0.0,
+            focus_dist: 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Scene {
+    pub world: Vec<Object>,
+    pub materials: Vec<Mat>,
+    pub view: View,
+}
+
+impl Scene {
+    pub fn new(view: View) -> Scene {
+        Scene {
+            world: Vec::new(),
+            materials: Vec::new(),
+            view,
+        }
+    }
+
+    // Registers a material and returns the index used as `mat_ptr`.
+    pub fn add_material(&mut self, m: Mat) -> usize {
+        self.materials.push(m);
+        self.materials.len() - 1
+    }
+
+    pub fn add<O: Into<Object>>(&mut self, object: O) {
+        self.world.push(object.into());
+    }
+
+    pub fn add_sphere(&mut self, center: Point3, radius: f64, m: Mat) {
+        let mat_ptr = self.add_material(m);
+        self.add(Sphere::of(center, radius, mat_ptr));
+    }
+
+    // Infinite ground at y = 0, facing up.
+    pub fn add_ground(&mut self, m: Mat) {
+        let mat_ptr = self.add_material(m);
+        self.add(Plane::of(Point3::new(), Vec3::of(0.0, 1.0, 0.0), mat_ptr));
+    }
+}
+
+pub struct SceneEntry {
+    pub name: &'static str,
+    pub description: &'static str,
+    build: fn(u64) -> Scene,
+}
+
+impl SceneEntry {
+    // Deterministic scenes ignore the seed.
+    pub fn build(&self, seed: u64) -> Scene {
+        (self.build)(seed)
+    }
+}
+
+pub const CATALOGUE: &[SceneEntry] = &[
+    SceneEntry {
+        name: "three-spheres",
+        description: "Matte centre sphere between fuzzed and polished metal (book ch. 10)",
+        build: three_spheres,
+    },
+    SceneEntry {
+        name: "hollow-glass",
+        description: "Hollow glass sphere using a negative inner radius (book ch. 11-12)",
+        build: hollow_glass,
+    },
+    SceneEntry {
+        name: "defocus",
+        description: "Three spheres with a wide aperture (book ch. 13)",
+        build: defocus,
+    },
+    SceneEntry {
+        name: "random",
+        description: "Final cover scene, seeded (book ch. 14)",
+        build: random_scene,
+    },
+    SceneEntry {
+        name: "cornell",
+        description: "Cornell box of axis-aligned rectangles, open at the front",
+        build: cornell_box,
+    },
+    SceneEntry {
+        name: "quads",
+        description: "Five coloured quads around a pair of boxes",
+        build: quads,
+    },
+    SceneEntry {
+        name: "shader-ball",
+        description: "Material test grid sweeping albedo, fuzz and refractive index",
+        build: shader_ball,
+    },
+    SceneEntry {
+        name: "table",
+        description: "Scene graph: ten glasses on a table, placed as one unit",
+        build: table,
+    },
+    SceneEntry {
+        name: "mesh",
+        description: "Icosphere meshes: per-face materials, flat and smooth shading",
+        build: meshes,
+    },
+    SceneEntry {
+        name: "quadrics",
+        description: "Cylinders, cone, annulus, paraboloid and hyperboloid, some cut away",
+        build: quadrics,
+    },
+    SceneEntry {
+        name: "tori",
+        description: "A donut, a glass ring and a pipe with a quarter-torus bend",
+        build: tori,
+    },
+    SceneEntry {
+        name: "csg",
+        description: "CSG: a lens, a cut-away hollow shell and a cube minus a sphere",
+        build: csg,
+    },
+    SceneEntry {
+        name: "sdf",
+        description: "Sphere-traced fields: blend, twisted box, Mandelbulb, repeated spheres",
+        build: sdf,
+    },
+    SceneEntry {
+        name: "teapot",
+        description: "Utah teapots: Bézier patches hit directly beside a tessellated copy",
+        build: teapots,
+    },
+    SceneEntry {
+        name: "subdiv",
+        description: "Subdivision: smooth and creased Catmull-Clark cubes, Loop octahedron",
+        build: subdiv,
+    },
+    SceneEntry {
+        name: "displaced",
+        description: "Displacement: a noisy asteroid and a corrugated plate, seeded",
+        build: displaced,
+    },
+    SceneEntry {
+        name: "curves",
+        description: "Hairy ball on a patch of grass: round and flat Bézier curves, seeded",
+        build: curves,
+    },
+    SceneEntry {
+        name: "terrain",
+        description: "Noise heightfield valley with a lake, seeded",
+        build: terrain,
+    },
+    SceneEntry {
+        name: "voxels",
+        description: "Voxel tree on a block of earth beside a metal ball, seeded",
+        build: voxels,
+    },
+    SceneEntry {
+        name: "forest",
+        description: "Thousands of instances of three tree meshes, seeded",
+        build: forest,
+    },
+    SceneEntry {
+        name: "stress",
+        description: "Random scene scaled up to ~300k spheres, seeded",
+        build: stress,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static SceneEntry> {
+    CATALOGUE.iter().find(|e| e.name == name)
+}
+
+// The catalogue less `stress` and `forest`, whose many thousands of objects
+// are too slow for tests that go through every scene. `grove` builds a
+// smaller forest where instancing needs covering.
+#[cfg(test)]
+pub(crate) fn small_scenes() -> impl Iterator<Item = &'static SceneEntry> {
+    CATALOGUE
+        .iter()
+        .filter(|e| e.name != "stress" && e.name != "forest")
+}
+
+fn lambertian(r: f64, g: f64, b: f64) -> Mat {
+    Mat::L(Lambertian {
+        albedo: Color::of(r, g, b),
+    })
+}
+
+// The chapter scenes share the book's ground sphere and centre row.
+fn chapter_scene(view: View, center: Mat, left: Mat, right: Mat) -> Scene {
+    let mut scene = Scene::new(view);
+    scene.add_sphere(
+        Point3::of(0.0, -100.5, -1.0),
+        100.0,
+        lambertian(0.8, 0.8, 0.0),
+    );
+    scene.add_sphere(Point3::of(0.0, 0.0, -1.0), 0.5, center);
+    scene.add_sphere(Point3::of(-1.0, 0.0, -1.0), 0.5, left);
+    scene.add_sphere(Point3::of(1.0, 0.0, -1.0), 0.5, right);
+    scene
+}
+
+fn three_spheres(_seed: u64) -> Scene {
+    chapter_scene(
+        View::default(),
+        lambertian(0.7, 0.3, 0.3),
+        Mat::M(Metal::new(Color::of(0.8, 0.8, 0.8), 0.3)),
+        Mat::M(Metal::new(Color::of(0.8, 0.6, 0.2), 1.0)),
+    )
+}
+
+fn hollow_glass(_seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(-2.0, 2.0, 1.0),
+        vfov: 20.0,
+        ..View::default()
+    };
+    let glass = Mat::D(Dielectric { ir: 1.5 });
+    let mut scene = chapter_scene(
+        view,
+        lambertian(0.1, 0.2, 0.5),
+        glass,
+        Mat::M(Metal::new(Color::of(0.8, 0.6, 0.2), 0.0)),
+    );
+    // A negative radius flips the normals, giving the inner surface of the shell.
+    scene.add_sphere(Point3::of(-1.0, 0.0, -1.0), -0.45, glass);
+    scene
+}
+
+fn defocus(seed: u64) -> Scene {
+    let mut scene = hollow_glass(seed);
+    let lookfrom = Point3::of(3.0, 3.0, 2.0);
+    let lookat = Point3::of(0.0, 0.0, -1.0);
+    scene.view = View {
+        lookfrom,
+        lookat,
+        vfov: 20.0,
+        aperture: 2.0,
+        focus_dist: (lookfrom - lookat).length(),
+        ..View::default()
+    };
+    scene
+}
+
+pub fn cover_view() -> View {
+    View {
+        lookfrom: Point3::of(13.0, 2.0, 3.0),
+        lookat: Point3::of(0.0, 0.0, 0.0),
+        vfov: 20.0,
+        aperture: 0.1,
+        focus_dist: 10.0,
+        ..View::default()
+    }
+}
+
+pub fn random_scene(seed: u64) -> Scene {
+    generate(&RandomSceneConfig {
+        seed,
+        ..RandomSceneConfig::default()
+    })
+}
+
+// The quadrics are built around z, so each is stood up on y before being
+// moved into its place along the row.
+fn quadrics(_seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 3.0, 10.0),
+        lookat: Point3::of(0.0, 0.6, 0.0),
+        vfov: 40.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    scene.add_ground(lambertian(0.5, 0.5, 0.5));
+    let red = scene.add_material(lambertian(0.7, 0.15, 0.1));
+    let teal = scene.add_material(lambertian(0.1, 0.5, 0.5));
+    let gold = scene.add_material(Mat::M(Metal::new(Color::of(0.8, 0.6, 0.2), 0.1)));
+    let steel = scene.add_material(Mat::M(Metal::new(Color::of(0.8, 0.85, 0.9), 0.02)));
+    let glass = scene.add_material(Mat::D(Dielectric { ir: 1.5 }));
+
+    let shapes: Vec<Object> = vec![
+        Cylinder::of(0.5, 0.0, 1.2, glass).with_caps().into(),
+        Cylinder::of(0.5, 0.0, 1.2, red).with_phi_max(270.0).into(),
+        Cone::of(0.6, 1.4, gold).with_z_range(0.0, 1.0).into(),
+        Paraboloid::of(0.6, 0.0, 1.2, teal)
+            .with_phi_max(300.0)
+            .into(),
+        Hyperboloid::of(Point3::of(0.6, 0.0, 0.0), Point3::of(0.0, 0.6, 1.2), steel).into(),
+    ];
+    let stand_up = Mat4::rotation(Vec3::of(1.0, 0.0, 0.0), -90.0);
+    for (i, shape) in shapes.into_iter().enumerate() {
+        let place = Mat4::translation(Vec3::of(1.6 * i as f64 - 3.2, 0.0, 0.0)) * stand_up;
+        scene.add(Transformed::new(Arc::new(shape), place));
+    }
+
+    // An annulus behind the row, facing the camera.
+    let ring = Disk::of(0.0, 1.0, gold).with_inner_radius(0.6);
+    let place = Mat4::translation(Vec3::of(0.0, 1.2, -2.5));
+    scene.add(Transformed::new(Arc::new(Object::from(ring)), place));
+    scene
+}
+
+// The pipe is built in its own xy plane: a vertical run up to (R, 0), a
+// bend about the origin and a run off along -x from (0, R).
+fn tori(_seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 3.5, 8.0),
+        lookat: Point3::of(0.0, 0.7, 0.0),
+        vfov: 35.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    scene.add_ground(lambertian(0.5, 0.5, 0.5));
+    let icing = scene.add_material(lambertian(0.9, 0.45, 0.6));
+    let glass = scene.add_material(Mat::D(Dielectric { ir: 1.5 }));
+    let copper = scene.add_material(Mat::M(Metal::new(Color::of(0.85, 0.5, 0.3), 0.15)));
+
+    let lie_flat = Mat4::rotation(Vec3::of(1.0, 0.0, 0.0), -90.0);
+    let donut = Torus::of(0.7, 0.3, icing);
+    let place = Mat4::translation(Vec3::of(-2.0, 0.3, 0.5)) * lie_flat;
+    scene.add(Transformed::new(Arc::new(Object::from(donut)), place));
+
+    let ring = Torus::of(0.8, 0.12, glass);
+    let place = Mat4::translation(Vec3::of(0.0, 0.92, -0.5));
+    scene.add(Transformed::new(Arc::new(Object::from(ring)), place));
+
+    let (bend, run, thickness) = (0.6, 1.0, 0.2);
+    let up = Mat4::translation(Vec3::of(bend, 0.0, 0.0)) * lie_flat;
+    let across = Mat4::translation(Vec3::of(0.0, bend, 0.0))
+        * Mat4::rotation(Vec3::of(0.0, 1.0, 0.0), -90.0);
+    let pipe: Vec<Object> = vec![
+        Transformed::new(
+            Arc::new(Object::from(Cylinder::of(thickness, -run, 0.0, copper))),
+            up,
+        )
+        .into(),
+        Torus::of(bend, thickness, copper).with_phi_max(90.0).into(),
+        Transformed::new(
+            Arc::new(Object::from(Cylinder::of(thickness, 0.0, run, copper))),
+            across,
+        )
+        .into(),
+    ];
+    let place = Mat4::translation(Vec3::of(1.6, run, 0.5));
+    scene.add(Transformed::new(Arc::new(Object::from(pipe)), place));
+    scene
+}
+
+fn csg(_seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 2.5, 8.0),
+        lookat: Point3::of(0.0, 1.0, 0.0),
+        vfov: 35.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    scene.add_ground(lambertian(0.5, 0.5, 0.5));
+    let glass = scene.add_material(Mat::D(Dielectric { ir: 1.5 }));
+    let red = scene.add_material(lambertian(0.7, 0.15, 0.1));
+    let cream = scene.add_material(lambertian(0.85, 0.8, 0.65));
+    let steel = scene.add_material(Mat::M(Metal::new(Color::of(0.8, 0.85, 0.9), 0.05)));
+    let solid = |o: Object| Arc::new(o);
+
+    // Biconvex lens: where two offset spheres overlap.
+    let lens = Csg::new(
+        CsgOp::Intersection,
+        solid(Sphere::of(Point3::of(-3.6, 1.0, 0.0), 1.5, glass).into()),
+        solid(Sphere::of(Point3::of(-1.2, 1.0, 0.0), 1.5, glass).into()),
+    );
+    scene.add(lens);
+
+    // A shell with its front half cut off by the half-space behind a plane.
+    let center = Point3::of(0.0, 1.0, 0.0);
+    let shell = Csg::new(
+        CsgOp::Difference,
+        solid(Sphere::of(center, 1.0, red).into()),
+        solid(Sphere::of(center, 0.85, cream).into()),
+    );
+    let cut = Plane::of(center, Vec3::of(0.0, 0.0, -1.0), cream);
+    scene.add(Csg::new(
+        CsgOp::Difference,
+        solid(shell.into()),
+        solid(cut.into()),
+    ));
+
+    let cube = Cuboid::of(Point3::of(1.7, 0.2, -0.8), Point3::of(3.3, 1.8, 0.8), steel);
+    let scoop = Sphere::of(Point3::of(2.5, 1.0, 0.0), 1.05, steel);
+    scene.add(Csg::new(
+        CsgOp::Difference,
+        solid(cube.into()),
+        solid(scoop.into()),
+    ));
+    scene
+}
+
+fn sdf(_seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 3.0, 9.0),
+        lookat: Point3::of(0.0, 1.0, 0.0),
+        vfov: 35.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    scene.add_ground(lambertian(0.5, 0.5, 0.5));
+    let clay = scene.add_material(lambertian(0.8, 0.5, 0.3));
+    let teal = scene.add_material(lambertian(0.1, 0.5, 0.5));
+    let gold = scene.add_material(Mat::M(Metal::new(Color::of(0.8, 0.6, 0.2), 0.2)));
+    let steel = scene.add_material(Mat::M(Metal::new(Color::of(0.8, 0.85, 0.9), 0.05)));
+
+    let blob = Sdf::round_box(Vec3::of(0.5, 0.5, 0.5), 0.1)
+        .smooth_union(Sdf::sphere(0.55).translate(Vec3::of(0.0, 0.8, 0.0)), 0.3)
+        .translate(Vec3::of(-3.0, 0.6, 0.0));
+    scene.add(SdfShape::new(blob, clay));
+
+    // Twisting stretches distances, so take shorter steps.
+    let twisted = Sdf::round_box(Vec3::of(0.35, 1.0, 0.35), 0.05)
+        .twist(1.2)
+        .translate(Vec3::of(-1.0, 1.05, 0.0));
+    scene.add(SdfShape::new(twisted, teal).with_step_scale(0.5));
+
+    let bulb = Sdf::mandelbulb(8.0, 8)
+        .scale(0.9)
+        .translate(Vec3::of(1.2, 1.0, 0.0));
+    scene.add(SdfShape::new(bulb, gold).with_epsilon(1e-3));
+
+    // Endless spheres, cut down to a short row by a box.
+    let row = Sdf::sphere(0.2)
+        .repeat(Vec3::of(0.5, 0.0, 0.5))
+        .intersection(Sdf::round_box(Vec3::of(0.5, 0.2, 1.0), 0.0))
+        .translate(Vec3::of(3.0, 0.2, 0.5));
+    scene.add(SdfShape::new(row, steel));
+    scene
+}
+
+fn teapots(_seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 4.0, 10.0),
+        lookat: Point3::of(0.0, 0.9, 0.0),
+        vfov: 30.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    scene.add_ground(lambertian(0.5, 0.5, 0.5));
+    let china = scene.add_material(lambertian(0.8, 0.75, 0.65));
+    let copper = scene.add_material(Mat::M(Metal::new(Color::of(0.95, 0.6, 0.4), 0.15)));
+
+    let exact: Vec<Object> = teapot(china).into_iter().map(Object::from).collect();
+    let meshed = tessellate_patches(&teapot(copper), 6);
+    let scale = Mat4::scaling(Vec3::of(0.6, 0.6, 0.6));
+    scene.add(Transformed::new(
+        Arc::new(Object::List(exact)),
+        Mat4::translation(Vec3::of(-1.9, 0.0, 0.0)) * scale,
+    ));
+    scene.add(Transformed::new(
+        Arc::new(Object::from(meshed)),
+        Mat4::translation(Vec3::of(1.9, 0.0, 0.0))
+            * Mat4::rotation(Vec3::of(0.0, 1.0, 0.0), 180.0)
+            * scale,
+    ));
+    scene
+}
+
+fn subdiv(_seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 3.0, 9.0),
+        lookat: Point3::of(0.0, 0.8, 0.0),
+        vfov: 30.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    scene.add_ground(lambertian(0.5, 0.5, 0.5));
+    let clay = scene.add_material(lambertian(0.8, 0.5, 0.3));
+    let teal = scene.add_material(lambertian(0.1, 0.5, 0.5));
+    let metal = scene.add_material(Mat::M(Metal::new(Color::of(0.8, 0.85, 0.9), 0.1)));
+
+    let smooth = Cage::cube()
+        .subdivide(Scheme::CatmullClark, 3)
+        .to_mesh(clay);
+
+    // Sharp around the top face only: a crisp rim on a soft base.
+    let mut rimmed = Cage::cube();
+    for (a, b) in [(2, 6), (6, 7), (7, 3), (3, 2)] {
+        rimmed = rimmed.with_crease(a, b, f64::INFINITY);
+    }
+    let rimmed = rimmed.subdivide(Scheme::CatmullClark, 3).to_mesh(teal);
+
+    // A semi-sharp equator that softens after the first level and a half.
+    let mut lens = Cage::octahedron();
+    for (a, b) in [(0, 4), (4, 1), (1, 5), (5, 0)] {
+        lens = lens.with_crease(a, b, 1.5);
+    }
+    let lens = lens.subdivide(Scheme::Loop, 3).to_mesh(metal);
+
+    // Set down so that each rests on the ground.
+    for (x, y, size, mesh) in [
+        (-2.2, 0.77, 0.9, smooth),
+        (0.0, 0.77, 0.9, rimmed),
+        (2.2, 0.62, 1.4, lens),
+    ] {
+        let place =
+            Mat4::translation(Vec3::of(x, y, 0.0)) * Mat4::scaling(Vec3::of(size, size, size));
+        scene.add(Transformed::new(Arc::new(Object::from(mesh)), place));
+    }
+    scene
+}
+
+fn displaced(seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 3.0, 8.0),
+        lookat: Point3::of(0.0, 0.8, 0.0),
+        vfov: 30.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    scene.add_ground(lambertian(0.5, 0.5, 0.5));
+    let rock = scene.add_material(lambertian(0.55, 0.5, 0.45));
+    let brass = scene.add_material(Mat::M(Metal::new(Color::of(0.8, 0.6, 0.3), 0.15)));
+
+    let noise = NoiseTexture {
+        frequency: 2.5,
+        octaves: 4,
+        seed,
+    };
+    let asteroid = displace::displaced(&TriangleMesh::icosphere(2, rock), 0.15, &noise, 0.25);
+    let place = Mat4::translation(Vec3::of(-1.4, 1.1, 0.0));
+    scene.add(Transformed::new(Arc::new(Object::from(asteroid)), place));
+
+    // Any closure of position and UV will do as a texture: ridges across u.
+    let plate = TriangleMesh::new(
+        vec![
+            Point3::of(-1.0, 0.0, 1.0),
+            Point3::of(1.0, 0.0, 1.0),
+            Point3::of(1.0, 0.0, -1.0),
+            Point3::of(-1.0, 0.0, -1.0),
+        ],
+        vec![[0, 1, 2], [0, 2, 3]],
+        brass,
+    )
+    .with_uvs(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+    let ridges = |_p: &Point3, (u, _v): (f64, f64)| 0.5 - 0.5 * (6.0 * TAU * u).cos();
+    let plate = displace::displaced(&plate, 0.08, &ridges, 0.12);
+    let place =
+        Mat4::translation(Vec3::of(1.4, 0.6, 0.0)) * Mat4::rotation(Vec3::of(1.0, 0.0, 0.0), 50.0);
+    scene.add(Transformed::new(Arc::new(Object::from(plate)), place));
+    scene
+}
+
+fn curves(seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 2.0, 6.0),
+        lookat: Point3::of(0.0, 0.9, 0.0),
+        vfov: 30.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    scene.add_ground(lambertian(0.35, 0.25, 0.15));
+    let skin = scene.add_material(lambertian(0.6, 0.3, 0.2));
+    let fur = scene.add_material(Mat::M(Metal::new(Color::of(0.7, 0.45, 0.25), 0.4)));
+    let grass = scene.add_material(lambertian(0.2, 0.5, 0.1));
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // Hairs leave the ball along its normal and droop under their weight.
+    let center = Point3::of(0.0, 1.0, 0.0);
+    scene.add(Sphere::of(center, 0.7, skin));
+    let down = Vec3::of(0.0, -1.0, 0.0);
+    for _ in 0..200 {
+        let n = loop {
+            let v = Vec3::of(
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+            );
+            if v.length_squared() > 1e-6 && v.length_squared() <= 1.0 {
+                break unit_vector(&v);
+            }
+        };
+        let root = center + n * 0.7;
+        let length = rng.gen_range(0.3, 0.5);
+        let points = [
+            root,
+            root + n * (0.4 * length),
+            root + n * (0.8 * length) + down * (0.2 * length),
+            root + n * length + down * (0.6 * length),
+        ];
+        scene.add(Curve::round(points, 0.03, 0.004, fur));
+    }
+
+    // Blades of grass lean away from the ball and taper to a point.
+    for _ in 0..300 {
+        let (x, z) = (rng.gen_range(-2.5, 2.5), rng.gen_range(-1.5, 1.5));
+        if x * x + z * z < 0.6 {
+            continue;
+        }
+        let root = Point3::of(x, 0.0, z);
+        let lean = unit_vector(&Vec3::of(x, 0.0, z)) * rng.gen_range(0.05, 0.2);
+        let height = rng.gen_range(0.2, 0.45);
+        let points = [
+            root,
+            root + Vec3::of(0.0, 0.4 * height, 0.0),
+            root + Vec3::of(0.0, 0.8 * height, 0.0) + lean * 0.5,
+            root + Vec3::of(0.0, height, 0.0) + lean * 1.5,
+        ];
+        scene.add(Curve::flat(points, 0.04, 0.0, grass));
+    }
+    scene
+}
+
+fn terrain(seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 9.0, 24.0),
+        lookat: Point3::of(0.0, 1.5, 0.0),
+        vfov: 40.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    let rock = scene.add_material(lambertian(0.45, 0.4, 0.3));
+    let water = scene.add_material(Mat::M(Metal::new(Color::of(0.3, 0.45, 0.55), 0.05)));
+
+    // Hills around the edge falling to a basin in the middle.
+    let land = Heightfield::from_fn(
+        160,
+        160,
+        |u, v| {
+            let (x, z) = (u - 0.5, v - 0.5);
+            let bowl = 2.5 * (x * x + z * z);
+            (bowl + 0.35 * fbm(&Point3::of(6.0 * u, 0.0, 6.0 * v), 5, seed)).max(0.0)
+        },
+        Point3::of(-20.0, 0.0, -20.0),
+        Vec3::of(40.0, 8.0, 40.0),
+        rock,
+    );
+    scene.add(land);
+    scene.add(Quad::of(
+        Point3::of(-20.0, 0.8, -20.0),
+        Vec3::of(0.0, 0.0, 40.0),
+        Vec3::of(40.0, 0.0, 0.0),
+        water,
+    ));
+    scene
+}
+
+fn voxels(seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(4.0, 3.5, 7.0),
+        lookat: Point3::of(0.0, 1.0, 0.0),
+        vfov: 35.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    scene.add_ground(lambertian(0.5, 0.5, 0.5));
+    let materials = vec![
+        scene.add_material(lambertian(0.2, 0.5, 0.1)),
+        scene.add_material(lambertian(0.35, 0.22, 0.1)),
+        scene.add_material(lambertian(0.3, 0.18, 0.08)),
+        scene.add_material(lambertian(0.1, 0.4, 0.1)),
+        scene.add_material(lambertian(0.2, 0.55, 0.15)),
+        scene.add_material(Mat::M(Metal::new(Color::of(0.9, 0.8, 0.5), 0.05))),
+    ];
+    let (grass, earth, bark, dark_leaf, leaf, gold) = (0, 1, 2, 3, 4, 5);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut grid = VoxelGrid::new(Point3::of(-1.6, 0.0, -1.6), 0.1, materials);
+
+    // A slab of earth with grass on top.
+    for i in 0..24 {
+        for k in 0..24 {
+            for j in 0..3 {
+                grid.set([i, j, k], earth);
+            }
+            grid.set([i, 3, k], grass);
+        }
+    }
+    // Trunk, then a lumpy crown of mixed leaves.
+    for j in 4..14 {
+        for (i, k) in [(11, 11), (12, 11), (11, 12), (12, 12)] {
+            grid.set([i, j, k], bark);
+        }
+    }
+    let centre = Point3::of(11.5, 16.0, 11.5);
+    for i in 4..20 {
+        for j in 10..23 {
+            for k in 4..20 {
+                let p = Point3::of(i as f64 + 0.5, j as f64 + 0.5, k as f64 + 0.5);
+                let bump = 1.5 * fbm(&(p * 0.3), 3, seed);
+                if (p - centre).length() < 6.5 + bump {
+                    let shade = if rng.gen_range(0.0, 1.0) < 0.3 {
+                        dark_leaf
+                    } else {
+                        leaf
+                    };
+                    grid.set([i, j, k], shade);
+                }
+            }
+        }
+    }
+    // A ball of gold beside the block, away from the tree.
+    for i in 26..36 {
+        for j in 0..10 {
+            for k in 8..18 {
+                let p = Point3::of(i as f64 - 30.5, j as f64 - 4.5, k as f64 - 12.5);
+                if p.length() < 5.0 {
+                    grid.set([i, j, k], gold);
+                }
+            }
+        }
+    }
+    scene.add(grid);
+    scene
+}
+
+fn forest(seed: u64) -> Scene {
+    grove(seed, 50)
+}
+
+// Every tree is an instance of one of a few shared meshes, so the triangles
+// are stored once per kind of tree however many are planted, `side` by
+// `side` of them.
+pub(crate) fn grove(seed: u64, side: usize) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 7.0, 24.0),
+        lookat: Point3::of(0.0, 1.0, 0.0),
+        vfov: 40.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    scene.add_ground(lambertian(0.3, 0.4, 0.2));
+    let bark = scene.add_material(lambertian(0.3, 0.2, 0.1));
+    let leaves = [
+        scene.add_material(lambertian(0.15, 0.4, 0.1)),
+        scene.add_material(lambertian(0.3, 0.45, 0.1)),
+        scene.add_material(lambertian(0.1, 0.3, 0.15)),
+    ];
+
+    // A tapered six-sided trunk, shared by every kind of tree.
+    let mut positions = Vec::new();
+    for (y, radius) in [(0.0, 0.12), (1.2, 0.07)] {
+        for i in 0..6 {
+            let phi = TAU * i as f64 / 6.0;
+            positions.push(Point3::of(radius * phi.cos(), y, radius * phi.sin()));
+        }
+    }
+    let mut indices = Vec::new();
+    for i in 0..6 {
+        let j = (i + 1) % 6;
+        indices.push([i, 6 + j, j]);
+        indices.push([i, 6 + i, 6 + j]);
+    }
+    let trunk = Object::from(TriangleMesh::new(positions, indices, bark));
+
+    let kinds: Vec<Arc<Object>> = leaves
+        .iter()
+        .enumerate()
+        .map(|(k, &leaf)| {
+            let noise = NoiseTexture {
+                frequency: 2.0,
+                octaves: 3,
+                seed: seed.wrapping_add(k as u64),
+            };
+            let mut crown =
+                displace::displaced(&TriangleMesh::icosphere(2, leaf), 0.3, &noise, 0.3);
+            let stretch = 0.8 + 0.3 * k as f64;
+            for p in &mut crown.positions {
+                *p = Point3::of(0.7 * p.x(), stretch * p.y() + 1.0 + stretch, 0.7 * p.z());
+            }
+            Arc::new(Object::List(vec![
+                trunk.clone(),
+                crown.with_smooth_normals().into(),
+            ]))
+        })
+        .collect();
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    // Centred across the view, with the last row just in front of the camera.
+    let (middle, back) = ((side as f64 - 1.0) / 2.0, side as f64 - 8.0);
+    for i in 0..side {
+        for j in 0..side {
+            let x = 1.6 * (i as f64 - middle) + rng.gen_range(-0.6, 0.6);
+            let z = 1.6 * (j as f64 - back) + rng.gen_range(-0.6, 0.6);
+            let kind = &kinds[rng.gen_range(0, kinds.len())];
+            let size = rng.gen_range(0.7, 1.3);
+            let place = Mat4::translation(Vec3::of(x, 0.0, z))
+                * Mat4::rotation(Vec3::of(0.0, 1.0, 0.0), rng.gen_range(0.0, 360.0))
+                * Mat4::scaling(Vec3::of(size, size, size));
+            let instance = Instance {
+                geometry: Arc::clone(kind),
+                material: None,
+            };
+            scene.add(Transformed::new(instance, place));
+        }
+    }
+    scene
+}
+
+fn stress(seed: u64) -> Scene {
+    generate(&RandomSceneConfig {
+        seed,
+        ..RandomSceneConfig::default().with_extent(275)
+    })
+}
+
+// smallpt's box with the walls as axis-aligned rectangles facing inwards.
+// There are no emissive materials, so the front is left open and the sky
+// lights the box through it.
+fn cornell_box(_seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(50.0, 52.0, 295.6),
+        lookat: Point3::of(50.0, 47.74, 195.6),
+        vfov: 40.0,
+        aspect_ratio: 1.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    let (width, height, depth) = (99.0, 81.6, 170.0);
+    let red = scene.add_material(lambertian(0.75, 0.25, 0.25));
+    let blue = scene.add_material(lambertian(0.25, 0.25, 0.75));
+    let white = scene.add_material(lambertian(0.75, 0.75, 0.75));
+
+    scene.add(AxisRect::yz(0.0, height, 0.0, depth, 1.0, red));
+    scene.add(AxisRect::yz(0.0, height, 0.0, depth, width, blue).flip());
+    scene.add(AxisRect::xy(1.0, width, 0.0, height, 0.0, white));
+    scene.add(AxisRect::xz(1.0, width, 0.0, depth, 0.0, white));
+    scene.add(AxisRect::xz(1.0, width, 0.0, depth, height, white).flip());
+
+    scene.add_sphere(
+        Point3::of(27.0, 16.5, 47.0),
+        16.5,
+        Mat::M(Metal::new(Color::of(0.999, 0.999, 0.999), 0.0)),
+    );
+    scene.add_sphere(
+        Point3::of(73.0, 16.5, 78.0),
+        16.5,
+        Mat::D(Dielectric { ir: 1.5 }),
+    );
+    scene
+}
+
+// The quads scene from "The Next Week", with two boxes in the middle, the
+// taller one turned about its vertical axis.
+fn quads(_seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 0.0, 9.0),
+        lookat: Point3::of(0.0, 0.0, 0.0),
+        vfov: 80.0,
+        aspect_ratio: 1.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    let left_red = scene.add_material(lambertian(1.0, 0.2, 0.2));
+    let back_green = scene.add_material(lambertian(0.2, 1.0, 0.2));
+    let right_blue = scene.add_material(lambertian(0.2, 0.2, 1.0));
+    let upper_orange = scene.add_material(lambertian(1.0, 0.5, 0.0));
+    let lower_teal = scene.add_material(lambertian(0.2, 0.8, 0.8));
+    let grey = scene.add_material(lambertian(0.73, 0.73, 0.73));
+
+    let quad = |q: (f64, f64, f64), u: (f64, f64, f64), v: (f64, f64, f64), mat_ptr| {
+        Quad::of(
+            Point3::of(q.0, q.1, q.2),
+            Vec3::of(u.0, u.1, u.2),
+            Vec3::of(v.0, v.1, v.2),
+            mat_ptr,
+        )
+    };
+    scene.add(quad(
+        (-3.0, -2.0, 5.0),
+        (0.0, 0.0, -4.0),
+        (0.0, 4.0, 0.0),
+        left_red,
+    ));
+    scene.add(quad(
+        (-2.0, -2.0, 0.0),
+        (4.0, 0.0, 0.0),
+        (0.0, 4.0, 0.0),
+        back_green,
+    ));
+    scene.add(quad(
+        (3.0, -2.0, 1.0),
+        (0.0, 0.0, 4.0),
+        (0.0, 4.0, 0.0),
+        right_blue,
+    ));
+    scene.add(quad(
+        (-2.0, 3.0, 1.0),
+        (4.0, 0.0, 0.0),
+        (0.0, 0.0, 4.0),
+        upper_orange,
+    ));
+    scene.add(quad(
+        (-2.0, -3.0, 5.0),
+        (4.0, 0.0, 0.0),
+        (0.0, 0.0, -4.0),
+        lower_teal,
+    ));
+
+    let tall = Cuboid::of(
+        Point3::of(-0.6, -1.6, -0.6),
+        Point3::of(0.6, 1.0, 0.6),
+        grey,
+    );
+    let turn = Mat4::translation(Vec3::of(-0.9, -0.4, 2.0))
+        * Mat4::rotation(Vec3::of(0.0, 1.0, 0.0), 30.0);
+    scene.add(Transformed::new(Arc::new(Object::from(tall)), turn));
+    scene.add(Cuboid::of(
+        Point3::of(0.4, -2.0, 2.0),
+        Point3::of(1.6, -0.8, 3.2),
+        grey,
+    ));
+    scene
+}
+
+// One row per material: albedo hue, metal fuzz and refractive index sweeps.
+fn shader_ball(_seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 1.6, 9.0),
+        lookat: Point3::of(0.0, 1.4, 0.0),
+        vfov: 30.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    scene.add_ground(lambertian(0.5, 0.5, 0.5));
+
+    let columns = 5;
+    for i in 0..columns {
+        let s = i as f64 / (columns - 1) as f64;
+        let x = (i as f64 - 2.0) * 1.1;
+
+        let albedo = Color::of(0.9 - 0.7 * s, 0.2 + 0.3 * s, 0.1 + 0.8 * s);
+        scene.add_sphere(Point3::of(x, 0.5, 0.0), 0.5, Mat::L(Lambertian { albedo }));
+
+        let metal = Metal::new(Color::of(0.8, 0.8, 0.8), s);
+        scene.add_sphere(Point3::of(x, 1.6, 0.0), 0.5, Mat::M(metal));
+
+        let ir = 1.0 + 1.4 * s;
+        scene.add_sphere(Point3::of(x, 2.7, 0.0), 0.5, Mat::D(Dielectric { ir }));
+    }
+    scene
+}
+
+// The table node carries the top, the legs and the glasses, so its transform
+// moves everything together. Top and legs share one unit sphere; the legs
+// override its material.
+fn table(_seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 3.5, 7.0),
+        lookat: Point3::of(0.0, 0.9, 0.0),
+        vfov: 35.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    scene.add_ground(lambertian(0.5, 0.5, 0.5));
+    let wood = scene.add_material(lambertian(0.45, 0.25, 0.1));
+    let dark_wood = scene.add_material(lambertian(0.2, 0.1, 0.05));
+    let glass = scene.add_material(Mat::D(Dielectric { ir: 1.5 }));
+
+    let origin = Point3::new();
+    let unit = Arc::new(Object::from(Sphere::of(origin, 1.0, wood)));
+    let goblet = Arc::new(Object::List(vec![
+        Sphere::of(origin, 1.0, glass).into(),
+        Sphere::of(origin, -0.9, glass).into(),
+    ]));
+
+    let mut table = Node::new(Mat4::rotation(Vec3::of(0.0, 1.0, 0.0), 25.0)).with_child(
+        Node::new(
+            Mat4::translation(Vec3::of(0.0, 1.0, 0.0)) * Mat4::scaling(Vec3::of(2.2, 0.06, 1.1)),
+        )
+        .with_geometry(Arc::clone(&unit)),
+    );
+    for &(x, z) in &[(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)] {
+        let leg = Mat4::translation(Vec3::of(1.8 * x, 0.5, 0.75 * z))
+            * Mat4::scaling(Vec3::of(0.07, 0.5, 0.07));
+        table = table.with_child(
+            Node::new(leg)
+                .with_geometry(Arc::clone(&unit))
+                .with_material(dark_wood),
+        );
+    }
+    for i in 0..10 {
+        let x = ((i % 5) as f64 - 2.0) * 0.7;
+        let z = if i < 5 { -0.35 } else { 0.35 };
+        let place =
+            Mat4::translation(Vec3::of(x, 1.31, z)) * Mat4::scaling(Vec3::of(0.15, 0.25, 0.15));
+        table = table.with_child(Node::new(place).with_geometry(Arc::clone(&goblet)));
+    }
+
+    for instance in table.instances().expect("table transforms are invertible") {
+        scene.add(instance);
+    }
+    scene
+}
+
+// Left to right: a coarse flat shaded icosphere with faces alternating
+// between two materials, the same sphere smooth shaded, and a finer smooth
+// shaded metal one.
+fn meshes(_seed: u64) -> Scene {
+    let view = View {
+        lookfrom: Point3::of(0.0, 2.0, 8.0),
+        lookat: Point3::of(0.0, 1.0, 0.0),
+        vfov: 30.0,
+        ..View::default()
+    };
+    let mut scene = Scene::new(view);
+    scene.add_ground(lambertian(0.5, 0.5, 0.5));
+    let red = scene.add_material(lambertian(0.7, 0.15, 0.1));
+    let cream = scene.add_material(lambertian(0.85, 0.8, 0.65));
+    let metal = scene.add_material(Mat::M(Metal::new(Color::of(0.8, 0.85, 0.9), 0.05)));
+
+    let coarse = TriangleMesh::icosphere(1, red);
+    let ids = (0..coarse.len() as u32).map(|i| i % 2).collect();
+    let checkered = coarse.with_face_materials(ids, vec![red, cream]);
+    let smooth = checkered.clone().with_smooth_normals();
+    let fine = TriangleMesh::icosphere(3, metal).with_smooth_normals();
+
+    for (x, mesh) in [(-2.2, checkered), (0.0, smooth), (2.2, fine)] {
+        let place = Mat4::translation(Vec3::of(x, 1.0, 0.0));
+        scene.add(Transformed::new(Arc::new(Object::from(mesh)), place));
+    }
+    scene
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::degrees_to_radians;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::ray::Ray;
+    use crate::scene_file::write_scene;
+
+    fn written(scene: &Scene) -> String {
+        let mut out = Vec::new();
+        write_scene(scene, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn every_scene_builds_with_materials_for_what_the_camera_sees() {
+        for entry in small_scenes() {
+            let scene = entry.build(11);
+            assert!(!scene.world.is_empty(), "{} is empty", entry.name);
+
+            // A grid of rays across the field of view.
+            let view = scene.view;
+            let forward = view.lookat - view.lookfrom;
+            let half = (degrees_to_radians(view.vfov) / 2.0).tan() * forward.length();
+            let right = unit_vector(&cross(&forward, &view.vup)) * (half * view.aspect_ratio);
+            let up = unit_vector(&cross(&right, &forward)) * half;
+            let mut hits = 0;
+            for i in 0..16 {
+                for j in 0..16 {
+                    let (x, y) = (i as f64 / 7.5 - 1.0, j as f64 / 7.5 - 1.0);
+                    let r = Ray::of(view.lookfrom, forward + right * x + up * y);
+                    let mut rec = HitRecord::default();
+                    if scene.world.hit(&r, 0.001, f64::INFINITY, &mut rec) {
+                        hits += 1;
+                        assert!(
+                            rec.mat_ptr < scene.materials.len(),
+                            "{}: material {} of {}",
+                            entry.name,
+                            rec.mat_ptr,
+                            scene.materials.len()
+                        );
+                    }
+                }
+            }
+            assert!(hits > 0, "{} shows nothing", entry.name);
+        }
+    }
+
+    #[test]
+    fn scenes_depend_only_on_the_seed() {
+        for entry in small_scenes() {
+            assert_eq!(
+                written(&entry.build(3)),
+                written(&entry.build(3)),
+                "{}",
+                entry.name
+            );
+        }
+        let random = find("random").unwrap();
+        assert_ne!(written(&random.build(3)), written(&random.build(4)));
+    }
+
+    #[test]
+    fn find_looks_up_every_name() {
+        for entry in CATALOGUE {
+            let found = find(entry.name).unwrap();
+            assert_eq!(
+                (found.name, found.description),
+                (entry.name, entry.description)
+            );
+        }
+        for unknown in &["", "Random", "three-sphere", "cornell "] {
+            assert!(find(unknown).is_none(), "{}", unknown);
+        }
+    }
+}
@@ -0,0 +1,447 @@
+// Shapes given by signed distance functions and rendered by sphere tracing:
+// step along the ray by the distance to the nearest surface until that
+// distance drops below `epsilon`. Any `Fn(&Point3) -> f64` can be traced;
+// `Sdf` is a small library of primitives and combinators that, unlike a
+// closure, can be saved with a scene.
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::sphere::sphere_uv;
+use crate::vec3::*;
+
+pub trait DistanceField {
+    // Negative inside. Exact fields never overstate the distance to the
+    // surface; others need `SdfShape::step_scale` below 1.
+    fn distance(&self, p: &Point3) -> f64;
+
+    // A box the surface lies within, or `None` if it is unbounded or not
+    // known, as for a closure.
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+impl<F: Fn(&Point3) -> f64> DistanceField for F {
+    fn distance(&self, p: &Point3) -> f64 {
+        self(p)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Sdf {
+    Sphere(f64),
+    // Half extents and the radius the edges are rounded by.
+    RoundBox(Vec3, f64),
+    // Major and minor radius, lying in the xz plane.
+    Torus(f64, f64),
+    // Power and iteration count of the fractal.
+    Mandelbulb(f64, u32),
+    Union(Box<Sdf>, Box<Sdf>),
+    Intersection(Box<Sdf>, Box<Sdf>),
+    Difference(Box<Sdf>, Box<Sdf>),
+    // Blends the two over a distance of the given radius.
+    SmoothUnion(Box<Sdf>, Box<Sdf>, f64),
+    Translate(Box<Sdf>, Vec3),
+    Scale(Box<Sdf>, f64),
+    // Rotates about y by the given radians per unit of height.
+    Twist(Box<Sdf>, f64),
+    // Repeats space with the given period on each axis; 0 leaves that axis
+    // alone.
+    Repeat(Box<Sdf>, Vec3),
+}
+
+impl Sdf {
+    pub fn sphere(radius: f64) -> Sdf {
+        Sdf::Sphere(radius)
+    }
+
+    pub fn round_box(half_extents: Vec3, radius: f64) -> Sdf {
+        Sdf::RoundBox(half_extents, radius)
+    }
+
+    pub fn torus(major_radius: f64, minor_radius: f64) -> Sdf {
+        Sdf::Torus(major_radius, minor_radius)
+    }
+
+    pub fn mandelbulb(power: f64, iterations: u32) -> Sdf {
+        Sdf::Mandelbulb(power, iterations)
+    }
+
+    pub fn union(self, other: Sdf) -> Sdf {
+        Sdf::Union(Box::new(self), Box::new(other))
+    }
+
+    pub fn intersection(self, other: Sdf) -> Sdf {
+        Sdf::Intersection(Box::new(self), Box::new(other))
+    }
+
+    pub fn difference(self, other: Sdf) -> Sdf {
+        Sdf::Difference(Box::new(self), Box::new(other))
+    }
+
+    pub fn smooth_union(self, other: Sdf, radius: f64) -> Sdf {
+        Sdf::SmoothUnion(Box::new(self), Box::new(other), radius)
+    }
+
+    pub fn translate(self, offset: Vec3) -> Sdf {
+        Sdf::Translate(Box::new(self), offset)
+    }
+
+    pub fn scale(self, factor: f64) -> Sdf {
+        Sdf::Scale(Box::new(self), factor)
+    }
+
+    pub fn twist(self, rate: f64) -> Sdf {
+        Sdf::Twist(Box::new(self), rate)
+    }
+
+    pub fn repeat(self, period: Vec3) -> Sdf {
+        Sdf::Repeat(Box::new(self), period)
+    }
+}
+
+fn max_vec(v: &Vec3, m: f64) -> Vec3 {
+    Vec3::of(v.x().max(m), v.y().max(m), v.z().max(m))
+}
+
+// Distance estimate from the escape-time iteration of the power-n
+// Mandelbulb, in the usual spherical-coordinate form.
+fn mandelbulb(p: &Point3, power: f64, iterations: u32) -> f64 {
+    let mut z = *p;
+    let mut dr = 1.0;
+    let mut r = z.length();
+    // The estimate only holds near the set and grows faster than r away
+    // from it. Beyond the escape radius use a sphere around the whole set.
+    if r > 2.0 {
+        return r - 1.25;
+    }
+    for _ in 0..iterations {
+        if r > 2.0 || r == 0.0 {
+            break;
+        }
+        let theta = (z.z() / r).acos() * power;
+        let phi = z.y().atan2(z.x()) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+        let zr = r.powf(power);
+        z = Vec3::of(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        ) * zr
+            + *p;
+        r = z.length();
+    }
+    if r == 0.0 {
+        return 0.0;
+    }
+    0.5 * r.ln() * r / dr
+}
+
+impl DistanceField for Sdf {
+    fn distance(&self, p: &Point3) -> f64 {
+        match self {
+            Sdf::Sphere(radius) => p.length() - radius,
+            Sdf::RoundBox(b, radius) => {
+                let q = Vec3::of(p.x().abs(), p.y().abs(), p.z().abs()) - *b;
+                max_vec(&q, 0.0).length() + q.x().max(q.y()).max(q.z()).min(0.0) - radius
+            }
+            Sdf::Torus(major, minor) => {
+                let ring = (p.x() * p.x() + p.z() * p.z()).sqrt() - major;
+                (ring * ring + p.y() * p.y()).sqrt() - minor
+            }
+            Sdf::Mandelbulb(power, iterations) => mandelbulb(p, *power, *iterations),
+            Sdf::Union(a, b) => a.distance(p).min(b.distance(p)),
+            Sdf::Intersection(a, b) => a.distance(p).max(b.distance(p)),
+            Sdf::Difference(a, b) => a.distance(p).max(-b.distance(p)),
+            Sdf::SmoothUnion(a, b, k) => {
+                let (d1, d2) = (a.distance(p), b.distance(p));
+                if *k <= 0.0 {
+                    return d1.min(d2);
+                }
+                let h = (0.5 + 0.5 * (d2 - d1) / k).clamp(0.0, 1.0);
+                d2 * (1.0 - h) + d1 * h - k * h * (1.0 - h)
+            }
+            Sdf::Translate(a, offset) => a.distance(&(*p - *offset)),
+            Sdf::Scale(a, s) => a.distance(&(*p / *s)) * s,
+            Sdf::Twist(a, rate) => {
+                let (sin, cos) = (rate * p.y()).sin_cos();
+                let q = Point3::of(cos * p.x() - sin * p.z(), p.y(), sin * p.x() + cos * p.z());
+                a.distance(&q)
+            }
+            Sdf::Repeat(a, period) => {
+                let wrap = |x: f64, c: f64| if c > 0.0 { x - c * (x / c).round() } else { x };
+                let q = Point3::of(
+                    wrap(p.x(), period.x()),
+                    wrap(p.y(), period.y()),
+                    wrap(p.z(), period.z()),
+                );
+                a.distance(&q)
+            }
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let cube = |r: f64| Aabb::of(Point3::of(-r, -r, -r), Point3::of(r, r, r));
+        Some(match self {
+            Sdf::Sphere(radius) => cube(radius.abs()),
+            Sdf::RoundBox(b, radius) => {
+                let e = Vec3::of(b.x().abs(), b.y().abs(), b.z().abs())
+                    + Vec3::of(1.0, 1.0, 1.0) * radius.abs();
+                Aabb::of(-e, e)
+            }
+            Sdf::Torus(major, minor) => {
+                let (outer, minor) = (major.abs() + minor.abs(), minor.abs());
+                Aabb::of(
+                    Point3::of(-outer, -minor, -outer),
+                    Point3::of(outer, minor, outer),
+                )
+            }
+            // Nothing beyond the escape radius is in the set.
+            Sdf::Mandelbulb(..) => cube(2.0),
+            Sdf::Union(a, b) => surrounding_box(&a.bounds()?, &b.bounds()?),
+            Sdf::Intersection(a, b) => match (a.bounds(), b.bounds()) {
+                (Some(x), Some(y)) => x.intersection(&y).unwrap_or(x),
+                (x, y) => x.or(y)?,
+            },
+            Sdf::Difference(a, _) => a.bounds()?,
+            // The blend reaches at most k / 4 beyond the nearer surface.
+            Sdf::SmoothUnion(a, b, k) => {
+                surrounding_box(&a.bounds()?, &b.bounds()?).padded(0.25 * k.max(0.0))
+            }
+            Sdf::Translate(a, offset) => {
+                let inner = a.bounds()?;
+                Aabb::of(inner.minimum + *offset, inner.maximum + *offset)
+            }
+            Sdf::Scale(a, s) => {
+                let inner = a.bounds()?;
+                Aabb::of(inner.minimum * *s, inner.maximum * *s)
+            }
+            // Any turn about y stays within the circle through the farthest
+            // corner.
+            Sdf::Twist(a, _) => {
+                let inner = a.bounds()?;
+                let x = inner.minimum.x().abs().max(inner.maximum.x().abs());
+                let z = inner.minimum.z().abs().max(inner.maximum.z().abs());
+                let r = (x * x + z * z).sqrt();
+                Aabb::of(
+                    Point3::of(-r, inner.minimum.y(), -r),
+                    Point3::of(r, inner.maximum.y(), r),
+                )
+            }
+            Sdf::Repeat(a, period) => {
+                if period.x() > 0.0 || period.y() > 0.0 || period.z() > 0.0 {
+                    return None;
+                }
+                a.bounds()?
+            }
+        })
+    }
+}
+
+// A distance field as a hittable. Normals come from the field's gradient,
+// and UVs from the normal's direction as on a sphere.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SdfShape<F: DistanceField> {
+    pub field: F,
+    pub max_steps: u32,
+    pub epsilon: f64,
+    // Fraction of the distance to advance each step. Fields that can
+    // overstate the distance (twists, fractals) need less than 1.
+    pub step_scale: f64,
+    // For a field without bounds, how far along the ray to give up, in world
+    // units. Without it a ray that misses spends every step before giving
+    // up. Bounded fields march only across their box instead.
+    pub max_distance: f64,
+    pub mat_ptr: usize,
+}
+
+impl<F: DistanceField> SdfShape<F> {
+    pub fn new(field: F, mat_ptr: usize) -> SdfShape<F> {
+        SdfShape {
+            field,
+            max_steps: 256,
+            epsilon: 1e-4,
+            step_scale: 1.0,
+            max_distance: 100.0,
+            mat_ptr,
+        }
+    }
+
+    pub fn with_max_steps(mut self, max_steps: u32) -> SdfShape<F> {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn with_epsilon(mut self, epsilon: f64) -> SdfShape<F> {
+        self.epsilon = epsilon;
+        self
+    }
+
+    pub fn with_step_scale(mut self, step_scale: f64) -> SdfShape<F> {
+        self.step_scale = step_scale;
+        self
+    }
+
+    pub fn with_max_distance(mut self, max_distance: f64) -> SdfShape<F> {
+        self.max_distance = max_distance;
+        self
+    }
+
+    // Central differences on a tetrahedron, which takes four samples rather
+    // than six.
+    pub fn gradient(&self, p: &Point3) -> Vec3 {
+        let h = self.epsilon;
+        let ks = [
+            Vec3::of(1.0, -1.0, -1.0),
+            Vec3::of(-1.0, -1.0, 1.0),
+            Vec3::of(-1.0, 1.0, -1.0),
+            Vec3::of(1.0, 1.0, 1.0),
+        ];
+        ks.iter().fold(Vec3::new(), |g, k| {
+            g + *k * self.field.distance(&(*p + *k * h))
+        })
+    }
+}
+
+impl<F: DistanceField> Hittable for SdfShape<F> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let speed = r.direction.length();
+        // Marching can't start from minus infinity; a ray that starts inside
+        // reports the way out instead.
+        let mut t = if t_min.is_finite() { t_min } else { 0.0 };
+        let t_max = match self.field.bounds() {
+            Some(b) => {
+                let inv_d = Vec3::of(
+                    1.0 / r.direction.x(),
+                    1.0 / r.direction.y(),
+                    1.0 / r.direction.z(),
+                );
+                let padded = b.padded(2.0 * self.epsilon);
+                match padded.clip(&r.origin, &inv_d, t, t_max) {
+                    Some((enter, exit)) => {
+                        t = enter;
+                        exit
+                    }
+                    None => return false,
+                }
+            }
+            None => t_max.min(t + self.max_distance / speed),
+        };
+        let mut d = self.field.distance(&r.at(t));
+
+        // A ray leaving the surface starts within epsilon of it. Step clear
+        // of that shell first so it doesn't hit where it started.
+        let mut steps = 0;
+        while d.abs() < self.epsilon && steps < self.max_steps {
+            t += 2.0 * self.epsilon / speed;
+            d = self.field.distance(&r.at(t));
+            steps += 1;
+        }
+        // Inside, trace the negated field to find the way out.
+        let sign = if d < 0.0 { -1.0 } else { 1.0 };
+
+        while steps < self.max_steps {
+            if t > t_max {
+                return false;
+            }
+            let dist = sign * d;
+            if dist < self.epsilon {
+                let p = r.at(t);
+                let outward_normal = unit_vector(&self.gradient(&p));
+                let front_face = dot(&r.direction, &outward_normal) < 0.0;
+                let (u, v) = sphere_uv(&outward_normal);
+                *rec = HitRecord {
+                    p,
+                    normal: if front_face {
+                        outward_normal
+                    } else {
+                        -outward_normal
+                    },
+                    mat_ptr: self.mat_ptr,
+                    t,
+                    u,
+                    v,
+                    tangent: Vec3::new(),
+                    front_face,
+                };
+                return true;
+            }
+            t += self.step_scale * dist / speed;
+            d = self.field.distance(&r.at(t));
+            steps += 1;
+        }
+        false
+    }
+
+    // Hits are reported up to `epsilon` short of the surface, so the box is
+    // grown by a little more than that.
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        match self.field.bounds() {
+            Some(b) => {
+                *output_box = b.padded(2.0 * self.epsilon);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(shape: &SdfShape<Sdf>, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        let r = Ray::of(origin, direction);
+        if shape.hit(&r, 0.001, f64::INFINITY, &mut rec) {
+            Some(rec)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn hits_a_sphere_from_outside() {
+        let shape = SdfShape::new(Sdf::sphere(1.0), 0);
+        let rec = trace(&shape, Point3::of(0.0, 0.0, 5.0), Vec3::of(0.0, 0.0, -1.0)).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-3);
+        assert!(rec.front_face);
+        assert!((rec.normal - Vec3::of(0.0, 0.0, 1.0)).length() < 1e-3);
+    }
+
+    #[test]
+    fn misses_beside_a_sphere() {
+        let shape = SdfShape::new(Sdf::sphere(1.0), 0);
+        assert!(trace(&shape, Point3::of(0.0, 1.5, 5.0), Vec3::of(0.0, 0.0, -1.0)).is_none());
+        assert!(trace(&shape, Point3::of(0.0, 0.0, 5.0), Vec3::of(0.0, 0.0, 1.0)).is_none());
+    }
+
+    #[test]
+    fn finds_the_way_out_from_inside() {
+        let shape = SdfShape::new(Sdf::sphere(1.0), 0);
+        let rec = trace(&shape, Point3::of(0.0, 0.0, 0.5), Vec3::of(0.0, 0.0, -1.0)).unwrap();
+        assert!((rec.t - 1.5).abs() < 1e-3);
+        assert!(!rec.front_face);
+        assert!((rec.normal - Vec3::of(0.0, 0.0, 1.0)).length() < 1e-3);
+    }
+
+    #[test]
+    fn bounded_fields_are_seen_from_afar() {
+        // Well beyond `max_distance`, which only limits unbounded fields.
+        let shape = SdfShape::new(Sdf::sphere(1.0).translate(Vec3::of(0.0, 0.0, -500.0)), 0);
+        let rec = trace(&shape, Point3::of(0.0, 0.0, 0.0), Vec3::of(0.0, 0.0, -1.0)).unwrap();
+        assert!((rec.t - 499.0).abs() < 1e-3);
+
+        let far = Point3::of(0.0, 0.0, 1000.0);
+        let shape = SdfShape::new(Sdf::sphere(1.0), 0);
+        assert!(trace(&shape, far, Vec3::of(0.0, 0.0, -1.0)).is_some());
+        let unbounded = SdfShape::new(|p: &Point3| p.length() - 1.0, 0);
+        let mut rec = HitRecord::default();
+        let r = Ray::of(far, Vec3::of(0.0, 0.0, -1.0));
+        assert!(!unbounded.hit(&r, 0.001, f64::INFINITY, &mut rec));
+        let unbounded = unbounded.with_max_distance(2000.0);
+        assert!(unbounded.hit(&r, 0.001, f64::INFINITY, &mut rec));
+        assert!((rec.t - 999.0).abs() < 1e-3);
+    }
+}
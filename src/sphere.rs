@@ -0,0 +1,82 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::vec3::*;
+use std::f64::consts::PI;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: Point3,
+    pub radius: f64,
+    pub mat_ptr: usize,
+}
+
+impl Sphere {
+    pub fn of(cen: Point3, radius: f64, mat_ptr: usize) -> Sphere {
+        Sphere {
+            center: cen,
+            radius,
+            mat_ptr,
+        }
+    }
+}
+
+// Longitude/latitude of a point on the unit sphere: u runs around the y axis
+// starting at -x, v from the bottom (-y) to the top.
+pub fn sphere_uv(p: &Point3) -> (f64, f64) {
+    let theta = (-p.y()).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let oc = r.origin - self.center;
+        let a = r.direction.length_squared();
+        let half_b = dot(&oc, &r.direction);
+        let c = oc.length_squared() - self.radius * self.radius;
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return false;
+        }
+        let sqrtd = discriminant.sqrt();
+
+        // Find the nearest root that lies in the acceptable range.
+        let mut root = (-half_b - sqrtd) / a;
+        if (root < t_min) || (t_max < root) {
+            root = (-half_b + sqrtd) / a;
+            if (root < t_min) || (t_max < root) {
+                return false;
+            }
+        }
+        let new_p = r.at(root);
+        let outward_normal = (new_p - self.center) / self.radius;
+        let (u, v) = sphere_uv(&((new_p - self.center) / self.radius.abs()));
+        let fface = dot(&r.direction, &outward_normal) < 0.0;
+        let new_normal = if fface {
+            outward_normal
+        } else {
+            -&outward_normal
+        };
+
+        *rec = HitRecord {
+            t: root,
+            p: new_p,
+            mat_ptr: self.mat_ptr,
+            normal: new_normal,
+            u,
+            v,
+            tangent: Vec3::new(),
+            front_face: fface,
+        };
+        true
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        let r = self.radius.abs();
+        let extent = Vec3::of(r, r, r);
+        *output_box = Aabb::of(self.center - extent, self.center + extent);
+        true
+    }
+}
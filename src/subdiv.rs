@@ -0,0 +1,496 @@
+// Subdivision surfaces. A coarse control cage is refined a fixed number of
+// times when the scene is built, by Catmull-Clark (any polygons, giving
+// quads) or Loop (triangles only), and the result is handed to the renderer
+// as an ordinary smooth-shaded `TriangleMesh`.
+//
+// Creases follow DeRose, Kass and Truong's semi-sharp rules: an edge of
+// sharpness s is refined with the sharp rules for the first s levels and
+// smoothly after that, blending for a fractional part. Open boundaries are
+// always sharp.
+
+use crate::mesh::TriangleMesh;
+use crate::vec3::*;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+// Subdividing stops short of the levels asked for rather than make more
+// faces than this.
+pub const MAX_FACES: usize = 1 << 22;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scheme {
+    CatmullClark,
+    Loop,
+}
+
+// Polygons index `positions` and wind counter-clockwise seen from the front.
+// `creases` holds the sharpness of creased edges under their (lower, higher)
+// vertex indices; `f64::INFINITY` keeps an edge sharp at every level.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cage {
+    pub positions: Vec<Point3>,
+    pub faces: Vec<Vec<u32>>,
+    pub creases: HashMap<(u32, u32), f64>,
+}
+
+fn edge_key(a: u32, b: u32) -> (u32, u32) {
+    (a.min(b), a.max(b))
+}
+
+fn mix(s: f64, smooth: Point3, sharp: Point3) -> Point3 {
+    if s >= 1.0 {
+        sharp
+    } else {
+        smooth * (1.0 - s) + sharp * s
+    }
+}
+
+fn average<I: Iterator<Item = Point3>>(points: I) -> Point3 {
+    let (sum, n) = points.fold((Vec3::new(), 0), |(sum, n), p| (sum + p, n + 1));
+    sum / n as f64
+}
+
+// Edges and incidences of a cage, with edges numbered in the order they are
+// first met going through the faces.
+struct Topology {
+    edges: Vec<(u32, u32)>,
+    edge_faces: Vec<Vec<usize>>,
+    edge_index: HashMap<(u32, u32), usize>,
+    vertex_edges: Vec<Vec<usize>>,
+    vertex_faces: Vec<Vec<usize>>,
+}
+
+impl Topology {
+    fn new(cage: &Cage) -> Topology {
+        let mut topology = Topology {
+            edges: Vec::new(),
+            edge_faces: Vec::new(),
+            edge_index: HashMap::new(),
+            vertex_edges: vec![Vec::new(); cage.positions.len()],
+            vertex_faces: vec![Vec::new(); cage.positions.len()],
+        };
+        for (f, face) in cage.faces.iter().enumerate() {
+            for (k, &a) in face.iter().enumerate() {
+                let b = face[(k + 1) % face.len()];
+                topology.vertex_faces[a as usize].push(f);
+                let key = edge_key(a, b);
+                let e = match topology.edge_index.get(&key) {
+                    Some(&e) => e,
+                    None => {
+                        let e = topology.edges.len();
+                        topology.edges.push(key);
+                        topology.edge_faces.push(Vec::new());
+                        topology.edge_index.insert(key, e);
+                        topology.vertex_edges[a as usize].push(e);
+                        topology.vertex_edges[b as usize].push(e);
+                        e
+                    }
+                };
+                topology.edge_faces[e].push(f);
+            }
+        }
+        topology
+    }
+
+    fn edge(&self, a: u32, b: u32) -> usize {
+        self.edge_index[&edge_key(a, b)]
+    }
+
+    fn other_end(&self, e: usize, v: u32) -> u32 {
+        let (a, b) = self.edges[e];
+        if a == v {
+            b
+        } else {
+            a
+        }
+    }
+}
+
+// Why `face` can't be part of a cage of `vertices` points: it needs three
+// or more of them, each in range and none repeated, or the edges around it
+// don't join up.
+pub fn check_face(face: &[u32], vertices: usize) -> Result<(), String> {
+    if face.len() < 3 {
+        return Err(String::from("a face needs three or more vertices"));
+    }
+    if let Some(i) = face.iter().find(|&&i| i as usize >= vertices) {
+        return Err(format!("vertex {} out of range", i));
+    }
+    for (k, i) in face.iter().enumerate() {
+        if face[k + 1..].contains(i) {
+            return Err(format!("vertex {} repeated in a face", i));
+        }
+    }
+    Ok(())
+}
+
+impl Cage {
+    pub fn new(positions: Vec<Point3>, faces: Vec<Vec<u32>>) -> Result<Cage, String> {
+        for face in &faces {
+            check_face(face, positions.len())?;
+        }
+        Ok(Cage {
+            positions,
+            faces,
+            creases: HashMap::new(),
+        })
+    }
+
+    // The cube [-1, 1]^3 as six quads.
+    pub fn cube() -> Cage {
+        let positions = (0..8)
+            .map(|i| {
+                let c = |bit: u32| if i & bit != 0 { 1.0 } else { -1.0 };
+                Point3::of(c(1), c(2), c(4))
+            })
+            .collect();
+        let faces = vec![
+            vec![0, 4, 6, 2],
+            vec![1, 3, 7, 5],
+            vec![0, 1, 5, 4],
+            vec![2, 6, 7, 3],
+            vec![0, 2, 3, 1],
+            vec![4, 5, 7, 6],
+        ];
+        Cage {
+            positions,
+            faces,
+            creases: HashMap::new(),
+        }
+    }
+
+    // The octahedron with vertices at distance 1 along each axis, as
+    // triangles: +x, -x, +y, -y, +z, -z.
+    pub fn octahedron() -> Cage {
+        let positions = vec![
+            Point3::of(1.0, 0.0, 0.0),
+            Point3::of(-1.0, 0.0, 0.0),
+            Point3::of(0.0, 1.0, 0.0),
+            Point3::of(0.0, -1.0, 0.0),
+            Point3::of(0.0, 0.0, 1.0),
+            Point3::of(0.0, 0.0, -1.0),
+        ];
+        let faces = vec![
+            vec![4, 0, 2],
+            vec![0, 5, 2],
+            vec![5, 1, 2],
+            vec![1, 4, 2],
+            vec![0, 4, 3],
+            vec![5, 0, 3],
+            vec![1, 5, 3],
+            vec![4, 1, 3],
+        ];
+        Cage {
+            positions,
+            faces,
+            creases: HashMap::new(),
+        }
+    }
+
+    pub fn with_crease(mut self, a: u32, b: u32, sharpness: f64) -> Cage {
+        self.creases.insert(edge_key(a, b), sharpness);
+        self
+    }
+
+    pub fn is_triangles(&self) -> bool {
+        self.faces.iter().all(|f| f.len() == 3)
+    }
+
+    fn sharpness(&self, topology: &Topology, e: usize) -> f64 {
+        if topology.edge_faces[e].len() != 2 {
+            return f64::INFINITY;
+        }
+        self.creases.get(&topology.edges[e]).cloned().unwrap_or(0.0)
+    }
+
+    // Applies the crease rules to vertex `v` given its smooth position and
+    // how to place it on a crease between two neighbours: a vertex on one
+    // sharp edge stays smooth, on two follows the crease, and on more is a
+    // corner that doesn't move.
+    fn vertex_rule<F: Fn(Point3, Point3) -> Point3>(
+        &self,
+        topology: &Topology,
+        v: u32,
+        smooth: Point3,
+        crease: F,
+    ) -> Point3 {
+        let sharp: Vec<(usize, f64)> = topology.vertex_edges[v as usize]
+            .iter()
+            .map(|&e| (e, self.sharpness(topology, e)))
+            .filter(|&(_, s)| s > 0.0)
+            .collect();
+        if sharp.len() < 2 {
+            return smooth;
+        }
+        let s = sharp.iter().map(|&(_, s)| s).sum::<f64>() / sharp.len() as f64;
+        let rule = if sharp.len() == 2 {
+            let end = |k: usize| self.positions[topology.other_end(sharp[k].0, v) as usize];
+            crease(end(0), end(1))
+        } else {
+            self.positions[v as usize]
+        };
+        mix(s, smooth, rule)
+    }
+
+    // Sharpness of the two halves of each creased edge one level down.
+    fn child_creases(
+        &self,
+        topology: &Topology,
+        edge_point: &dyn Fn(usize) -> u32,
+    ) -> HashMap<(u32, u32), f64> {
+        let mut creases = HashMap::new();
+        for (e, &(a, b)) in topology.edges.iter().enumerate() {
+            let s = match self.creases.get(&(a, b)) {
+                Some(&s) if s > 1.0 => s - 1.0,
+                _ => continue,
+            };
+            let m = edge_point(e);
+            creases.insert(edge_key(a, m), s);
+            creases.insert(edge_key(m, b), s);
+        }
+        creases
+    }
+
+    // One level of Catmull-Clark. Each n-gon becomes n quads.
+    pub fn catmull_clark(&self) -> Cage {
+        let topology = Topology::new(self);
+        let p = |i: u32| self.positions[i as usize];
+        let face_points: Vec<Point3> = self
+            .faces
+            .iter()
+            .map(|f| average(f.iter().map(|&i| p(i))))
+            .collect();
+        let edge_points: Vec<Point3> = topology
+            .edges
+            .iter()
+            .enumerate()
+            .map(|(e, &(a, b))| {
+                let mid = (p(a) + p(b)) * 0.5;
+                let faces = &topology.edge_faces[e];
+                if faces.len() != 2 {
+                    return mid;
+                }
+                let smooth = (p(a) + p(b) + face_points[faces[0]] + face_points[faces[1]]) * 0.25;
+                mix(self.sharpness(&topology, e), smooth, mid)
+            })
+            .collect();
+        let vertex_points = (0..self.positions.len() as u32).map(|v| {
+            let faces = &topology.vertex_faces[v as usize];
+            let edges = &topology.vertex_edges[v as usize];
+            if faces.is_empty() {
+                return p(v);
+            }
+            let n = edges.len() as f64;
+            let q = average(faces.iter().map(|&f| face_points[f]));
+            let r = average(edges.iter().map(|&e| {
+                let (a, b) = topology.edges[e];
+                (p(a) + p(b)) * 0.5
+            }));
+            let smooth = (q + r * 2.0 + p(v) * (n - 3.0)) / n;
+            self.vertex_rule(&topology, v, smooth, |a, b| (a + b + p(v) * 6.0) / 8.0)
+        });
+
+        // Old vertices keep their numbers, then edge points, then face points.
+        let mut positions: Vec<Point3> = vertex_points.collect();
+        let edge_base = positions.len() as u32;
+        positions.extend(edge_points);
+        let face_base = positions.len() as u32;
+        positions.extend(face_points);
+
+        let edge_point = |a: u32, b: u32| edge_base + topology.edge(a, b) as u32;
+        let mut faces = Vec::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            let n = face.len();
+            for k in 0..n {
+                let (prev, v, next) = (face[(k + n - 1) % n], face[k], face[(k + 1) % n]);
+                faces.push(vec![
+                    v,
+                    edge_point(v, next),
+                    face_base + f as u32,
+                    edge_point(prev, v),
+                ]);
+            }
+        }
+        let creases = self.child_creases(&topology, &|e| edge_base + e as u32);
+        Cage {
+            positions,
+            faces,
+            creases,
+        }
+    }
+
+    // One level of Loop subdivision. Each triangle becomes four.
+    pub fn loop_subdivide(&self) -> Cage {
+        assert!(self.is_triangles(), "Loop subdivision needs triangles");
+        let topology = Topology::new(self);
+        let p = |i: u32| self.positions[i as usize];
+        let edge_points: Vec<Point3> = topology
+            .edges
+            .iter()
+            .enumerate()
+            .map(|(e, &(a, b))| {
+                let mid = (p(a) + p(b)) * 0.5;
+                let faces = &topology.edge_faces[e];
+                if faces.len() != 2 {
+                    return mid;
+                }
+                let opposite = |f: usize| {
+                    let v = self.faces[f].iter().find(|&&v| v != a && v != b);
+                    p(*v.unwrap())
+                };
+                let smooth =
+                    (p(a) + p(b)) * (3.0 / 8.0) + (opposite(faces[0]) + opposite(faces[1])) / 8.0;
+                mix(self.sharpness(&topology, e), smooth, mid)
+            })
+            .collect();
+        let vertex_points = (0..self.positions.len() as u32).map(|v| {
+            let edges = &topology.vertex_edges[v as usize];
+            if edges.is_empty() {
+                return p(v);
+            }
+            let n = edges.len() as f64;
+            let c = 3.0 / 8.0 + 0.25 * (2.0 * PI / n).cos();
+            let beta = (5.0 / 8.0 - c * c) / n;
+            let ring = edges
+                .iter()
+                .fold(Vec3::new(), |sum, &e| sum + p(topology.other_end(e, v)));
+            let smooth = p(v) * (1.0 - n * beta) + ring * beta;
+            self.vertex_rule(&topology, v, smooth, |a, b| p(v) * 0.75 + (a + b) * 0.125)
+        });
+
+        let mut positions: Vec<Point3> = vertex_points.collect();
+        let edge_base = positions.len() as u32;
+        positions.extend(edge_points);
+
+        let edge_point = |a: u32, b: u32| edge_base + topology.edge(a, b) as u32;
+        let mut faces = Vec::new();
+        for face in &self.faces {
+            let (a, b, c) = (face[0], face[1], face[2]);
+            let (ab, bc, ca) = (edge_point(a, b), edge_point(b, c), edge_point(c, a));
+            faces.push(vec![a, ab, ca]);
+            faces.push(vec![b, bc, ab]);
+            faces.push(vec![c, ca, bc]);
+            faces.push(vec![ab, bc, ca]);
+        }
+        let creases = self.child_creases(&topology, &|e| edge_base + e as u32);
+        Cage {
+            positions,
+            faces,
+            creases,
+        }
+    }
+
+    // Faces after `levels` levels: Catmull-Clark turns each n-gon into n
+    // quads and then each quad into four, and Loop each triangle into four.
+    pub fn faces_after(&self, scheme: Scheme, levels: u32) -> usize {
+        let mut count = self.faces.len();
+        for level in 0..levels {
+            count = match (scheme, level) {
+                (Scheme::CatmullClark, 0) => self.faces.iter().map(Vec::len).sum(),
+                _ => count.saturating_mul(4),
+            };
+        }
+        count
+    }
+
+    // Refines `levels` times, or as many times as `MAX_FACES` allows.
+    pub fn subdivide(&self, scheme: Scheme, levels: u32) -> Cage {
+        let mut cage = self.clone();
+        for _ in 0..levels {
+            if cage.faces_after(scheme, 1) > MAX_FACES {
+                break;
+            }
+            cage = match scheme {
+                Scheme::CatmullClark => cage.catmull_clark(),
+                Scheme::Loop => cage.loop_subdivide(),
+            };
+        }
+        cage
+    }
+
+    // Splits the polygons into triangle fans and smooths the normals.
+    pub fn to_mesh(&self, mat_ptr: usize) -> TriangleMesh {
+        let indices = self
+            .faces
+            .iter()
+            .flat_map(|f| (1..f.len() - 1).map(move |k| [f[0], f[k], f[k + 1]]))
+            .collect();
+        TriangleMesh::new(self.positions.clone(), indices, mat_ptr).with_smooth_normals()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creases_hold_their_shape() {
+        // With every edge sharp the corners stay put and the edges and faces
+        // stay flat, so nothing leaves the cube's surface.
+        let mut cube = Cage::cube();
+        for face in cube.faces.clone() {
+            for k in 0..4 {
+                cube = cube.with_crease(face[k], face[(k + 1) % 4], f64::INFINITY);
+            }
+        }
+        let fine = cube.subdivide(Scheme::CatmullClark, 3);
+        assert_eq!(fine.faces.len(), 6 * 64);
+        for p in &fine.positions {
+            let m = p.x().abs().max(p.y().abs()).max(p.z().abs());
+            assert!((m - 1.0).abs() < 1e-12, "{:?}", p);
+        }
+
+        // Left smooth, the cube shrinks towards a rounded shape.
+        let smooth = Cage::cube().subdivide(Scheme::CatmullClark, 3);
+        assert!(smooth
+            .positions
+            .iter()
+            .all(|p| p.length() < 3f64.sqrt() - 0.1));
+
+        // The equator of a Loop octahedron creased for good becomes a
+        // B-spline curve of its own, staying in its plane and inside the
+        // square it started as.
+        let mut octahedron = Cage::octahedron();
+        for (a, b) in [(0, 4), (4, 1), (1, 5), (5, 0)] {
+            octahedron = octahedron.with_crease(a, b, f64::INFINITY);
+        }
+        let fine = octahedron.subdivide(Scheme::Loop, 2);
+        assert_eq!(fine.faces.len(), 8 * 16);
+        let ring: Vec<&Point3> = fine.positions.iter().filter(|p| p.y() == 0.0).collect();
+        assert_eq!(ring.len(), 16);
+        for p in ring {
+            assert!(p.x().abs() + p.z().abs() <= 1.0, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn cages_need_proper_faces() {
+        let corners = || Cage::cube().positions;
+        assert!(Cage::new(corners(), vec![vec![0, 1, 3]]).is_ok());
+        for bad in [vec![0, 1], vec![0, 1, 8], vec![0, 0, 1], vec![0, 1, 3, 1]] {
+            assert!(
+                Cage::new(corners(), vec![bad.clone()]).is_err(),
+                "{:?}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn subdividing_stops_at_the_face_budget() {
+        let cube = Cage::cube();
+        assert_eq!(cube.faces_after(Scheme::CatmullClark, 0), 6);
+        assert_eq!(cube.faces_after(Scheme::CatmullClark, 3), 6 * 4 * 16);
+        assert_eq!(Cage::octahedron().faces_after(Scheme::Loop, 2), 8 * 16);
+        assert_eq!(cube.faces_after(Scheme::Loop, 200), usize::MAX);
+
+        // A level more than the budget allows is left out.
+        let levels = (0..).find(|&l| cube.faces_after(Scheme::CatmullClark, l) > MAX_FACES);
+        let levels = levels.unwrap();
+        let fine = cube.subdivide(Scheme::CatmullClark, levels);
+        assert_eq!(
+            fine.faces.len(),
+            cube.faces_after(Scheme::CatmullClark, levels - 1)
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use crate::aabb::Aabb;
+use crate::camera::degrees_to_radians;
+use crate::hittable::{HitRecord, Hittable};
+use crate::poly::{solve_quadratic, solve_quartic};
+use crate::quadric::{fill_hit, phi_of, Surface};
+use crate::ray::Ray;
+use crate::vec3::*;
+use std::f64::consts::PI;
+
+// Torus around the z axis: a tube of `minor_radius` whose centre runs round a
+// circle of `major_radius` in the xy plane. Like the quadrics it can be swept
+// through `phi_max` degrees, which makes pipe bends. u runs around the axis
+// and v around the tube, starting from the outer equator.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Torus {
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub phi_max: f64,
+    pub mat_ptr: usize,
+}
+
+impl Torus {
+    pub fn of(major_radius: f64, minor_radius: f64, mat_ptr: usize) -> Torus {
+        Torus {
+            major_radius,
+            minor_radius,
+            phi_max: 360.0,
+            mat_ptr,
+        }
+    }
+
+    pub fn with_phi_max(mut self, degrees: f64) -> Torus {
+        self.phi_max = degrees.clamp(0.0, 360.0);
+        self
+    }
+}
+
+impl Surface for Torus {
+    fn point(&self, u: f64, v: f64) -> Point3 {
+        let phi = u * degrees_to_radians(self.phi_max);
+        let theta = 2.0 * PI * v;
+        let rho = self.major_radius + self.minor_radius * theta.cos();
+        Point3::of(
+            rho * phi.cos(),
+            rho * phi.sin(),
+            self.minor_radius * theta.sin(),
+        )
+    }
+
+    // Gradient of (|p|^2 + R^2 - r^2)^2 - 4 R^2 (x^2 + y^2), which unlike
+    // p minus the tube's centre stays defined on the axis.
+    fn outward_normal(&self, p: &Point3) -> Vec3 {
+        let big2 = self.major_radius * self.major_radius;
+        let k = p.length_squared() + big2 - self.minor_radius * self.minor_radius;
+        let n = *p * k - Vec3::of(p.x(), p.y(), 0.0) * (2.0 * big2);
+        if n.length_squared() > 0.0 {
+            unit_vector(&n)
+        } else {
+            n
+        }
+    }
+}
+
+// Newton steps on x^4 + c3 x^3 + c2 x^2 + c1 x + c0 to win back the
+// precision the closed form loses near double roots. At a double root the
+// derivative vanishes along with f, so a step that leaves f no smaller is
+// not taken.
+fn polish(x: f64, [c3, c2, c1, c0]: [f64; 4]) -> f64 {
+    let f = |x: f64| (((x + c3) * x + c2) * x + c1) * x + c0;
+    let mut x = x;
+    for _ in 0..2 {
+        let df = ((4.0 * x + 3.0 * c3) * x + 2.0 * c2) * x + c1;
+        if df == 0.0 {
+            break;
+        }
+        let next = x - f(x) / df;
+        if f(next).abs() >= f(x).abs() {
+            break;
+        }
+        x = next;
+    }
+    x
+}
+
+impl Hittable for Torus {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (big, small) = (self.major_radius, self.minor_radius);
+        let len = r.direction.length();
+        let dir = r.direction / len;
+
+        // Clip to the bounding sphere first and start the ray from where it
+        // enters, so the quartic is solved close to its roots.
+        let bound = big + small;
+        let oc = r.origin;
+        let (enter, exit) = match solve_quadratic(
+            1.0,
+            2.0 * dot(&oc, &dir),
+            oc.length_squared() - bound * bound,
+        ) {
+            Some(x) => x,
+            None => return false,
+        };
+        if exit < t_min * len || enter > t_max * len {
+            return false;
+        }
+        let s0 = enter.max(t_min * len);
+
+        // In units of the major radius, along a unit direction:
+        // (|p|^2 - 1 - r^2)^2 = 4 (r^2 - z^2) with p = o + s d.
+        let o = (r.origin + dir * s0) / big;
+        let rr = small / big;
+        let e = o.length_squared() - 1.0 - rr * rr;
+        let f = dot(&o, &dir);
+        let c = [
+            4.0 * f,
+            2.0 * e + 4.0 * f * f + 4.0 * dir.z() * dir.z(),
+            4.0 * f * e + 8.0 * o.z() * dir.z(),
+            e * e - 4.0 * (rr * rr - o.z() * o.z()),
+        ];
+
+        let phi_max = degrees_to_radians(self.phi_max);
+        let mut roots: Vec<f64> = solve_quartic(c[0], c[1], c[2], c[3])
+            .into_iter()
+            .map(|s| (s0 + polish(s, c) * big) / len)
+            .collect();
+        roots.sort_by(|a, b| a.total_cmp(b));
+        let hit = roots
+            .into_iter()
+            .filter(|&t| t >= t_min && t <= t_max)
+            .map(|t| (t, r.at(t)))
+            .find(|(_, p)| phi_of(p) <= phi_max);
+
+        match hit {
+            Some((t, p)) => {
+                let rho = (p.x() * p.x() + p.y() * p.y()).sqrt();
+                let theta = p.z().atan2(rho - big);
+                let theta = if theta < 0.0 { theta + 2.0 * PI } else { theta };
+                let uv = (phi_of(&p) / phi_max, theta / (2.0 * PI));
+                fill_hit(r, (t, p), self.outward_normal(&p), uv, self.mat_ptr, rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        let minor = self.minor_radius.abs();
+        let outer = self.major_radius.abs() + minor;
+        *output_box = Aabb::of(
+            Point3::of(-outer, -outer, -minor),
+            Point3::of(outer, outer, minor),
+        );
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(torus: &Torus, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        let r = Ray::of(origin, direction);
+        torus.hit(&r, 0.001, f64::INFINITY, &mut rec).then_some(rec)
+    }
+
+    // Distance from `p` to the circle through the middle of the tube.
+    fn tube_distance(torus: &Torus, p: Point3) -> f64 {
+        let rho = (p.x() * p.x() + p.y() * p.y()).sqrt();
+        (rho - torus.major_radius).hypot(p.z())
+    }
+
+    #[test]
+    fn rays_down_the_axis_pass_through_the_hole() {
+        let torus = Torus::of(2.0, 0.5, 0);
+        assert!(hit(&torus, Point3::of(0.0, 0.0, 5.0), Vec3::of(0.0, 0.0, -1.0)).is_none());
+        assert!(hit(&torus, Point3::of(0.3, -0.2, -5.0), Vec3::of(0.0, 0.0, 1.0)).is_none());
+    }
+
+    #[test]
+    fn uvs_start_on_the_outer_equator() {
+        let torus = Torus::of(2.0, 0.5, 0);
+        assert_eq!(torus.point(0.0, 0.0), Point3::of(2.5, 0.0, 0.0));
+        let rec = hit(&torus, Point3::of(5.0, 0.0, 0.0), Vec3::of(-1.0, 0.0, 0.0)).unwrap();
+        assert!((rec.t - 2.5).abs() < 1e-9);
+        assert!(
+            rec.u.abs() < 1e-9 && rec.v.abs() < 1e-9,
+            "{:?}",
+            (rec.u, rec.v)
+        );
+        assert!(rec.front_face);
+        assert!((rec.normal - Vec3::of(1.0, 0.0, 0.0)).length() < 1e-9);
+
+        // A quarter of the way round the axis and round the tube, on top.
+        let rec = hit(&torus, Point3::of(0.0, 2.0, 3.0), Vec3::of(0.0, 0.0, -1.0)).unwrap();
+        assert!((rec.u - 0.25).abs() < 1e-9 && (rec.v - 0.25).abs() < 1e-9);
+        assert!((torus.point(rec.u, rec.v) - rec.p).length() < 1e-9);
+    }
+
+    #[test]
+    fn grazing_rays_touch_or_miss_cleanly() {
+        let torus = Torus::of(2.0, 0.5, 0);
+        // Along the outer equator's tangent at (2.5, 0, 0), and along the
+        // top of the tube, tangent at (-2, 0, 0.5) and (2, 0, 0.5).
+        let grazes = [
+            (Point3::of(2.5, -5.0, 0.0), Vec3::of(0.0, 1.0, 0.0)),
+            (Point3::of(-5.0, 0.0, 0.5), Vec3::of(1.0, 0.0, 0.0)),
+        ];
+        for &(origin, direction) in &grazes {
+            if let Some(rec) = hit(&torus, origin, direction) {
+                assert!(rec.t.is_finite() && rec.normal.length() > 0.99);
+                assert!((tube_distance(&torus, rec.p) - 0.5).abs() < 1e-6);
+            }
+            let nudge = if origin.z() == 0.0 {
+                Vec3::of(1e-4, 0.0, 0.0)
+            } else {
+                Vec3::of(0.0, 0.0, 1e-4)
+            };
+            assert!(hit(&torus, origin + nudge, direction).is_none());
+            for &depth in &[1e-2, 1e-3, 1e-4, 1e-5, 1e-6] {
+                let rec = hit(&torus, origin - nudge * (depth / 1e-4), direction).unwrap();
+                assert!((tube_distance(&torus, rec.p) - 0.5).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn hits_lie_on_the_surface_with_the_gradient_as_normal() {
+        let torus = Torus::of(2.0, 0.5, 0);
+        let implicit = |p: Point3| {
+            let k = p.length_squared() + 4.0 - 0.25;
+            k * k - 16.0 * (p.x() * p.x() + p.y() * p.y())
+        };
+        let mut hits = 0;
+        for i in 0..200 {
+            let a = i as f64 * 0.37;
+            let origin = Point3::of(6.0 * a.cos(), 6.0 * a.sin(), 3.0 * (a * 1.7).sin());
+            let b = i as f64 * 1.13;
+            let target = Point3::of(2.0 * b.cos(), 2.0 * b.sin(), 0.6 * (a * 2.3).cos());
+            let rec = match hit(&torus, origin, target - origin) {
+                Some(rec) => rec,
+                None => continue,
+            };
+            hits += 1;
+            assert!((tube_distance(&torus, rec.p) - 0.5).abs() < 1e-9);
+            assert!(dot(&rec.normal, &(target - origin)) < 0.0);
+
+            let h = 1e-6;
+            let axis = |d: Vec3| (implicit(rec.p + d * h) - implicit(rec.p - d * h)) / (2.0 * h);
+            let gradient = Vec3::of(
+                axis(Vec3::of(1.0, 0.0, 0.0)),
+                axis(Vec3::of(0.0, 1.0, 0.0)),
+                axis(Vec3::of(0.0, 0.0, 1.0)),
+            );
+            let outward = if rec.front_face {
+                rec.normal
+            } else {
+                -rec.normal
+            };
+            assert!((outward - unit_vector(&gradient)).length() < 1e-5);
+        }
+        assert!(hits > 100, "{} hits", hits);
+    }
+
+    #[test]
+    fn sweeps_stop_at_phi_max() {
+        let torus = Torus::of(2.0, 0.5, 0).with_phi_max(90.0);
+        // Along x through the hole: the tube at phi = 180 degrees is cut
+        // away, leaving the inside of the tube at phi = 0.
+        let rec = hit(&torus, Point3::of(-5.0, 0.0, 0.0), Vec3::of(1.0, 0.0, 0.0)).unwrap();
+        assert!((rec.t - 6.5).abs() < 1e-9);
+        assert!(rec.front_face);
+        assert!(rec.u.abs() < 1e-9 && (rec.v - 0.5).abs() < 1e-9);
+
+        let diagonal = Vec3::of(-1.0, 1.0, 0.0);
+        assert!(hit(&torus, Point3::new(), diagonal).is_none());
+        let full = Torus::of(2.0, 0.5, 0);
+        let rec = hit(&full, Point3::new(), diagonal).unwrap();
+        assert!((rec.t * diagonal.length() - 1.5).abs() < 1e-9);
+    }
+}
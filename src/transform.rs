@@ -0,0 +1,74 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::mat4::Mat4;
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Places any hittable in the world with an affine object-to-world matrix.
+// Rays are moved into object space without renormalising the direction, so
+// `t` means the same thing on both sides and needs no conversion.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transformed<H: Hittable> {
+    pub object: H,
+    object_to_world: Mat4,
+    world_to_object: Mat4,
+    // Inverse transpose of the linear part, for normals.
+    normal_to_world: Mat4,
+}
+
+impl<H: Hittable> Transformed<H> {
+    // Panics if `object_to_world` is singular.
+    pub fn new(object: H, object_to_world: Mat4) -> Transformed<H> {
+        let world_to_object = object_to_world
+            .inverse()
+            .expect("object-to-world transform is not invertible");
+        Transformed::with_inverse(object, object_to_world, world_to_object)
+    }
+
+    pub fn with_inverse(object: H, object_to_world: Mat4, world_to_object: Mat4) -> Transformed<H> {
+        Transformed {
+            object,
+            object_to_world,
+            world_to_object,
+            normal_to_world: world_to_object.transpose(),
+        }
+    }
+
+    pub fn object_to_world(&self) -> &Mat4 {
+        &self.object_to_world
+    }
+
+    pub fn world_to_object(&self) -> &Mat4 {
+        &self.world_to_object
+    }
+}
+
+impl<H: Hittable> Hittable for Transformed<H> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let object_ray = Ray::of(
+            self.world_to_object.transform_point(&r.origin),
+            self.world_to_object.transform_vector(&r.direction),
+        );
+        if !self.object.hit(&object_ray, t_min, t_max, rec) {
+            return false;
+        }
+
+        // dot(n', d') == dot(n, d) under the inverse transpose, so the normal
+        // still faces the ray and `front_face` carries over unchanged.
+        rec.p = self.object_to_world.transform_point(&rec.p);
+        rec.normal = unit_vector(&self.normal_to_world.transform_vector(&rec.normal));
+        if rec.tangent.length_squared() > 0.0 {
+            rec.tangent = unit_vector(&self.object_to_world.transform_vector(&rec.tangent));
+        }
+        true
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        let mut object_box = Aabb::empty();
+        if !self.object.bounding_box(time0, time1, &mut object_box) {
+            return false;
+        }
+        *output_box = object_box.transformed(&self.object_to_world);
+        true
+    }
+}
@@ -0,0 +1,296 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Index of the largest component by magnitude.
+fn max_dimension(v: &Vec3) -> usize {
+    let (x, y, z) = (v.x().abs(), v.y().abs(), v.z().abs());
+    if x > y {
+        if x > z {
+            0
+        } else {
+            2
+        }
+    } else if y > z {
+        1
+    } else {
+        2
+    }
+}
+
+fn permute(v: &Vec3, kx: usize, ky: usize, kz: usize) -> Vec3 {
+    Vec3::of(v[kx], v[ky], v[kz])
+}
+
+// Watertight ray/triangle test (Woop, Benthin and Wald 2013, as in pbrt).
+// The triangle is moved into a space where the ray starts at the origin and
+// runs down +z, so the edge functions are evaluated identically for triangles
+// that share an edge and rays cannot slip between them.
+// Returns `t` and the barycentric weights of `p0`, `p1` and `p2`.
+pub fn intersect_triangle(
+    r: &Ray,
+    p0: &Point3,
+    p1: &Point3,
+    p2: &Point3,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64, f64)> {
+    let kz = max_dimension(&r.direction);
+    let kx = (kz + 1) % 3;
+    let ky = (kx + 1) % 3;
+    let d = permute(&r.direction, kx, ky, kz);
+    if d.z() == 0.0 {
+        return None;
+    }
+    let p0t = permute(&(*p0 - r.origin), kx, ky, kz);
+    let p1t = permute(&(*p1 - r.origin), kx, ky, kz);
+    let p2t = permute(&(*p2 - r.origin), kx, ky, kz);
+
+    let sx = -d.x() / d.z();
+    let sy = -d.y() / d.z();
+    let sz = 1.0 / d.z();
+    let (x0, y0) = (p0t.x() + sx * p0t.z(), p0t.y() + sy * p0t.z());
+    let (x1, y1) = (p1t.x() + sx * p1t.z(), p1t.y() + sy * p1t.z());
+    let (x2, y2) = (p2t.x() + sx * p2t.z(), p2t.y() + sy * p2t.z());
+
+    let e0 = x1 * y2 - y1 * x2;
+    let e1 = x2 * y0 - y2 * x0;
+    let e2 = x0 * y1 - y0 * x1;
+    if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
+        return None;
+    }
+    let det = e0 + e1 + e2;
+    if det == 0.0 {
+        return None;
+    }
+    // Rounding can still put the ray inside a triangle with no area, which
+    // has no normal to give the hit.
+    if cross(&(*p1 - *p0), &(*p2 - *p0)).length_squared() == 0.0 {
+        return None;
+    }
+
+    // Compare against the range before dividing by det.
+    let t_scaled = (e0 * p0t.z() + e1 * p1t.z() + e2 * p2t.z()) * sz;
+    if det < 0.0 && (t_scaled > t_min * det || t_scaled < t_max * det) {
+        return None;
+    }
+    if det > 0.0 && (t_scaled < t_min * det || t_scaled > t_max * det) {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    Some((t_scaled * inv_det, e0 * inv_det, e1 * inv_det, e2 * inv_det))
+}
+
+// Fills `rec` for a hit returned by `intersect_triangle`. The geometric
+// normal follows the winding of `p`; a shading normal, if given, is kept on
+// the same side as the geometric one so that front and back stay consistent.
+pub fn fill_triangle_hit(
+    r: &Ray,
+    p: [&Point3; 3],
+    normals: Option<[&Vec3; 3]>,
+    uv: [(f64, f64); 3],
+    (t, b0, b1, b2): (f64, f64, f64, f64),
+    mat_ptr: usize,
+    rec: &mut HitRecord,
+) {
+    let b = [b0, b1, b2];
+    let geometric = unit_vector(&cross(&(p[1] - p[0]), &(p[2] - p[0])));
+    let front_face = dot(&r.direction, &geometric) < 0.0;
+    let facing = if front_face { geometric } else { -geometric };
+
+    let normal = match normals {
+        Some(n) => {
+            let shading = unit_vector(&(n[0] * b[0] + n[1] * b[1] + n[2] * b[2]));
+            if dot(&shading, &facing) < 0.0 {
+                -shading
+            } else {
+                shading
+            }
+        }
+        None => facing,
+    };
+
+    *rec = HitRecord {
+        p: p[0] * b[0] + p[1] * b[1] + p[2] * b[2],
+        normal,
+        mat_ptr,
+        t,
+        u: uv[0].0 * b[0] + uv[1].0 * b[1] + uv[2].0 * b[2],
+        v: uv[0].1 * b[0] + uv[1].1 * b[1] + uv[2].1 * b[2],
+        tangent: Vec3::new(),
+        front_face,
+    };
+}
+
+// Vertices wind counter-clockwise seen from the front. With the default UVs,
+// `u` and `v` are the barycentric weights of `p[1]` and `p[2]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Triangle {
+    pub p: [Point3; 3],
+    pub normals: Option<[Vec3; 3]>,
+    pub uv: [(f64, f64); 3],
+    pub mat_ptr: usize,
+}
+
+impl Triangle {
+    pub fn of(p0: Point3, p1: Point3, p2: Point3, mat_ptr: usize) -> Triangle {
+        Triangle {
+            p: [p0, p1, p2],
+            normals: None,
+            uv: [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            mat_ptr,
+        }
+    }
+
+    pub fn with_normals(mut self, n0: Vec3, n1: Vec3, n2: Vec3) -> Triangle {
+        self.normals = Some([n0, n1, n2]);
+        self
+    }
+
+    pub fn with_uv(mut self, uv: [(f64, f64); 3]) -> Triangle {
+        self.uv = uv;
+        self
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let [p0, p1, p2] = &self.p;
+        match intersect_triangle(r, p0, p1, p2, t_min, t_max) {
+            Some(hit) => {
+                let normals = self.normals.as_ref().map(|[a, b, c]| [a, b, c]);
+                fill_triangle_hit(r, [p0, p1, p2], normals, self.uv, hit, self.mat_ptr, rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        *output_box = Aabb::from_points(&self.p);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn hit(tri: &Triangle, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        let r = Ray::of(origin, direction);
+        tri.hit(&r, 0.001, f64::INFINITY, &mut rec).then_some(rec)
+    }
+
+    #[test]
+    fn nothing_slips_between_neighbours() {
+        // Six triangles fanned around a shared vertex.
+        let centre = Point3::of(0.3, -0.2, 0.1);
+        let rim: Vec<Point3> = (0..6)
+            .map(|i| {
+                let phi = std::f64::consts::TAU * i as f64 / 6.0;
+                centre + Vec3::of(phi.cos(), phi.sin(), 0.2 * (3.0 * phi).sin())
+            })
+            .collect();
+        let fan: Vec<Triangle> = (0..6)
+            .map(|i| Triangle::of(centre, rim[i], rim[(i + 1) % 6], 0))
+            .collect();
+
+        let mut rng = StdRng::seed_from_u64(3);
+        for &b in &rim {
+            let a = centre;
+            for k in 0..200 {
+                // Points on the shared edge, or the shared vertex itself.
+                let target = if k == 0 {
+                    centre
+                } else {
+                    a + (b - a) * rng.gen_range(0.0, 1.0)
+                };
+                let direction = Vec3::of(
+                    rng.gen_range(-0.5, 0.5),
+                    rng.gen_range(-0.5, 0.5),
+                    rng.gen_range(-1.0, -0.1),
+                );
+                let origin = target - direction * 3.0;
+                let hits = fan.iter().filter(|t| hit(t, origin, direction).is_some());
+                assert!(hits.count() >= 1, "ray through {:?} got through", target);
+            }
+        }
+    }
+
+    #[test]
+    fn degenerate_triangles_are_never_hit() {
+        let p = Point3::of(0.0, 0.0, 0.0);
+        let line = Triangle::of(p, Point3::of(1.0, 1.0, 0.0), Point3::of(2.0, 2.0, 0.0), 0);
+        let point = Triangle::of(p, p, p, 0);
+        for tri in &[line, point] {
+            for target in &[p, Point3::of(1.0, 1.0, 0.0), Point3::of(0.5, 0.5, 0.0)] {
+                let origin = *target + Vec3::of(0.1, -0.2, 1.0);
+                assert!(hit(tri, origin, *target - origin).is_none(), "{:?}", target);
+            }
+        }
+    }
+
+    #[test]
+    fn default_uvs_are_barycentric_weights() {
+        let p0 = Point3::of(1.0, 0.0, 0.0);
+        let (e1, e2) = (Vec3::of(2.0, 0.0, 0.0), Vec3::of(0.5, 3.0, 0.0));
+        let tri = Triangle::of(p0, p0 + e1, p0 + e2, 0);
+        let target = p0 + e1 * 0.2 + e2 * 0.3;
+        let rec = hit(
+            &tri,
+            target + Vec3::of(0.0, 0.0, 2.0),
+            Vec3::of(0.0, 0.0, -1.0),
+        )
+        .unwrap();
+        assert!((rec.u - 0.2).abs() < 1e-12 && (rec.v - 0.3).abs() < 1e-12);
+        assert!((rec.p - target).length() < 1e-12);
+        assert!((rec.t - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn uvs_are_interpolated_across_the_face() {
+        let (p0, p1, p2) = (
+            Point3::of(0.0, 0.0, 0.0),
+            Point3::of(1.0, 0.0, 0.0),
+            Point3::of(0.0, 1.0, 0.0),
+        );
+        let tri = Triangle::of(p0, p1, p2, 0).with_uv([(0.5, 0.5), (1.0, 0.0), (0.0, 0.25)]);
+        // Weights 0.5, 0.25 and 0.25.
+        let target = Point3::of(0.25, 0.25, 0.0);
+        let rec = hit(
+            &tri,
+            target + Vec3::of(0.0, 0.0, 1.0),
+            Vec3::of(0.0, 0.0, -1.0),
+        )
+        .unwrap();
+        assert!((rec.u - 0.5).abs() < 1e-12, "{}", rec.u);
+        assert!((rec.v - 0.3125).abs() < 1e-12, "{}", rec.v);
+    }
+
+    #[test]
+    fn shading_normals_stay_on_the_side_of_the_ray() {
+        let down = Vec3::of(0.0, 0.0, -1.0);
+        // Wound to face +z but with vertex normals pointing -z.
+        let tri = Triangle::of(
+            Point3::of(0.0, 0.0, 0.0),
+            Point3::of(1.0, 0.0, 0.0),
+            Point3::of(0.0, 1.0, 0.0),
+            0,
+        )
+        .with_normals(down, down, down);
+        let target = Point3::of(0.2, 0.2, 0.0);
+
+        let from_above = hit(&tri, target - down, down).unwrap();
+        assert!(from_above.front_face);
+        assert_eq!(from_above.normal, -down);
+
+        let from_below = hit(&tri, target + down, -down).unwrap();
+        assert!(!from_below.front_face);
+        assert_eq!(from_below.normal, down);
+    }
+}
@@ -14,7 +14,7 @@ pub fn random_float(min: f64, max: f64) -> f64 {
 
 // Vec3 implementation
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
 pub struct Vec3 {
     data: [f64; 3],
 }
@@ -130,7 +130,7 @@ impl Add for Vec3 {
     }
 }
 
-impl<'a, 'b> Add<&'b Vec3> for &'a Vec3 {
+impl<'b> Add<&'b Vec3> for &Vec3 {
     type Output = Vec3;
     fn add(self, other: &'b Vec3) -> Vec3 {
         Vec3 {
@@ -139,7 +139,7 @@ impl<'a, 'b> Add<&'b Vec3> for &'a Vec3 {
     }
 }
 
-impl<'a> Add<Vec3> for &'a Vec3 {
+impl Add<Vec3> for &Vec3 {
     type Output = Vec3;
     fn add(self, other: Vec3) -> Vec3 {
         Vec3 {
@@ -157,7 +157,7 @@ impl<'a> Add<&'a Vec3> for Vec3 {
     }
 }
 
-impl<'a, 'b> Sub<&'b Vec3> for &'a Vec3 {
+impl<'b> Sub<&'b Vec3> for &Vec3 {
     type Output = Vec3;
     fn sub(self, other: &'b Vec3) -> Vec3 {
         Vec3 {
@@ -184,7 +184,7 @@ impl<'a> Sub<&'a Vec3> for Vec3 {
     }
 }
 
-impl<'a> Sub<Vec3> for &'a Vec3 {
+impl Sub<Vec3> for &Vec3 {
     type Output = Vec3;
     fn sub(self, other: Vec3) -> Vec3 {
         Vec3 {
@@ -193,7 +193,7 @@ impl<'a> Sub<Vec3> for &'a Vec3 {
     }
 }
 
-impl<'a> Mul<f64> for &'a Vec3 {
+impl Mul<f64> for &Vec3 {
     type Output = Vec3;
 
     fn mul(self, t: f64) -> Vec3 {
@@ -223,7 +223,7 @@ impl Mul<Vec3> for Vec3 {
     }
 }
 
-impl<'a> Mul<Vec3> for &'a Vec3 {
+impl Mul<Vec3> for &Vec3 {
     type Output = Vec3;
 
     fn mul(self, other: Vec3) -> Vec3 {
@@ -243,7 +243,7 @@ impl<'a> Mul<&'a Vec3> for Vec3 {
     }
 }
 
-impl<'a, 'b> Mul<&'b Vec3> for &'a Vec3 {
+impl<'b> Mul<&'b Vec3> for &Vec3 {
     type Output = Vec3;
 
     fn mul(self, other: &'b Vec3) -> Vec3 {
@@ -253,7 +253,7 @@ impl<'a, 'b> Mul<&'b Vec3> for &'a Vec3 {
     }
 }
 
-impl<'a> Div<f64> for &'a Vec3 {
+impl Div<f64> for &Vec3 {
     type Output = Vec3;
     fn div(self, other: f64) -> Vec3 {
         self * (1.0 / other)
@@ -267,7 +267,7 @@ impl Div<f64> for Vec3 {
     }
 }
 
-impl<'a> Neg for &'a Vec3 {
+impl Neg for &Vec3 {
     type Output = Vec3;
     fn neg(self) -> Vec3 {
         Vec3 {
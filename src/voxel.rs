@@ -0,0 +1,490 @@
+// Voxel models. Filled cells are kept sparsely in a hash map, and a ray
+// walks the cells it passes through one by one (a 3D DDA after Amanatides
+// and Woo) until it reaches a filled one, so the cost follows the length of
+// the ray through the model rather than the number of voxels.
+//
+// MagicaVoxel .vox files load into a `VoxModel`, whose palette becomes one
+// `Lambertian` or `Metal` material per colour in use.
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::*;
+use crate::mesh::TriangleMesh;
+use crate::ray::Ray;
+use crate::scenes::Scene;
+use crate::vec3::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Cells lie within this many of the origin on each axis, which leaves room
+// for the `hi + 1` corners and face steps in i32 and keeps every corner
+// exact in f64.
+pub const MAX_CELL: i32 = 1 << 24;
+
+// Cubes of side `size` on a grid with a corner at `origin`; cell (i, j, k)
+// spans `origin + (i, j, k) * size` to one `size` further on each axis. Each
+// filled cell holds a slot in `materials`, which maps onto the scene's
+// `mat_ptr` indices. Faces report UVs across the face.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxelGrid {
+    pub origin: Point3,
+    pub size: f64,
+    pub materials: Vec<usize>,
+    voxels: HashMap<[i32; 3], u8>,
+    // Inclusive cell bounds of the filled voxels.
+    lo: [i32; 3],
+    hi: [i32; 3],
+}
+
+impl VoxelGrid {
+    pub fn new(origin: Point3, size: f64, materials: Vec<usize>) -> VoxelGrid {
+        assert!(size > 0.0, "voxel size must be positive");
+        VoxelGrid {
+            origin,
+            size,
+            materials,
+            voxels: HashMap::new(),
+            lo: [i32::MAX; 3],
+            hi: [i32::MIN; 3],
+        }
+    }
+
+    pub fn set(&mut self, cell: [i32; 3], slot: u8) {
+        assert!(
+            (slot as usize) < self.materials.len(),
+            "voxel material slot out of range"
+        );
+        assert!(
+            cell.iter().all(|c| c.unsigned_abs() <= MAX_CELL as u32),
+            "voxel cell out of range"
+        );
+        self.voxels.insert(cell, slot);
+        for (a, &c) in cell.iter().enumerate() {
+            self.lo[a] = self.lo[a].min(c);
+            self.hi[a] = self.hi[a].max(c);
+        }
+    }
+
+    pub fn with_voxel(mut self, cell: [i32; 3], slot: u8) -> VoxelGrid {
+        self.set(cell, slot);
+        self
+    }
+
+    pub fn get(&self, cell: [i32; 3]) -> Option<u8> {
+        self.voxels.get(&cell).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.voxels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty()
+    }
+
+    // Filled cells and their slots in a fixed order, for writing out.
+    pub fn voxels(&self) -> BTreeMap<[i32; 3], u8> {
+        self.voxels.iter().map(|(&c, &s)| (c, s)).collect()
+    }
+
+    // The lowest corner of a cell.
+    fn corner(&self, c: [i32; 3]) -> Point3 {
+        self.origin + Vec3::of(c[0] as f64, c[1] as f64, c[2] as f64) * self.size
+    }
+
+    // The faces between filled and empty cells as a flat-shaded mesh, for
+    // export.
+    pub fn to_mesh(&self) -> TriangleMesh {
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+        let mut ids = Vec::new();
+        for (cell, slot) in self.voxels() {
+            for a in 0..3 {
+                for &side in &[-1, 1] {
+                    let mut next = cell;
+                    next[a] += side;
+                    if self.voxels.contains_key(&next) {
+                        continue;
+                    }
+                    // cross(e[a + 1], e[a + 2]) = e[a], so going round in
+                    // that order faces +a.
+                    let (b, c) = ((a + 1) % 3, (a + 2) % 3);
+                    let mut base = cell;
+                    if side > 0 {
+                        base[a] += 1;
+                    }
+                    let mut quad = [base; 4];
+                    quad[1][b] += 1;
+                    quad[2][b] += 1;
+                    quad[2][c] += 1;
+                    quad[3][c] += 1;
+                    if side < 0 {
+                        quad.reverse();
+                    }
+                    let first = positions.len() as u32;
+                    positions.extend(quad.iter().map(|&q| self.corner(q)));
+                    indices.push([first, first + 1, first + 2]);
+                    indices.push([first, first + 2, first + 3]);
+                    ids.extend([slot as u32, slot as u32]);
+                }
+            }
+        }
+        let table = self.materials.clone();
+        TriangleMesh::new(positions, indices, table.first().cloned().unwrap_or(0))
+            .with_face_materials(ids, table)
+    }
+}
+
+impl Hittable for VoxelGrid {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if self.voxels.is_empty() {
+            return false;
+        }
+        // Clip to the filled cells' bounds, noting which face the ray
+        // comes in by. A ray that starts inside has none.
+        let (mut t0, mut t1) = (t_min, t_max);
+        let mut axis = None;
+        for a in 0..3 {
+            let lo = self.origin[a] + self.lo[a] as f64 * self.size;
+            let hi = self.origin[a] + (self.hi[a] + 1) as f64 * self.size;
+            let inv = 1.0 / r.direction[a];
+            let (mut ta, mut tb) = ((lo - r.origin[a]) * inv, (hi - r.origin[a]) * inv);
+            if inv < 0.0 {
+                std::mem::swap(&mut ta, &mut tb);
+            }
+            if ta > t0 {
+                t0 = ta;
+                axis = Some(a);
+            }
+            t1 = t1.min(tb);
+            if t1 < t0 {
+                return false;
+            }
+        }
+        if !t0.is_finite() {
+            return false;
+        }
+
+        let start = r.at(t0);
+        let mut cell = [0; 3];
+        let mut step = [0; 3];
+        let mut next = [f64::INFINITY; 3];
+        let mut delta = [f64::INFINITY; 3];
+        for a in 0..3 {
+            let x = ((start[a] - self.origin[a]) / self.size).floor();
+            cell[a] = (x as i32).clamp(self.lo[a], self.hi[a]);
+            let d = r.direction[a];
+            let edge = self.origin[a] + cell[a] as f64 * self.size;
+            if d > 0.0 {
+                step[a] = 1;
+                next[a] = (edge + self.size - r.origin[a]) / d;
+                delta[a] = self.size / d;
+            } else if d < 0.0 {
+                step[a] = -1;
+                next[a] = (edge - r.origin[a]) / d;
+                delta[a] = -self.size / d;
+            }
+        }
+
+        let mut t = t0;
+        loop {
+            if let (Some(slot), Some(a)) = (self.voxels.get(&cell), axis) {
+                let p = r.at(t);
+                let mut outward_normal = [0.0; 3];
+                outward_normal[a] = if r.direction[a] > 0.0 { -1.0 } else { 1.0 };
+                let local = |k: usize| {
+                    let x = (p[k] - self.origin[k]) / self.size - cell[k] as f64;
+                    x.clamp(0.0, 1.0)
+                };
+                *rec = HitRecord {
+                    p,
+                    normal: Vec3::of(outward_normal[0], outward_normal[1], outward_normal[2]),
+                    mat_ptr: self.materials[*slot as usize],
+                    t,
+                    u: local((a + 1) % 3),
+                    v: local((a + 2) % 3),
+                    tangent: Vec3::new(),
+                    front_face: true,
+                };
+                return true;
+            }
+            let a = if next[0] < next[1] {
+                if next[0] < next[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if next[1] < next[2] {
+                1
+            } else {
+                2
+            };
+            if next[a] > t1 {
+                return false;
+            }
+            cell[a] += step[a];
+            if cell[a] < self.lo[a] || cell[a] > self.hi[a] {
+                return false;
+            }
+            t = next[a];
+            next[a] += delta[a];
+            axis = Some(a);
+        }
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        if self.voxels.is_empty() {
+            return false;
+        }
+        let hi = [self.hi[0] + 1, self.hi[1] + 1, self.hi[2] + 1];
+        *output_box = Aabb::of(self.corner(self.lo), self.corner(hi));
+        true
+    }
+}
+
+// A model read from a MagicaVoxel file. Cells are in the file's z-up
+// coordinates; colour index i, from 1 to 255, picks `palette[i]`, and
+// `metal[i]` holds the roughness of entries given a metal material.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxModel {
+    pub size: [u32; 3],
+    pub voxels: Vec<([u8; 3], u8)>,
+    pub palette: Vec<Color>,
+    pub metal: Vec<Option<f64>>,
+}
+
+// Palette colours are stored gamma encoded; squaring undoes the gamma of 2
+// that the renderer writes out with.
+fn palette_color(rgba: [u8; 4]) -> Color {
+    let c = |x: u8| (x as f64 / 255.0).powi(2);
+    Color::of(c(rgba[0]), c(rgba[1]), c(rgba[2]))
+}
+
+// MagicaVoxel's built-in palette, used by files without an RGBA chunk: a
+// 6 x 6 x 6 colour cube less black, then ramps of red, green, blue and grey.
+fn default_palette() -> Vec<Color> {
+    const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let mut palette = vec![Color::new()];
+    for i in 0..215 {
+        palette.push(palette_color([
+            CUBE[i / 36],
+            CUBE[i / 6 % 6],
+            CUBE[i % 6],
+            255,
+        ]));
+    }
+    for channel in 0..4 {
+        for &x in &RAMP {
+            let mut rgba = [0, 0, 0, 255];
+            if channel == 3 {
+                rgba = [x, x, x, 255];
+            } else {
+                rgba[channel] = x;
+            }
+            palette.push(palette_color(rgba));
+        }
+    }
+    palette
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() - self.pos < n {
+            return Err(invalid("truncated .vox data"));
+        }
+        self.pos += n;
+        Ok(&self.data[self.pos - n..self.pos])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let n = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(n)?).into_owned())
+    }
+}
+
+// Reads the first model of a .vox file, with its palette and any metal
+// materials. Scene graph, layer and other chunks are skipped.
+pub fn read_vox(data: &[u8]) -> io::Result<VoxModel> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.bytes(4)? != b"VOX " {
+        return Err(invalid("not a .vox file"));
+    }
+    reader.u32()?;
+    if reader.bytes(4)? != b"MAIN" {
+        return Err(invalid("missing MAIN chunk"));
+    }
+    let content = reader.u32()? as usize;
+    reader.u32()?;
+    reader.bytes(content)?;
+
+    let mut model = VoxModel {
+        size: [0; 3],
+        voxels: Vec::new(),
+        palette: default_palette(),
+        metal: vec![None; 256],
+    };
+    let (mut have_size, mut have_voxels) = (false, false);
+    while reader.pos < data.len() {
+        let id = reader.bytes(4)?;
+        let content = reader.u32()? as usize;
+        let children = reader.u32()? as usize;
+        let mut chunk = Reader {
+            data: reader.bytes(content)?,
+            pos: 0,
+        };
+        reader.bytes(children)?;
+        match id {
+            b"SIZE" if !have_size => {
+                model.size = [chunk.u32()?, chunk.u32()?, chunk.u32()?];
+                have_size = true;
+            }
+            b"XYZI" if !have_voxels => {
+                let n = chunk.u32()? as usize;
+                for _ in 0..n {
+                    let v = chunk.bytes(4)?;
+                    if v[3] != 0 {
+                        model.voxels.push(([v[0], v[1], v[2]], v[3]));
+                    }
+                }
+                have_voxels = true;
+            }
+            b"RGBA" => {
+                // Entry i holds colour index i + 1; the last is unused.
+                for i in 1..256 {
+                    let c = chunk.bytes(4)?;
+                    model.palette[i] = palette_color([c[0], c[1], c[2], c[3]]);
+                }
+            }
+            b"MATL" => {
+                let index = chunk.u32()? as usize;
+                let pairs = chunk.u32()?;
+                let mut properties = HashMap::new();
+                for _ in 0..pairs {
+                    let key = chunk.string()?;
+                    properties.insert(key, chunk.string()?);
+                }
+                if index < 256 && properties.get("_type").map(String::as_str) == Some("_metal") {
+                    let rough = properties
+                        .get("_rough")
+                        .and_then(|r| r.parse().ok())
+                        .unwrap_or(0.1);
+                    model.metal[index] = Some(rough);
+                }
+            }
+            _ => {}
+        }
+    }
+    if !have_size || !have_voxels {
+        return Err(invalid(".vox file has no model"));
+    }
+    Ok(model)
+}
+
+pub fn load_vox(path: &Path) -> io::Result<VoxModel> {
+    read_vox(&fs::read(path)?)
+}
+
+impl VoxModel {
+    // A grid of the model standing on `origin`, turned from z-up to y-up,
+    // with a material added to `scene` for each colour it uses.
+    pub fn to_grid(&self, scene: &mut Scene, origin: Point3, size: f64) -> VoxelGrid {
+        let mut slots: BTreeMap<u8, u8> = BTreeMap::new();
+        for &(_, index) in &self.voxels {
+            let next = slots.len() as u8;
+            slots.entry(index).or_insert(next);
+        }
+        let mut materials = vec![0; slots.len()];
+        for (&index, &slot) in &slots {
+            let albedo = self.palette[index as usize];
+            let m = match self.metal[index as usize] {
+                Some(rough) => Mat::M(Metal::new(albedo, rough)),
+                None => Mat::L(Lambertian { albedo }),
+            };
+            materials[slot as usize] = scene.add_material(m);
+        }
+        let mut grid = VoxelGrid::new(origin, size, materials);
+        for &([x, y, z], index) in &self.voxels {
+            grid.set([x as i32, z as i32, -(y as i32) - 1], slots[&index]);
+        }
+        grid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenes::View;
+
+    fn chunk(id: &[u8], content: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.extend((content.len() as u32).to_le_bytes());
+        out.extend(0u32.to_le_bytes());
+        out.extend(content);
+        out
+    }
+
+    #[test]
+    fn reads_vox_and_walks_the_grid() {
+        let words = |w: &[u32]| w.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<u8>>();
+        let mut children = chunk(b"SIZE", &words(&[3, 1, 1]));
+        let mut xyzi = words(&[2]);
+        xyzi.extend([0, 0, 0, 1, 2, 0, 0, 9]);
+        children.extend(chunk(b"XYZI", &xyzi));
+        let mut rgba = vec![0u8; 1024];
+        rgba[..4].copy_from_slice(&[255, 0, 0, 255]);
+        children.extend(chunk(b"RGBA", &rgba));
+        let mut matl = words(&[9, 2]);
+        for s in ["_type", "_metal", "_rough", "0.25"] {
+            matl.extend(words(&[s.len() as u32]));
+            matl.extend(s.as_bytes());
+        }
+        children.extend(chunk(b"MATL", &matl));
+        let mut file = b"VOX ".to_vec();
+        file.extend(150u32.to_le_bytes());
+        file.extend(b"MAIN");
+        file.extend(0u32.to_le_bytes());
+        file.extend((children.len() as u32).to_le_bytes());
+        file.extend(children);
+
+        let model = read_vox(&file).unwrap();
+        assert_eq!(model.size, [3, 1, 1]);
+        assert_eq!(model.voxels, vec![([0, 0, 0], 1), ([2, 0, 0], 9)]);
+        assert_eq!(model.palette[1], Color::of(1.0, 0.0, 0.0));
+        assert_eq!(model.metal[9], Some(0.25));
+
+        let mut scene = Scene::new(View::default());
+        let grid = model.to_grid(&mut scene, Point3::new(), 1.0);
+        assert!(matches!(scene.materials[grid.materials[1]], Mat::M(_)));
+
+        // Along the row from -x, the ray crosses the gap and stops at the
+        // first cube; from +x, at the other.
+        let mut rec = HitRecord::default();
+        let r = Ray::of(Point3::of(-2.0, 0.5, -0.5), Vec3::of(1.0, 0.0, 0.0));
+        assert!(grid.hit(&r, 0.0, f64::INFINITY, &mut rec));
+        assert_eq!((rec.t, rec.normal), (2.0, Vec3::of(-1.0, 0.0, 0.0)));
+        let r = Ray::of(Point3::of(5.0, 0.5, -0.5), Vec3::of(-1.0, 0.0, 0.0));
+        assert!(grid.hit(&r, 0.0, f64::INFINITY, &mut rec));
+        assert_eq!(rec.t, 2.0);
+        assert_eq!(rec.mat_ptr, grid.materials[1]);
+        // Through the gap from above.
+        let r = Ray::of(Point3::of(1.5, 3.0, -0.5), Vec3::of(0.0, -1.0, 0.0));
+        assert!(!grid.hit(&r, 0.0, f64::INFINITY, &mut rec));
+    }
+}